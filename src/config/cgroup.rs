@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// cgroup v2 limits applied to the server process group, stored in `MineGuardConfig`.
+///
+/// Each server gets its own cgroup `mineguard-<instance uuid>` below `parent`. Limits
+/// left unset are reset to the kernel default, so none remain from an earlier run.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CgroupLimits {
+    /// Absolute path of a cgroup under `/sys/fs/cgroup` delegated to the user running
+    /// MineGuard. It must not hold any processes itself, MineGuard included, since
+    /// cgroup v2 only enables controllers for children of cgroups without processes.
+    pub parent: PathBuf,
+    /// `memory.max`; the server is OOM-killed when it uses more.
+    pub memory_max_bytes: Option<u64>,
+    /// `cpu.weight`, relative share of CPU time from 1 to 10000, 100 by default.
+    pub cpu_weight: Option<u32>,
+    /// `cpu.max` as a percentage of one core, 200 allowing two full cores.
+    pub cpu_quota_percent: Option<u32>,
+    /// `pids.max`, counting threads as well as processes.
+    pub pids_max: Option<u64>,
+    /// `io.weight`, relative share of IO from 1 to 10000, 100 by default.
+    pub io_weight: Option<u32>,
+}
+
+impl CgroupLimits {
+    /// No limits below `parent`, to be set field by field.
+    pub fn new<P: Into<PathBuf>>(parent: P) -> Self {
+        Self {
+            parent: parent.into(),
+            memory_max_bytes: None,
+            cpu_weight: None,
+            cpu_quota_percent: None,
+            pids_max: None,
+            io_weight: None,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Tick health thresholds, `tick query` polling and the watchdog, stored in
+/// `MineGuardConfig`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HealthPolicy {
+    pub enabled: bool,
+    /// Average milliseconds per tick at which the server is reported degraded; 50ms is
+    /// the whole budget of a tick at 20 TPS.
+    pub degraded_mspt: f64,
+    /// Average the server must fall below again to be reported recovered.
+    pub recovered_mspt: f64,
+    /// Measurements older than this are left out of the average.
+    pub window_secs: u64,
+    /// Polls `tick query`, available since 1.20.3, at this interval.
+    pub tick_query_interval_secs: Option<u64>,
+    /// Sends `list` at this interval while the watchdog is enabled.
+    pub watchdog_interval_secs: u64,
+    /// Restarts the server when it does not answer within this many seconds, within the
+    /// retry limit and backoff of the `RestartPolicy` whatever its mode.
+    pub watchdog_timeout_secs: Option<u64>,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            degraded_mspt: 50.0,
+            recovered_mspt: 40.0,
+            window_secs: 60,
+            tick_query_interval_secs: None,
+            watchdog_interval_secs: 30,
+            watchdog_timeout_secs: None,
+        }
+    }
+}
+
+impl HealthPolicy {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    pub fn tick_query_interval(&self) -> Option<Duration> {
+        self.tick_query_interval_secs
+            .map(|secs| Duration::from_secs(secs.max(1)))
+    }
+
+    pub fn watchdog_interval(&self) -> Duration {
+        Duration::from_secs(self.watchdog_interval_secs.max(1))
+    }
+
+    pub fn watchdog_timeout(&self) -> Option<Duration> {
+        self.watchdog_timeout_secs
+            .map(|secs| Duration::from_secs(secs.max(1)))
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::LaunchError;
+
+/// Named sets of JVM flags that can be applied to a `LaunchProfile`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LaunchPreset {
+    /// Aikar's G1GC tuning, see <https://docs.papermc.io/paper/aikars-flags>.
+    Aikar,
+    /// Small footprint settings for servers with only a few players.
+    LowMemory,
+}
+
+/// How the server JVM is launched, stored in `MineGuardConfig`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LaunchProfile {
+    pub java: PathBuf,
+    pub preset: Option<LaunchPreset>,
+    pub min_heap_mb: Option<u32>,
+    pub max_heap_mb: Option<u32>,
+    /// JVM flags appended after the preset flags.
+    pub jvm_flags: Vec<String>,
+    /// Arguments passed to the server after the jar.
+    pub server_args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    /// Runs the server in its own session with stdio under `.mineguard/run/`, so it
+    /// survives the supervisor and can be reattached by `MineGuardServer::load`.
+    pub detached: bool,
+}
+
+const AIKAR_FLAGS: &[&str] = &[
+    "-XX:+UseG1GC",
+    "-XX:+ParallelRefProcEnabled",
+    "-XX:MaxGCPauseMillis=200",
+    "-XX:+UnlockExperimentalVMOptions",
+    "-XX:+DisableExplicitGC",
+    "-XX:+AlwaysPreTouch",
+    "-XX:G1NewSizePercent=30",
+    "-XX:G1MaxNewSizePercent=40",
+    "-XX:G1HeapRegionSize=8M",
+    "-XX:G1ReservePercent=20",
+    "-XX:G1HeapWastePercent=5",
+    "-XX:G1MixedGCCountTarget=4",
+    "-XX:InitiatingHeapOccupancyPercent=15",
+    "-XX:G1MixedGCLiveThresholdPercent=90",
+    "-XX:G1RSetUpdatingPauseTimePercent=5",
+    "-XX:SurvivorRatio=32",
+    "-XX:+PerfDisableSharedMem",
+    "-XX:MaxTenuringThreshold=1",
+    "-Dusing.aikars.flags=https://mcflags.emc.gs",
+    "-Daikars.new.flags=true",
+];
+
+/// Aikar recommends different young generation sizes above 12GB of heap.
+const AIKAR_LARGE_HEAP_FLAGS: &[&str] = &[
+    "-XX:G1NewSizePercent=40",
+    "-XX:G1MaxNewSizePercent=50",
+    "-XX:G1HeapRegionSize=16M",
+    "-XX:G1ReservePercent=15",
+    "-XX:InitiatingHeapOccupancyPercent=20",
+];
+
+const LOW_MEMORY_FLAGS: &[&str] = &[
+    "-XX:+UseSerialGC",
+    "-XX:MaxMetaspaceSize=256M",
+    "-XX:ReservedCodeCacheSize=64M",
+    "-Xss512K",
+];
+
+impl LaunchPreset {
+    /// JVM flags of the preset, tuned for the given maximum heap where relevant.
+    pub fn jvm_flags(&self, max_heap_mb: Option<u32>) -> Vec<String> {
+        match self {
+            LaunchPreset::Aikar => {
+                let large_heap = max_heap_mb.is_some_and(|mb| mb >= 12 * 1024);
+                AIKAR_FLAGS
+                    .iter()
+                    .map(|flag| {
+                        AIKAR_LARGE_HEAP_FLAGS
+                            .iter()
+                            .find(|large| large_heap && flag_key(large) == flag_key(flag))
+                            .unwrap_or(flag)
+                            .to_string()
+                    })
+                    .collect()
+            }
+            LaunchPreset::LowMemory => LOW_MEMORY_FLAGS.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+}
+
+fn flag_key(flag: &str) -> &str {
+    flag.split_once('=').map_or(flag, |(key, _)| key)
+}
+
+impl Display for LaunchPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LaunchPreset::Aikar => write!(f, "aikar"),
+            LaunchPreset::LowMemory => write!(f, "low-memory"),
+        }
+    }
+}
+
+impl FromStr for LaunchPreset {
+    type Err = LaunchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aikar" => Ok(LaunchPreset::Aikar),
+            "low-memory" => Ok(LaunchPreset::LowMemory),
+            _ => Err(LaunchError::UnknownPreset(s.to_string())),
+        }
+    }
+}
+
+impl Default for LaunchProfile {
+    fn default() -> Self {
+        Self {
+            java: PathBuf::from("java"),
+            preset: None,
+            min_heap_mb: None,
+            max_heap_mb: None,
+            jvm_flags: Vec::new(),
+            server_args: vec!["nogui".to_string()],
+            env: BTreeMap::new(),
+            detached: false,
+        }
+    }
+}
+
+impl LaunchProfile {
+    pub fn from_preset(preset: LaunchPreset) -> Self {
+        let mut profile = Self {
+            preset: Some(preset),
+            ..Default::default()
+        };
+
+        if preset == LaunchPreset::LowMemory {
+            profile.min_heap_mb = Some(512);
+            profile.max_heap_mb = Some(1024);
+        }
+
+        profile
+    }
+
+    pub fn with_heap(mut self, min_heap_mb: Option<u32>, max_heap_mb: Option<u32>) -> Self {
+        self.min_heap_mb = min_heap_mb;
+        self.max_heap_mb = max_heap_mb;
+        self
+    }
+
+    /// Full argument list passed to the java executable, ending with the server arguments.
+    pub fn args(&self, jar_path: &Path) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if let Some(mb) = self.min_heap_mb {
+            args.push(format!("-Xms{}M", mb));
+        }
+        if let Some(mb) = self.max_heap_mb {
+            args.push(format!("-Xmx{}M", mb));
+        }
+        if let Some(preset) = self.preset {
+            args.extend(preset.jvm_flags(self.max_heap_mb));
+        }
+        args.extend(self.jvm_flags.iter().cloned());
+
+        args.push("-jar".to_string());
+        args.push(jar_path.to_string_lossy().to_string());
+        args.extend(self.server_args.iter().cloned());
+
+        args
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// Rotation, compression and retention of the console log written to
+/// `.mineguard/logs/`, stored in `MineGuardConfig`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogSinkPolicy {
+    pub enabled: bool,
+    /// Size after which the active segment is closed and a new one started.
+    pub max_segment_bytes: u64,
+    /// Starts a new segment when the UTC date changes.
+    pub rotate_daily: bool,
+    /// Gzips closed segments.
+    pub compress: bool,
+    /// Segments from before this many days ago are deleted.
+    pub retention_days: Option<u32>,
+    /// Oldest segments are deleted while all segments together are larger than this.
+    pub max_total_bytes: Option<u64>,
+}
+
+impl Default for LogSinkPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_segment_bytes: 16 * 1024 * 1024,
+            rotate_daily: true,
+            compress: true,
+            retention_days: Some(14),
+            max_total_bytes: None,
+        }
+    }
+}
+
+/// Size of the in-memory backlog kept for each output stream, replayed by
+/// `subscribe_with_backlog`. Events are dropped oldest first once either limit is hit.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BacklogPolicy {
+    pub max_lines: usize,
+    /// Approximate memory used by the kept events.
+    pub max_bytes: usize,
+}
+
+impl Default for BacklogPolicy {
+    fn default() -> Self {
+        Self {
+            max_lines: 1_000,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Shortest interval between resource samples, whatever the policy says.
+const MIN_INTERVAL_MS: u64 = 100;
+
+/// Sampling of the CPU, memory, threads, file descriptors and IO of the server
+/// process group, stored in `MineGuardConfig`. Off by default, since every sample is
+/// also an event that takes up room in the backlog.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MetricsPolicy {
+    pub enabled: bool,
+    pub interval_ms: u64,
+}
+
+impl Default for MetricsPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_ms: 5_000,
+        }
+    }
+}
+
+impl MetricsPolicy {
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.max(MIN_INTERVAL_MS))
+    }
+}
@@ -1,5 +1,21 @@
+#[cfg(feature = "cgroups")]
+pub mod cgroup;
+pub mod health;
+pub mod launch;
+pub mod logging;
+pub mod metrics;
+pub mod restart;
+pub mod rules;
 pub mod stream;
 pub mod version;
 
+#[cfg(feature = "cgroups")]
+pub use cgroup::CgroupLimits;
+pub use health::HealthPolicy;
+pub use launch::{LaunchPreset, LaunchProfile};
+pub use logging::{BacklogPolicy, LogSinkPolicy};
+pub use metrics::MetricsPolicy;
+pub use restart::{RestartMode, RestartPolicy};
+pub use rules::EventRule;
 pub use stream::{LogMeta, StreamLine, StreamSource};
 pub use version::{MinecraftType, MinecraftVersion, Snapshot, Version};
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::instance::InstanceStatus;
+
+/// Decides which process exits trigger an automatic restart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RestartMode {
+    Never,
+    OnCrash,
+    Always,
+}
+
+/// Automatic restart policy applied by the instance handle, stored in `MineGuardConfig`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RestartPolicy {
+    pub mode: RestartMode,
+    /// Maximum number of restart attempts allowed inside `window_secs`. Once they are
+    /// used up, the server is left down until it is next started and reaches running.
+    pub max_retries: u32,
+    pub window_secs: u64,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub multiplier: f64,
+    /// Fraction of the computed delay that is randomly added or removed, clamped to `0.0..=1.0`.
+    pub jitter: f64,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            mode: RestartMode::Never,
+            max_retries: 5,
+            window_secs: 600,
+            initial_backoff_ms: 1_000,
+            max_backoff_ms: 60_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RestartPolicy {
+    pub fn new(mode: RestartMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    /// Returns true when a transition from `old` to `new` should schedule a restart.
+    ///
+    /// Exits that follow a requested stop, terminate or kill never trigger a restart.
+    pub fn should_restart(&self, old: &InstanceStatus, new: &InstanceStatus) -> bool {
+        match self.mode {
+            RestartMode::Never => false,
+            RestartMode::OnCrash => *new == InstanceStatus::Crashed,
+            RestartMode::Always => {
+                *new == InstanceStatus::Crashed
+                    || (*new == InstanceStatus::Stopped
+                        && !matches!(old, InstanceStatus::Stopping | InstanceStatus::Terminating))
+            }
+        }
+    }
+
+    /// Delay before the given 1-based restart attempt, including jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial_backoff_ms as f64 * self.multiplier.max(1.0).powi(exponent);
+        let capped = base.min(self.max_backoff_ms as f64);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (fastrand::f64() * 2.0 - 1.0);
+
+        Duration::from_millis((capped * factor).max(0.0) as u64)
+    }
+}
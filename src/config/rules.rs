@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::stream::LogLevel;
+
+/// User-defined log rule stored in `MineGuardConfig`, emitted as a `Custom` event.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EventRule {
+    pub name: String,
+    /// Regex matched against the logging thread, e.g. `Server thread`.
+    #[serde(default)]
+    pub thread: Option<String>,
+    #[serde(default)]
+    pub level: Option<LogLevel>,
+    /// Regex matched against the log message; its named captures are available to `fields`.
+    pub pattern: String,
+    /// Event fields as templates referencing captures with `$name` or `${name}`.
+    /// When empty, every named capture becomes a field.
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+}
+
+impl EventRule {
+    pub fn new<N: Into<String>, P: Into<String>>(name: N, pattern: P) -> Self {
+        Self {
+            name: name.into(),
+            thread: None,
+            level: None,
+            pattern: pattern.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_thread<S: Into<String>>(mut self, thread: S) -> Self {
+        self.thread = Some(thread.into());
+        self
+    }
+
+    pub fn with_level(mut self, level: LogLevel) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    pub fn with_field<K: Into<String>, V: Into<String>>(mut self, key: K, template: V) -> Self {
+        self.fields.insert(key.into(), template.into());
+        self
+    }
+}
+
+#[cfg(all(test, feature = "mc-vanilla"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optional_parts_default_when_missing() {
+        let rule: EventRule =
+            serde_json::from_str(r#"{"name": "saved", "pattern": "^Saved the game$"}"#).unwrap();
+        assert_eq!(rule, EventRule::new("saved", "^Saved the game$"));
+    }
+
+    #[test]
+    fn reads_every_part() {
+        let rule: EventRule = serde_json::from_str(
+            r#"{
+                "name": "lag",
+                "thread": "^Server thread$",
+                "level": "WARN",
+                "pattern": "Running (?P<ms>\\d+)ms",
+                "fields": {"behind": "${ms}"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rule,
+            EventRule::new("lag", r"Running (?P<ms>\d+)ms")
+                .with_thread("^Server thread$")
+                .with_level(LogLevel::Warn)
+                .with_field("behind", "${ms}")
+        );
+    }
+}
@@ -1,12 +1,18 @@
-use std::fmt::{self, Display};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    time::Duration,
+};
 
-use uuid::{Uuid, timestamp};
+use uuid::Uuid;
 
-use crate::instance::InstanceStatus;
+use crate::instance::{ExitInfo, InstanceStatus, ResourceSample, ServerHealth};
 
-use super::line::StreamLine;
+use super::line::{StreamLine, StreamSource};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "wire", serde(tag = "type", rename_all = "snake_case"))]
 pub enum EventPayload {
     #[cfg(feature = "events")]
     StateChange {
@@ -17,44 +23,221 @@ pub enum EventPayload {
     StdLine {
         line: StreamLine,
     },
+
+    #[cfg(feature = "events")]
+    Exited {
+        info: ExitInfo,
+    },
+
+    #[cfg(feature = "events")]
+    RestartAttempt {
+        attempt: u32,
+        max_retries: u32,
+        #[cfg_attr(
+            feature = "wire",
+            serde(with = "crate::config::stream::wire::duration_ms")
+        )]
+        delay: Duration,
+    },
+
+    #[cfg(feature = "events")]
+    RestartGaveUp {
+        attempts: u32,
+    },
+
+    #[cfg(feature = "events")]
+    PlayerJoined {
+        name: String,
+        uuid: Option<Uuid>,
+        ip: Option<String>,
+    },
+
+    #[cfg(feature = "events")]
+    PlayerLeft {
+        name: String,
+        reason: Option<String>,
+    },
+
+    #[cfg(feature = "events")]
+    Chat {
+        sender: String,
+        message: String,
+    },
+
+    /// Message broadcast with `/say`, from a player, `Server` or `Rcon`.
+    #[cfg(feature = "events")]
+    Say {
+        source: String,
+        message: String,
+    },
+
+    /// Action broadcast with `/me`.
+    #[cfg(feature = "events")]
+    Emote {
+        name: String,
+        action: String,
+    },
+
+    #[cfg(feature = "events")]
+    PlayerDied {
+        name: String,
+        message: String,
+    },
+
+    #[cfg(feature = "events")]
+    Advancement {
+        name: String,
+        kind: AdvancementKind,
+        title: String,
+    },
+
+    /// The server reported falling behind with `Can't keep up!`.
+    #[cfg(feature = "events")]
+    LagWarning {
+        #[cfg_attr(
+            feature = "wire",
+            serde(with = "crate::config::stream::wire::duration_ms")
+        )]
+        behind: Duration,
+        ticks: u64,
+    },
+
+    #[cfg(feature = "events")]
+    WorldSaved,
+
+    #[cfg(feature = "events")]
+    Stopping {
+        stage: StopStage,
+    },
+
+    /// Match of a user-defined `EventRule`.
+    #[cfg(feature = "events")]
+    Custom {
+        name: String,
+        fields: BTreeMap<String, String>,
+    },
+
+    /// An internal consumer of `stream` fell behind and skipped `missed` events.
+    #[cfg(feature = "events")]
+    StreamLagged {
+        stream: StreamSource,
+        missed: u64,
+    },
+
+    /// Periodic resource usage of the server process group.
+    #[cfg(feature = "events")]
+    ResourceSample {
+        sample: ResourceSample,
+    },
+
+    /// The average tick time rose above the degraded threshold of the `HealthPolicy`.
+    #[cfg(feature = "events")]
+    HealthDegraded {
+        health: ServerHealth,
+    },
+
+    /// The average tick time fell below the recovered threshold again.
+    #[cfg(feature = "events")]
+    HealthRecovered {
+        health: ServerHealth,
+    },
+
+    /// The server did not answer the watchdog probe within `unresponsive` and is being
+    /// restarted.
+    #[cfg(feature = "events")]
+    WatchdogRestart {
+        #[cfg_attr(
+            feature = "wire",
+            serde(with = "crate::config::stream::wire::duration_ms")
+        )]
+        unresponsive: Duration,
+    },
+
+    /// A detached server left running by an earlier supervisor could not be taken
+    /// over when the instance was loaded, and keeps running unsupervised.
+    #[cfg(feature = "events")]
+    ReattachFailed {
+        error: String,
+    },
+
+    /// Writing or maintaining the console log failed. Reported again only after a
+    /// write succeeded in between.
+    #[cfg(feature = "events")]
+    LogSinkFailed {
+        error: String,
+    },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// How an advancement was announced; achievements are the pre-1.12 equivalent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "wire", serde(rename_all = "snake_case"))]
+pub enum AdvancementKind {
+    Advancement,
+    Goal,
+    Challenge,
+    Achievement,
+}
+
+/// Steps of the shutdown sequence as logged by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "wire", serde(rename_all = "snake_case"))]
+pub enum StopStage {
+    /// `Stopping the server`, logged by the `stop` command.
+    Requested,
+    Stopping,
+    SavingPlayers,
+    SavingWorlds,
+    /// Every dimension has been written to disk.
+    Saved,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
 pub struct InstanceEvent {
     pub id: Uuid,
 
+    /// Position among the events of all streams of the instance, counting up from 1;
+    /// 0 until the event is published.
+    pub seq: u64,
+
     pub timestamp: chrono::DateTime<chrono::Utc>,
 
     pub payload: EventPayload,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "wire", serde(rename_all = "snake_case"))]
 pub enum InternalEvent {
     ServerStarted,
 }
 
 impl InstanceEvent {
-    pub fn stdout<S: Into<String>>(line: S) -> Self {
-        let line = line.into();
-        let s_line = StreamLine::stdout(line);
-        let timestamp = s_line.extract_timestamp().unwrap_or(chrono::Utc::now());
-        let payload = EventPayload::StdLine { line: s_line };
+    /// A console line logged at `timestamp`, which the runtime resolves from the line's
+    /// prefix with the instance's `TimestampResolver`.
+    pub fn stdout<S: Into<String>>(line: S, timestamp: chrono::DateTime<chrono::Utc>) -> Self {
+        let payload = EventPayload::StdLine {
+            line: StreamLine::stdout(line),
+        };
 
         Self {
             id: Uuid::new_v4(),
+            seq: 0,
             timestamp,
             payload,
         }
     }
 
-    pub fn stderr<S: Into<String>>(line: S) -> Self {
-        let line = line.into();
-        let s_line = StreamLine::stderr(line);
-        let timestamp = s_line.extract_timestamp().unwrap_or(chrono::Utc::now());
-        let payload = EventPayload::StdLine { line: s_line };
+    pub fn stderr<S: Into<String>>(line: S, timestamp: chrono::DateTime<chrono::Utc>) -> Self {
+        let payload = EventPayload::StdLine {
+            line: StreamLine::stderr(line),
+        };
 
         Self {
             id: Uuid::new_v4(),
+            seq: 0,
             timestamp,
             payload,
         }
@@ -65,6 +248,7 @@ impl InstanceEvent {
 
         Self {
             id: Uuid::new_v4(),
+            seq: 0,
             timestamp,
             payload,
         }
@@ -88,6 +272,166 @@ impl Display for InstanceEvent {
                 let full = format!("{}State changed: {:?} -> {:?}", head, old, new);
                 writeln!(f, "{}", full)
             }
+
+            #[cfg(feature = "events")]
+            EventPayload::Exited { info } if info.oom_killed => {
+                let full = format!("{}Process OOM-killed after {:?}", head, info.uptime);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::Exited { info } => {
+                let full = format!(
+                    "{}Process exited (code: {:?}, signal: {:?}) after {:?}",
+                    head, info.code, info.signal, info.uptime
+                );
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::RestartAttempt {
+                attempt,
+                max_retries,
+                delay,
+            } => {
+                let full = format!(
+                    "{}Restart attempt {}/{} in {:?}",
+                    head, attempt, max_retries, delay
+                );
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::RestartGaveUp { attempts } => {
+                let full = format!("{}Restart gave up after {} attempts", head, attempts);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::PlayerJoined { name, uuid, ip } => {
+                let full = format!(
+                    "{}Player joined: {} (uuid: {:?}, ip: {:?})",
+                    head, name, uuid, ip
+                );
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::PlayerLeft { name, reason } => {
+                let full = format!("{}Player left: {} (reason: {:?})", head, name, reason);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::Chat { sender, message } => {
+                let full = format!("{}Chat: <{}> {}", head, sender, message);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::Say { source, message } => {
+                let full = format!("{}Say: [{}] {}", head, source, message);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::Emote { name, action } => {
+                let full = format!("{}Emote: * {} {}", head, name, action);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::PlayerDied { name, message } => {
+                let full = format!("{}Player died: {} ({})", head, name, message);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::Advancement { name, kind, title } => {
+                let full = format!("{}{:?} by {}: [{}]", head, kind, name, title);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::LagWarning { behind, ticks } => {
+                let full = format!("{}Server behind by {:?} ({} ticks)", head, behind, ticks);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::WorldSaved => {
+                let full = format!("{}World saved", head);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::Stopping { stage } => {
+                let full = format!("{}Server stopping: {:?}", head, stage);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::Custom { name, fields } => {
+                let full = format!("{}Custom event {}: {:?}", head, name, fields);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::StreamLagged { stream, missed } => {
+                let full = format!(
+                    "{}{:?} consumer lagged, missed {} events",
+                    head, stream, missed
+                );
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::ResourceSample { sample } => {
+                let full = format!(
+                    "{}Resources: {:?}% CPU, {} bytes RSS, {} threads, {} fds",
+                    head, sample.cpu_percent, sample.rss_bytes, sample.threads, sample.open_fds
+                );
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::HealthDegraded { health } => {
+                let full = format!(
+                    "{}Health degraded: {:?} MSPT, {:?} TPS",
+                    head, health.mspt, health.tps
+                );
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::HealthRecovered { health } => {
+                let full = format!(
+                    "{}Health recovered: {:?} MSPT, {:?} TPS",
+                    head, health.mspt, health.tps
+                );
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::WatchdogRestart { unresponsive } => {
+                let full = format!(
+                    "{}Watchdog restarting server, unresponsive for {:?}",
+                    head, unresponsive
+                );
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::ReattachFailed { error } => {
+                let full = format!("{}Failed to reattach to the server: {}", head, error);
+                writeln!(f, "{}", full)
+            }
+
+            #[cfg(feature = "events")]
+            EventPayload::LogSinkFailed { error } => {
+                let full = format!("{}Failed to write the console log: {}", head, error);
+                writeln!(f, "{}", full)
+            }
         }
     }
 }
@@ -1,11 +1,22 @@
-use std::fmt::{self, Display};
+use std::{
+    fmt::{self, Display},
+    sync::LazyLock,
+};
 
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "events")]
-use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg(feature = "events")]
+use super::TimestampResolver;
+
+static LOG_PREFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^\[[^\]]*\]\s*\[[^\]]*\]:\s*"#).unwrap());
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum StreamSource {
     Stdout,
     Stderr,
@@ -14,6 +25,7 @@ pub enum StreamSource {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
 pub struct StreamLine {
     pub line: String,
     pub source: StreamSource,
@@ -22,8 +34,7 @@ pub struct StreamLine {
 impl StreamLine {
     pub fn new<S: Into<String>>(line: S, source: StreamSource) -> Self {
         let line = line.into();
-        let re = Regex::new(r#"^\[[^\]]*\]\s*\[[^\]]*\]:\s*"#).unwrap();
-        let line = re.replace(&line, "").to_string();
+        let line = LOG_PREFIX.replace(&line, "").to_string();
         Self { line, source }
     }
 
@@ -47,21 +58,10 @@ impl StreamLine {
         self.line.clone()
     }
 
+    /// Timestamp at the start of the line, placed on the calendar relative to now.
+    /// Lines built with `new` have their prefix stripped and never have one.
     pub fn extract_timestamp(&self) -> Option<DateTime<Utc>> {
-        let input = self.line.as_str();
-        let re = Regex::new(r"\[(.*?)\]").unwrap();
-        let time_s = re.captures(input).map(|v| v[1].to_string());
-        time_s.as_ref()?;
-        let time = NaiveTime::parse_from_str(&time_s.unwrap(), "%H:%M:%S").ok()?;
-
-        let today = Local::now().date_naive();
-        let naive_dt = today.and_time(time);
-
-        let local_dt = Local.from_local_datetime(&naive_dt).unwrap();
-
-        let utc_dt = local_dt.with_timezone(&Utc);
-
-        Some(utc_dt)
+        TimestampResolver::new().resolve(&self.line, Utc::now())
     }
 }
 
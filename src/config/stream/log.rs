@@ -1,5 +1,7 @@
 use std::fmt::{self, Display};
 
+use serde::{Deserialize, Serialize};
+
 use crate::error::ParserError;
 
 #[cfg(feature = "mc-vanilla")]
@@ -11,7 +13,8 @@ pub struct LogMeta {
 }
 
 #[cfg(feature = "mc-vanilla")]
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
 pub enum LogLevel {
     Info,
     Warn,
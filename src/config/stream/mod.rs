@@ -2,9 +2,17 @@ mod event;
 mod line;
 #[cfg(feature = "mc-vanilla")]
 mod log;
+#[cfg(feature = "events")]
+mod timestamp;
+#[cfg(feature = "wire")]
+pub(crate) mod wire;
 
 pub use event::InternalEvent;
-pub use event::{EventPayload, InstanceEvent};
+pub use event::{AdvancementKind, EventPayload, InstanceEvent, StopStage};
 pub use line::{StreamLine, StreamSource};
 #[cfg(feature = "mc-vanilla")]
 pub use log::{LogLevel, LogMeta};
+#[cfg(feature = "events")]
+pub use timestamp::TimestampResolver;
+#[cfg(feature = "wire")]
+pub use wire::WIRE_VERSION;
@@ -0,0 +1,342 @@
+use std::sync::LazyLock;
+
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime,
+    Offset, TimeZone, Utc,
+};
+use regex::Regex;
+
+static BRACKETED_PREFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*\[([^\]]+)\]").unwrap());
+static ISO_PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*(\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?)")
+        .unwrap()
+});
+
+const TIME_FORMATS: &[&str] = &["%H:%M:%S%.f"];
+const OFFSET_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%.f%#z", "%Y-%m-%d %H:%M:%S%.f%#z"];
+const LOCAL_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    // Forge: `[14Mar2021 12:00:00.123]`, some packs add spaces around the month.
+    "%d%b%Y %H:%M:%S%.f",
+    "%d %b %Y %H:%M:%S%.f",
+];
+
+/// Longest clock change looked past when a local time falls into a DST gap.
+const MAX_GAP_HOURS: i64 = 3;
+
+/// Timestamp written at the start of a log line, before it is placed on the calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineTime {
+    Absolute(DateTime<FixedOffset>),
+    Local(NaiveDateTime),
+    TimeOnly(NaiveTime),
+}
+
+impl LineTime {
+    fn parse(line: &str) -> Option<Self> {
+        let raw = BRACKETED_PREFIX
+            .captures(line)
+            .or_else(|| ISO_PREFIX.captures(line))?
+            .get(1)?
+            .as_str()
+            .trim()
+            .replace(',', ".");
+
+        if let Some(time) = TIME_FORMATS
+            .iter()
+            .find_map(|f| NaiveTime::parse_from_str(&raw, f).ok())
+        {
+            return Some(Self::TimeOnly(time));
+        }
+        if let Some(dt) = OFFSET_FORMATS
+            .iter()
+            .find_map(|f| DateTime::parse_from_str(&raw.replace('Z', "+00:00"), f).ok())
+        {
+            return Some(Self::Absolute(dt));
+        }
+        LOCAL_FORMATS
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(&raw, f).ok())
+            .map(Self::Local)
+    }
+}
+
+/// Turns the timestamps log4j writes at the start of console lines into UTC instants.
+///
+/// Understands vanilla `[HH:mm:ss]`, optional milliseconds, ISO dates with or without
+/// an offset and Forge's `[ddMMMyyyy HH:mm:ss.SSS]`. Lines that carry only a time of day
+/// are placed on the date closest to when they were received, so lines logged just
+/// before midnight keep their day. Local times are read in the server's time zone;
+/// times repeated by a DST change resolve so that consecutive lines never go backwards,
+/// and times skipped by one are read with the offset in effect before the change.
+#[derive(Debug, Clone)]
+pub struct TimestampResolver<Tz: TimeZone = Local> {
+    tz: Tz,
+    last: Option<DateTime<Utc>>,
+}
+
+impl Default for TimestampResolver<Local> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimestampResolver<Local> {
+    pub fn new() -> Self {
+        Self::with_timezone(Local)
+    }
+}
+
+impl<Tz: TimeZone> TimestampResolver<Tz> {
+    /// Resolver for a server whose logs are written in `tz`.
+    pub fn with_timezone(tz: Tz) -> Self {
+        Self { tz, last: None }
+    }
+
+    /// Timestamp of the unstripped `line`, received at `received`, or `None` when the
+    /// line does not start with a known timestamp.
+    pub fn resolve(&mut self, line: &str, received: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let resolved = match LineTime::parse(line)? {
+            LineTime::Absolute(dt) => dt.with_timezone(&Utc),
+            LineTime::Local(naive) => self.local_to_utc(naive)?,
+            LineTime::TimeOnly(time) => {
+                let anchor = received.with_timezone(&self.tz).naive_local();
+                let naive = closest_date(anchor, time);
+                self.local_to_utc(naive)?
+            }
+        };
+
+        self.last = Some(resolved);
+        Some(resolved)
+    }
+
+    fn local_to_utc(&self, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self.tz.from_local_datetime(&naive) {
+            LocalResult::Single(dt) => Some(dt.with_timezone(&Utc)),
+            LocalResult::Ambiguous(a, b) => {
+                // Not every `TimeZone` returns the two candidates in instant order.
+                let (a, b) = (a.with_timezone(&Utc), b.with_timezone(&Utc));
+                let (earliest, latest) = (a.min(b), a.max(b));
+                match self.last {
+                    Some(last) if earliest < last => Some(latest),
+                    _ => Some(earliest),
+                }
+            }
+            LocalResult::None => (1..=MAX_GAP_HOURS).find_map(|hours| {
+                let offset = self
+                    .tz
+                    .offset_from_local_datetime(&(naive - Duration::hours(hours)))
+                    .earliest()?
+                    .fix();
+                Some(Utc.from_utc_datetime(&(naive - offset)))
+            }),
+        }
+    }
+}
+
+/// `time` on the day before, of or after `anchor`, whichever is nearest to it.
+fn closest_date(anchor: NaiveDateTime, time: NaiveTime) -> NaiveDateTime {
+    let date = anchor.date();
+    [date.pred_opt(), Some(date), date.succ_opt()]
+        .into_iter()
+        .flatten()
+        .map(|d: NaiveDate| d.and_time(time))
+        .min_by_key(|candidate| (*candidate - anchor).abs())
+        .unwrap_or_else(|| date.and_time(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::{Europe::Berlin, Tz};
+
+    use super::*;
+
+    fn utc(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().to_utc()
+    }
+
+    fn berlin() -> TimestampResolver<Tz> {
+        TimestampResolver::with_timezone(Berlin)
+    }
+
+    /// Resolves `line`, received at the UTC instant `received`, in Berlin.
+    fn resolve(resolver: &mut TimestampResolver<Tz>, line: &str, received: &str) -> String {
+        resolver
+            .resolve(line, utc(received))
+            .expect("no timestamp")
+            .to_rfc3339()
+    }
+
+    #[test]
+    fn detects_log4j_formats() {
+        let received = utc("2026-01-10T11:00:30Z");
+        let cases = [
+            (
+                "[12:00:00] [Server thread/INFO]: Done",
+                "2026-01-10T11:00:00+00:00",
+            ),
+            (
+                "[12:00:00.250] [Server thread/INFO]: Done",
+                "2026-01-10T11:00:00.250+00:00",
+            ),
+            (
+                "2026-01-10T12:00:00Z [main/INFO]: Loading",
+                "2026-01-10T12:00:00+00:00",
+            ),
+            (
+                "2026-01-10 12:00:00+0300 [main/INFO]: Loading",
+                "2026-01-10T09:00:00+00:00",
+            ),
+            (
+                "2026-01-10 12:00:00,125 [main/INFO]: Loading",
+                "2026-01-10T11:00:00.125+00:00",
+            ),
+            (
+                "[10Jan2026 12:00:00.500] [main/INFO]: Forge",
+                "2026-01-10T11:00:00.500+00:00",
+            ),
+            (
+                "[10 Jan 2026 12:00:00.500] [main/INFO]: Forge",
+                "2026-01-10T11:00:00.500+00:00",
+            ),
+        ];
+
+        for (line, expected) in cases {
+            let resolved = berlin().resolve(line, received).map(|dt| dt.to_rfc3339());
+            assert_eq!(resolved.as_deref(), Some(expected), "{line}");
+        }
+    }
+
+    #[test]
+    fn ignores_lines_without_a_timestamp() {
+        let received = utc("2026-01-10T11:00:30Z");
+        for line in [
+            "Starting net.minecraft.server.Main",
+            "[Server thread/INFO]: Done",
+            "\tat java.base/java.lang.Thread.run(Thread.java:1583)",
+            "",
+        ] {
+            assert_eq!(berlin().resolve(line, received), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn keeps_the_day_across_midnight() {
+        let mut resolver = berlin();
+        // Logged just before midnight, received just after it.
+        assert_eq!(
+            resolve(
+                &mut resolver,
+                "[23:59:59] [Server thread/INFO]: a",
+                "2026-01-10T23:00:01Z"
+            ),
+            "2026-01-10T22:59:59+00:00"
+        );
+        // Logged just after midnight by a server whose clock runs ahead.
+        assert_eq!(
+            resolve(
+                &mut resolver,
+                "[00:00:01] [Server thread/INFO]: b",
+                "2026-01-10T22:59:59Z"
+            ),
+            "2026-01-10T23:00:01+00:00"
+        );
+        assert_eq!(
+            resolve(
+                &mut resolver,
+                "[12:00:00] [Server thread/INFO]: c",
+                "2026-01-11T11:00:00Z"
+            ),
+            "2026-01-11T11:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn reads_skipped_times_with_the_offset_before_spring_forward() {
+        // Clocks in Berlin went from 02:00 to 03:00 on 29 March 2026, at 01:00 UTC.
+        let mut resolver = berlin();
+        assert_eq!(
+            resolve(
+                &mut resolver,
+                "[01:59:59] [Server thread/INFO]: a",
+                "2026-03-29T00:59:59Z"
+            ),
+            "2026-03-29T00:59:59+00:00"
+        );
+        assert_eq!(
+            resolve(
+                &mut resolver,
+                "[02:30:00] [Server thread/INFO]: b",
+                "2026-03-29T01:30:00Z"
+            ),
+            "2026-03-29T01:30:00+00:00"
+        );
+        assert_eq!(
+            resolve(
+                &mut resolver,
+                "2026-03-29 02:00:00 [main/INFO]: c",
+                "2026-03-29T01:00:00Z"
+            ),
+            "2026-03-29T01:00:00+00:00"
+        );
+        assert_eq!(
+            resolve(
+                &mut resolver,
+                "[03:00:00] [Server thread/INFO]: d",
+                "2026-03-29T01:00:00Z"
+            ),
+            "2026-03-29T01:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn never_goes_backwards_through_the_fall_back_fold() {
+        // Clocks in Berlin went from 03:00 back to 02:00 on 25 October 2026, at 01:00 UTC.
+        let mut resolver = berlin();
+        let lines = [
+            (
+                "[02:30:00]",
+                "2026-10-25T00:30:00Z",
+                "2026-10-25T00:30:00+00:00",
+            ),
+            (
+                "[02:59:59]",
+                "2026-10-25T00:59:59Z",
+                "2026-10-25T00:59:59+00:00",
+            ),
+            (
+                "[02:00:00]",
+                "2026-10-25T01:00:00Z",
+                "2026-10-25T01:00:00+00:00",
+            ),
+            (
+                "[02:30:00]",
+                "2026-10-25T01:30:00Z",
+                "2026-10-25T01:30:00+00:00",
+            ),
+            (
+                "[03:00:00]",
+                "2026-10-25T02:00:00Z",
+                "2026-10-25T02:00:00+00:00",
+            ),
+        ];
+        for (time, received, expected) in lines {
+            let line = format!("{time} [Server thread/INFO]: tick");
+            assert_eq!(resolve(&mut resolver, &line, received), expected, "{time}");
+        }
+    }
+
+    #[test]
+    fn first_line_in_the_fold_takes_the_earlier_instant() {
+        assert_eq!(
+            resolve(
+                &mut berlin(),
+                "[02:30:00] [Server thread/INFO]: a",
+                "2026-10-25T01:30:00Z"
+            ),
+            "2026-10-25T00:30:00+00:00"
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::WireError;
+
+use super::InstanceEvent;
+
+/// Version of the event wire format. Bumped whenever a field or variant changes in a
+/// way older readers cannot handle.
+pub const WIRE_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a InstanceEvent,
+}
+
+#[derive(Deserialize)]
+struct OwnedEnvelope {
+    #[serde(flatten)]
+    event: InstanceEvent,
+}
+
+#[derive(Deserialize)]
+struct VersionOnly {
+    version: u32,
+}
+
+fn check_version(header: VersionOnly) -> Result<(), WireError> {
+    if header.version == 0 || header.version > WIRE_VERSION {
+        return Err(WireError::UnsupportedVersion(header.version));
+    }
+    Ok(())
+}
+
+/// Durations as whole milliseconds.
+pub(crate) mod duration_ms {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_millis().min(u64::MAX as u128) as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+impl InstanceEvent {
+    fn envelope(&self) -> Envelope<'_> {
+        Envelope {
+            version: WIRE_VERSION,
+            event: self,
+        }
+    }
+
+    /// Encodes the event as a versioned JSON object with a `type`-tagged payload and an
+    /// RFC 3339 timestamp.
+    pub fn to_json(&self) -> Result<String, WireError> {
+        serde_json::to_string(&self.envelope()).map_err(|e| WireError::Encode(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, WireError> {
+        let decode = |e: serde_json::Error| WireError::Decode(e.to_string());
+        check_version(serde_json::from_str(json).map_err(decode)?)?;
+        let envelope: OwnedEnvelope = serde_json::from_str(json).map_err(decode)?;
+        Ok(envelope.event)
+    }
+
+    /// Encodes the event as CBOR, with the same structure as `to_json`.
+    #[cfg(feature = "wire-cbor")]
+    pub fn to_cbor(&self) -> Result<Vec<u8>, WireError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&self.envelope(), &mut bytes)
+            .map_err(|e| WireError::Encode(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    #[cfg(feature = "wire-cbor")]
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self, WireError> {
+        let decode = |e: ciborium::de::Error<std::io::Error>| WireError::Decode(e.to_string());
+        check_version(ciborium::from_reader(bytes).map_err(decode)?)?;
+        let envelope: OwnedEnvelope = ciborium::from_reader(bytes).map_err(decode)?;
+        Ok(envelope.event)
+    }
+
+    /// Encodes the event as MessagePack with named fields, with the same structure as
+    /// `to_json`.
+    #[cfg(feature = "wire-msgpack")]
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, WireError> {
+        rmp_serde::to_vec_named(&self.envelope()).map_err(|e| WireError::Encode(e.to_string()))
+    }
+
+    #[cfg(feature = "wire-msgpack")]
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, WireError> {
+        let decode = |e: rmp_serde::decode::Error| WireError::Decode(e.to_string());
+        check_version(rmp_serde::from_slice(bytes).map_err(decode)?)?;
+        let envelope: OwnedEnvelope = rmp_serde::from_slice(bytes).map_err(decode)?;
+        Ok(envelope.event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        config::stream::{AdvancementKind, EventPayload, StopStage, StreamLine, StreamSource},
+        instance::{ExitInfo, InstanceStatus, ResourceSample, ServerHealth},
+    };
+
+    /// Golden file of a variant; adding a variant fails to compile until it has one.
+    fn golden_name(payload: &EventPayload) -> &'static str {
+        match payload {
+            EventPayload::StateChange { .. } => "state_change",
+            EventPayload::StdLine { .. } => "std_line",
+            EventPayload::Exited { .. } => "exited",
+            EventPayload::RestartAttempt { .. } => "restart_attempt",
+            EventPayload::RestartGaveUp { .. } => "restart_gave_up",
+            EventPayload::PlayerJoined { .. } => "player_joined",
+            EventPayload::PlayerLeft { .. } => "player_left",
+            EventPayload::Chat { .. } => "chat",
+            EventPayload::Say { .. } => "say",
+            EventPayload::Emote { .. } => "emote",
+            EventPayload::PlayerDied { .. } => "player_died",
+            EventPayload::Advancement { .. } => "advancement",
+            EventPayload::LagWarning { .. } => "lag_warning",
+            EventPayload::WorldSaved => "world_saved",
+            EventPayload::Stopping { .. } => "stopping",
+            EventPayload::Custom { .. } => "custom",
+            EventPayload::StreamLagged { .. } => "stream_lagged",
+            EventPayload::ResourceSample { .. } => "resource_sample",
+            EventPayload::HealthDegraded { .. } => "health_degraded",
+            EventPayload::HealthRecovered { .. } => "health_recovered",
+            EventPayload::WatchdogRestart { .. } => "watchdog_restart",
+            EventPayload::ReattachFailed { .. } => "reattach_failed",
+            EventPayload::LogSinkFailed { .. } => "log_sink_failed",
+        }
+    }
+
+    fn health(degraded: bool) -> ServerHealth {
+        ServerHealth {
+            mspt: Some(if degraded { 62.5 } else { 25.0 }),
+            tps: Some(if degraded { 16.0 } else { 20.0 }),
+            degraded,
+            samples: 4,
+            last_response: Some(Utc.with_ymd_and_hms(2024, 6, 1, 11, 59, 30).unwrap()),
+        }
+    }
+
+    fn payloads() -> Vec<EventPayload> {
+        vec![
+            EventPayload::StateChange {
+                old: InstanceStatus::Starting,
+                new: InstanceStatus::Running,
+            },
+            EventPayload::StdLine {
+                line: StreamLine::stdout("[12:00:00] [Server thread/INFO]: Done (3.2s)!"),
+            },
+            EventPayload::Exited {
+                info: ExitInfo {
+                    code: Some(1),
+                    signal: None,
+                    uptime: Duration::from_millis(93_500),
+                    stderr_tail: vec!["java.lang.OutOfMemoryError".to_string()],
+                    oom_killed: false,
+                },
+            },
+            EventPayload::RestartAttempt {
+                attempt: 2,
+                max_retries: 5,
+                delay: Duration::from_secs(10),
+            },
+            EventPayload::RestartGaveUp { attempts: 5 },
+            EventPayload::PlayerJoined {
+                name: "Notch".to_string(),
+                uuid: Some(Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap()),
+                ip: Some("127.0.0.1".to_string()),
+            },
+            EventPayload::PlayerLeft {
+                name: "Notch".to_string(),
+                reason: Some("Disconnected".to_string()),
+            },
+            EventPayload::Chat {
+                sender: "Notch".to_string(),
+                message: "hello".to_string(),
+            },
+            EventPayload::Say {
+                source: "Server".to_string(),
+                message: "restarting soon".to_string(),
+            },
+            EventPayload::Emote {
+                name: "Notch".to_string(),
+                action: "waves".to_string(),
+            },
+            EventPayload::PlayerDied {
+                name: "Notch".to_string(),
+                message: "Notch was slain by Zombie".to_string(),
+            },
+            EventPayload::Advancement {
+                name: "Notch".to_string(),
+                kind: AdvancementKind::Goal,
+                title: "Sky's the Limit".to_string(),
+            },
+            EventPayload::LagWarning {
+                behind: Duration::from_millis(2034),
+                ticks: 40,
+            },
+            EventPayload::WorldSaved,
+            EventPayload::Stopping {
+                stage: StopStage::SavingWorlds,
+            },
+            EventPayload::Custom {
+                name: "backup".to_string(),
+                fields: BTreeMap::from([("world".to_string(), "world".to_string())]),
+            },
+            EventPayload::StreamLagged {
+                stream: StreamSource::Stdout,
+                missed: 12,
+            },
+            EventPayload::ResourceSample {
+                sample: ResourceSample {
+                    timestamp: Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+                    processes: 1,
+                    cpu_percent: Some(37.5),
+                    rss_bytes: 2_147_483_648,
+                    threads: 64,
+                    open_fds: 212,
+                    read_bytes: Some(4096),
+                    write_bytes: None,
+                },
+            },
+            EventPayload::HealthDegraded {
+                health: health(true),
+            },
+            EventPayload::HealthRecovered {
+                health: health(false),
+            },
+            EventPayload::WatchdogRestart {
+                unresponsive: Duration::from_secs(30),
+            },
+            EventPayload::ReattachFailed {
+                error: "Failed to open the detached server's stdio".to_string(),
+            },
+            EventPayload::LogSinkFailed {
+                error: "No space left on device (os error 28)".to_string(),
+            },
+        ]
+    }
+
+    fn event(payload: EventPayload) -> InstanceEvent {
+        InstanceEvent {
+            id: Uuid::parse_str("3f2b8c1e-9d4a-4e6b-8f7c-1a2b3c4d5e6f").unwrap(),
+            seq: 42,
+            timestamp: Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+            payload,
+        }
+    }
+
+    fn golden_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/wire")
+            .join(format!("{name}.json"))
+    }
+
+    /// Compares every variant with its golden file. Run with `BLESS=1` to rewrite them
+    /// after an intended format change, together with a `WIRE_VERSION` bump.
+    #[test]
+    fn json_matches_golden_files() {
+        for payload in payloads() {
+            let name = golden_name(&payload);
+            let event = event(payload);
+            let encoded: serde_json::Value =
+                serde_json::from_str(&event.to_json().unwrap()).unwrap();
+
+            let path = golden_path(name);
+            if std::env::var_os("BLESS").is_some() {
+                let pretty = serde_json::to_string_pretty(&encoded).unwrap();
+                fs::write(&path, pretty + "\n").unwrap();
+            }
+            let golden = fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("missing golden file {}: {e}", path.display()));
+
+            let expected: serde_json::Value = serde_json::from_str(&golden).unwrap();
+            assert_eq!(encoded, expected, "{name} does not match its golden file");
+            assert_eq!(InstanceEvent::from_json(&golden).unwrap(), event, "{name}");
+        }
+    }
+
+    #[test]
+    fn json_rejects_unknown_version() {
+        let mut envelope: serde_json::Value =
+            serde_json::from_str(&event(EventPayload::WorldSaved).to_json().unwrap()).unwrap();
+
+        for version in [0, WIRE_VERSION + 1] {
+            envelope["version"] = version.into();
+            let err = InstanceEvent::from_json(&envelope.to_string()).unwrap_err();
+            assert!(matches!(err, WireError::UnsupportedVersion(v) if v == version));
+        }
+    }
+
+    #[cfg(feature = "wire-cbor")]
+    #[test]
+    fn cbor_round_trips_every_variant() {
+        for payload in payloads() {
+            let name = golden_name(&payload);
+            let event = event(payload);
+            let bytes = event.to_cbor().unwrap();
+            assert_eq!(InstanceEvent::from_cbor(&bytes).unwrap(), event, "{name}");
+        }
+    }
+
+    #[cfg(feature = "wire-cbor")]
+    #[test]
+    fn cbor_rejects_unknown_version() {
+        let mut bytes = Vec::new();
+        let envelope = Envelope {
+            version: WIRE_VERSION + 1,
+            event: &event(EventPayload::WorldSaved),
+        };
+        ciborium::into_writer(&envelope, &mut bytes).unwrap();
+
+        assert!(matches!(
+            InstanceEvent::from_cbor(&bytes).unwrap_err(),
+            WireError::UnsupportedVersion(v) if v == WIRE_VERSION + 1
+        ));
+    }
+
+    #[cfg(feature = "wire-msgpack")]
+    #[test]
+    fn msgpack_round_trips_every_variant() {
+        for payload in payloads() {
+            let name = golden_name(&payload);
+            let event = event(payload);
+            let bytes = event.to_msgpack().unwrap();
+            assert_eq!(
+                InstanceEvent::from_msgpack(&bytes).unwrap(),
+                event,
+                "{name}"
+            );
+        }
+    }
+
+    #[cfg(feature = "wire-msgpack")]
+    #[test]
+    fn msgpack_rejects_unknown_version() {
+        let envelope = Envelope {
+            version: WIRE_VERSION + 1,
+            event: &event(EventPayload::WorldSaved),
+        };
+        let bytes = rmp_serde::to_vec_named(&envelope).unwrap();
+
+        assert!(matches!(
+            InstanceEvent::from_msgpack(&bytes).unwrap_err(),
+            WireError::UnsupportedVersion(v) if v == WIRE_VERSION + 1
+        ));
+    }
+}
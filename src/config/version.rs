@@ -4,7 +4,6 @@ use std::{
 };
 
 use serde::{Deserialize, Serialize};
-use tokio::sync::watch;
 
 use crate::error::VersionError;
 
@@ -52,8 +51,8 @@ impl Display for Snapshot {
 impl Display for MinecraftVersion {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            MinecraftVersion::Release(version) => write!(f, "{}", version.to_string()),
-            MinecraftVersion::Snapshot(snapshot) => write!(f, "{}", snapshot.to_string()),
+            MinecraftVersion::Release(version) => write!(f, "{}", version),
+            MinecraftVersion::Snapshot(snapshot) => write!(f, "{}", snapshot),
         }
     }
 }
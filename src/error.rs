@@ -1,5 +1,9 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
+use crate::instance::InstanceStatus;
+
 #[derive(Debug, Clone, Error)]
 pub enum Error {
     #[error("Undefined error")]
@@ -55,6 +59,159 @@ pub enum HandleError {
 
     #[error("Invalid relative JAR path: {0}")]
     InvalidPathJAR(String),
+
+    #[error("Invalid event rule: {0}")]
+    InvalidRule(String),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum JavaError {
+    #[error("Invalid Java version: {0}")]
+    InvalidVersion(String),
+
+    #[error("Unrecognized java -version output: {0}")]
+    UnrecognizedOutput(String),
+
+    #[error("Failed to run java executable: {0}")]
+    ProbeFailed(String),
+
+    #[error("Timed out probing java executable: {0}")]
+    ProbeTimeout(String),
+
+    #[error("No Java {0} or newer runtime found")]
+    NoCompatibleRuntime(u32),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum RconError {
+    #[error("RCON is not configured for this server")]
+    NotConfigured,
+
+    #[error("RCON client is not connected")]
+    NotConnected,
+
+    #[error("Server is not running")]
+    NotRunning,
+
+    #[error("Failed to connect to RCON: {0}")]
+    ConnectFailed(String),
+
+    #[error("RCON authentication failed")]
+    AuthFailed,
+
+    #[error("RCON command is too long: {0} bytes")]
+    CommandTooLong(usize),
+
+    #[error("RCON request timed out")]
+    Timeout,
+
+    #[error("RCON io error: {0}")]
+    Io(String),
+
+    #[error("RCON protocol error: {0}")]
+    Protocol(String),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum PingError {
+    #[error("Failed to connect to server: {0}")]
+    ConnectFailed(String),
+
+    #[error("Ping timed out")]
+    Timeout,
+
+    #[error("Ping io error: {0}")]
+    Io(String),
+
+    #[error("Ping protocol error: {0}")]
+    Protocol(String),
+
+    #[error("Invalid status response: {0}")]
+    InvalidResponse(String),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum QueryError {
+    #[error("Query is not enabled in server.properties")]
+    NotEnabled,
+
+    #[error("Failed to connect to query port: {0}")]
+    ConnectFailed(String),
+
+    #[error("Query timed out")]
+    Timeout,
+
+    #[error("Query io error: {0}")]
+    Io(String),
+
+    #[error("Query protocol error: {0}")]
+    Protocol(String),
+
+    #[error("Invalid query response: {0}")]
+    InvalidResponse(String),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum PropertiesError {
+    #[error("Invalid escape sequence in server.properties: {0}")]
+    InvalidEscape(String),
+
+    #[error("Properties io error: {0}")]
+    Io(String),
+
+    #[error("Changing {0:?} requires a server restart")]
+    RestartRequired(Vec<String>),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum PlayerListError {
+    #[error("Player lists cannot be changed while the server is {0:?}")]
+    Busy(InstanceStatus),
+
+    #[error("Invalid player name: {0}")]
+    InvalidName(String),
+
+    #[error("Unknown player: {0}")]
+    UnknownPlayer(String),
+
+    #[error("Failed to look up player UUID: {0}")]
+    LookupFailed(String),
+
+    #[error("Failed to send console command: {0}")]
+    CommandFailed(String),
+
+    #[error("Failed to parse player list: {0}")]
+    Parse(String),
+
+    #[error("Player list io error: {0}")]
+    Io(String),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum LaunchError {
+    #[error("Unknown launch preset: {0}")]
+    UnknownPreset(String),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum WireError {
+    #[error("Failed to encode event: {0}")]
+    Encode(String),
+
+    #[error("Failed to decode event: {0}")]
+    Decode(String),
+
+    #[error("Unsupported wire format version {0}")]
+    UnsupportedVersion(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum StreamError {
+    /// The subscriber fell behind; `resume_from` is the sequence number to pass to
+    /// `resume_from` on the handle to replay the missed events from the backlog. When
+    /// resuming, `missed` is at most the number of events no longer in the backlog.
+    #[error("Subscriber lagged and missed {missed} events starting at sequence {resume_from}")]
+    Lagged { missed: u64, resume_from: u64 },
 }
 
 #[derive(Debug, Clone, Error)]
@@ -74,12 +231,27 @@ pub enum ServerError {
     #[error("Server is not running")]
     NotRunning,
 
-    #[error("Server crashed early")]
-    EarlyCrash,
+    #[error("Server crashed early (exit code: {code:?})")]
+    EarlyCrash {
+        code: Option<i32>,
+        output: Vec<String>,
+    },
+
+    #[error("Server did not finish starting within {timeout:?}")]
+    StartTimeout {
+        timeout: Duration,
+        output: Vec<String>,
+    },
 
     #[error("Failed to run java command")]
     CommandFailed,
 
+    #[error("Java executable not usable: {0}")]
+    JavaNotFound(String),
+
+    #[error("Server needs Java {required} but the configured runtime is Java {found}")]
+    IncompatibleJava { required: u32, found: u32 },
+
     #[error("Failed to access child stdout pipe")]
     NoStdoutPipe,
 
@@ -92,6 +264,40 @@ pub enum ServerError {
     #[error("Failed to write to stdin")]
     StdinWriteFailed,
 
+    #[error("Failed to signal the server process group")]
+    SignalFailed,
+
+    #[error("Failed to set up the stdio of a detached server: {0}")]
+    DetachedIo(String),
+
+    #[cfg(feature = "cgroups")]
+    #[error("Failed to set up the server cgroup: {0}")]
+    Cgroup(String),
+
+    #[error("Command output did not match before the capture timeout")]
+    CaptureTimeout { output: Vec<String> },
+
+    #[error("Capture fell behind the server output and missed {missed} lines")]
+    CaptureIncomplete { missed: u64, output: Vec<String> },
+
+    #[error("Failed to read the player list from the server")]
+    PlayerListUnavailable,
+
+    #[error("Illegal status transition from {from:?} to {to:?}")]
+    IllegalTransition {
+        from: InstanceStatus,
+        to: InstanceStatus,
+    },
+
+    #[error("Status did not change as expected within {timeout:?} (last: {last:?})")]
+    StatusTimeout {
+        timeout: Duration,
+        last: InstanceStatus,
+    },
+
+    #[error("Failed to generate an RCON password: {0}")]
+    PasswordGeneration(String),
+
     #[error("Failed to open eula.txt")]
     NoEULA,
     #[error("Failed to write eula.txt")]
@@ -106,6 +312,9 @@ pub enum ServerError {
 pub enum ParserError {
     #[error("ParserError")]
     ParserError,
+
+    #[error("Invalid event rule {name}: {error}")]
+    InvalidRule { name: String, error: String },
 }
 
 #[derive(Debug, Clone, Error)]
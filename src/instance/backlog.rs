@@ -0,0 +1,306 @@
+use std::{
+    collections::VecDeque,
+    mem::size_of,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll, ready},
+};
+
+use tokio::sync::broadcast;
+use tokio_stream::{
+    Stream,
+    wrappers::{BroadcastStream, errors::BroadcastStreamRecvError},
+};
+
+use crate::{
+    config::{BacklogPolicy, stream::EventPayload, stream::InstanceEvent},
+    error::StreamError,
+};
+
+/// Bounded ring buffer of the most recent events of one stream.
+#[derive(Debug)]
+struct Backlog {
+    events: VecDeque<InstanceEvent>,
+    bytes: usize,
+    policy: BacklogPolicy,
+    /// Number of events trimmed so far, and the sequence number of the last one.
+    trimmed: u64,
+    last_trimmed: u64,
+}
+
+impl Backlog {
+    fn push(&mut self, event: InstanceEvent) {
+        self.bytes += event_size(&event);
+        self.events.push_back(event);
+        self.trim();
+    }
+
+    /// Sequence number of the oldest event still kept, or `next_seq` when there is none.
+    fn oldest_seq(&self, next_seq: u64) -> u64 {
+        self.events.front().map_or(next_seq, |event| event.seq)
+    }
+
+    fn trim(&mut self) {
+        while self.events.len() > self.policy.max_lines || self.bytes > self.policy.max_bytes {
+            let Some(event) = self.events.pop_front() else {
+                break;
+            };
+            self.bytes -= event_size(&event);
+            self.trimmed += 1;
+            self.last_trimmed = event.seq;
+        }
+    }
+}
+
+fn event_size(event: &InstanceEvent) -> usize {
+    let text = match &event.payload {
+        EventPayload::StdLine { line } => line.line.len(),
+        _ => 0,
+    };
+    size_of::<InstanceEvent>() + text
+}
+
+/// Broadcast channel of one output stream that numbers its events and remembers the
+/// most recent ones.
+///
+/// The channels of an instance share `seq`, so sequence numbers increase across all of
+/// its streams and those of one stream are not contiguous. Sending and subscribing with
+/// backlog take the same lock, so a subscriber sees every event exactly once: either in
+/// the backlog or on the live receiver.
+#[derive(Debug, Clone)]
+pub(crate) struct EventChannel {
+    tx: broadcast::Sender<InstanceEvent>,
+    backlog: Arc<Mutex<Backlog>>,
+    /// Sequence number of the next event sent on any channel of the instance.
+    seq: Arc<AtomicU64>,
+}
+
+impl EventChannel {
+    pub(crate) fn new(capacity: usize, policy: BacklogPolicy, seq: Arc<AtomicU64>) -> Self {
+        Self {
+            tx: broadcast::Sender::new(capacity),
+            backlog: Arc::new(Mutex::new(Backlog {
+                events: VecDeque::new(),
+                bytes: 0,
+                policy,
+                trimmed: 0,
+                last_trimmed: 0,
+            })),
+            seq,
+        }
+    }
+
+    /// Counter for the channels of a new instance, starting at sequence number 1.
+    pub(crate) fn sequence() -> Arc<AtomicU64> {
+        Arc::new(AtomicU64::new(1))
+    }
+
+    pub(crate) fn send(&self, mut event: InstanceEvent) {
+        // Numbered under the backlog lock, so that the backlog stays in sequence order.
+        let mut backlog = self.backlog.lock().unwrap();
+        event.seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        backlog.push(event.clone());
+        _ = self.tx.send(event);
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.seq.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<InstanceEvent> {
+        self.tx.subscribe()
+    }
+
+    /// The last `n` events followed by everything sent after them.
+    pub(crate) fn subscribe_with_backlog(&self, n: usize) -> BacklogStream {
+        let backlog = self.backlog.lock().unwrap();
+        let skip = backlog.events.len().saturating_sub(n);
+        let replay: VecDeque<_> = backlog.events.iter().skip(skip).cloned().collect();
+        let next_seq = replay.front().map_or(self.next_seq(), |event| event.seq);
+
+        BacklogStream {
+            gap: None,
+            replay,
+            live: BroadcastStream::new(self.tx.subscribe()),
+            next_seq,
+        }
+    }
+
+    /// Every event from sequence number `seq` on. When some of them are no longer in
+    /// the backlog, the stream starts with a `StreamError::Lagged` covering them.
+    pub(crate) fn subscribe_from(&self, seq: u64) -> BacklogStream {
+        let backlog = self.backlog.lock().unwrap();
+        let seq = seq.clamp(1, self.next_seq());
+        let oldest = backlog.oldest_seq(self.next_seq());
+        // Trimmed events are not remembered one by one, and the sequence numbers
+        // between them may belong to other streams, so `missed` is an upper bound.
+        let gap = (seq <= backlog.last_trimmed).then(|| StreamError::Lagged {
+            missed: (backlog.last_trimmed - seq + 1).min(backlog.trimmed),
+            resume_from: seq,
+        });
+        let replay = backlog
+            .events
+            .iter()
+            .filter(|event| event.seq >= seq)
+            .cloned()
+            .collect();
+
+        BacklogStream {
+            gap,
+            replay,
+            live: BroadcastStream::new(self.tx.subscribe()),
+            next_seq: seq.max(oldest),
+        }
+    }
+
+    pub(crate) fn set_policy(&self, policy: BacklogPolicy) {
+        let mut backlog = self.backlog.lock().unwrap();
+        backlog.policy = policy;
+        backlog.trim();
+    }
+}
+
+/// Stream returned by `subscribe_with_backlog` and `resume_from`: the replayed backlog,
+/// then live events. Events that were dropped are reported as `StreamError::Lagged`.
+#[derive(Debug)]
+pub struct BacklogStream {
+    gap: Option<StreamError>,
+    replay: VecDeque<InstanceEvent>,
+    live: BroadcastStream<InstanceEvent>,
+    /// Sequence number to resume from after the last event yielded.
+    next_seq: u64,
+}
+
+impl BacklogStream {
+    /// Number of backlog events not yet yielded.
+    pub fn pending_replay(&self) -> usize {
+        self.replay.len()
+    }
+}
+
+impl Stream for BacklogStream {
+    type Item = Result<InstanceEvent, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(gap) = self.gap.take() {
+            return Poll::Ready(Some(Err(gap)));
+        }
+        if let Some(event) = self.replay.pop_front() {
+            self.next_seq = event.seq + 1;
+            return Poll::Ready(Some(Ok(event)));
+        }
+
+        match ready!(Pin::new(&mut self.live).poll_next(cx)) {
+            Some(Ok(event)) => {
+                self.next_seq = event.seq + 1;
+                Poll::Ready(Some(Ok(event)))
+            }
+            // The missed events come after the last one yielded, whatever their numbers.
+            Some(Err(BroadcastStreamRecvError::Lagged(missed))) => {
+                Poll::Ready(Some(Err(StreamError::Lagged {
+                    missed,
+                    resume_from: self.next_seq,
+                })))
+            }
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+
+    fn channel(capacity: usize, max_lines: usize, seq: &Arc<AtomicU64>) -> EventChannel {
+        let policy = BacklogPolicy {
+            max_lines,
+            ..BacklogPolicy::default()
+        };
+        EventChannel::new(capacity, policy, seq.clone())
+    }
+
+    async fn next_seqs(stream: &mut BacklogStream, n: usize) -> Vec<Result<u64, StreamError>> {
+        let mut seqs = Vec::new();
+        for _ in 0..n {
+            seqs.push(stream.next().await.unwrap().map(|event| event.seq));
+        }
+        seqs
+    }
+
+    #[cfg(feature = "events")]
+    #[tokio::test]
+    async fn streams_of_an_instance_share_one_sequence() {
+        let seq = EventChannel::sequence();
+        let stdout = channel(16, 16, &seq);
+        let events = channel(16, 16, &seq);
+        let mut live = stdout.subscribe_with_backlog(0);
+
+        for n in 0..3 {
+            stdout.send(InstanceEvent::stdout(format!("line {n}"), Utc::now()));
+            events.send(InstanceEvent::new(EventPayload::WorldSaved));
+        }
+
+        assert_eq!(next_seqs(&mut live, 3).await, [Ok(1), Ok(3), Ok(5)]);
+        let mut replay = events.subscribe_with_backlog(10);
+        assert_eq!(next_seqs(&mut replay, 3).await, [Ok(2), Ok(4), Ok(6)]);
+    }
+
+    #[tokio::test]
+    async fn resuming_skips_the_numbers_of_other_streams() {
+        let seq = EventChannel::sequence();
+        let stdout = channel(16, 2, &seq);
+        let stderr = channel(16, 16, &seq);
+
+        // stdout gets 1, 3, 4 and 6; 1 and 3 are trimmed.
+        stdout.send(InstanceEvent::stdout("a", Utc::now()));
+        stderr.send(InstanceEvent::stderr("b", Utc::now()));
+        stdout.send(InstanceEvent::stdout("c", Utc::now()));
+        stdout.send(InstanceEvent::stdout("d", Utc::now()));
+        stderr.send(InstanceEvent::stderr("e", Utc::now()));
+        stdout.send(InstanceEvent::stdout("f", Utc::now()));
+
+        let mut resumed = stdout.subscribe_from(4);
+        assert_eq!(next_seqs(&mut resumed, 2).await, [Ok(4), Ok(6)]);
+
+        let mut resumed = stdout.subscribe_from(3);
+        let lagged = Err(StreamError::Lagged {
+            missed: 1,
+            resume_from: 3,
+        });
+        assert_eq!(next_seqs(&mut resumed, 3).await, [lagged, Ok(4), Ok(6)]);
+    }
+
+    #[tokio::test]
+    async fn live_lag_resumes_after_the_last_event_seen() {
+        let seq = EventChannel::sequence();
+        let stdout = channel(2, 16, &seq);
+        let stderr = channel(16, 16, &seq);
+        let mut live = stdout.subscribe_with_backlog(0);
+
+        stdout.send(InstanceEvent::stdout("a", Utc::now()));
+        assert_eq!(next_seqs(&mut live, 1).await, [Ok(1)]);
+        for n in 0..4 {
+            stderr.send(InstanceEvent::stderr("other", Utc::now()));
+            stdout.send(InstanceEvent::stdout(format!("line {n}"), Utc::now()));
+        }
+
+        // Four stdout events were sent since; the channel only holds the last two.
+        let lagged = Err(StreamError::Lagged {
+            missed: 2,
+            resume_from: 2,
+        });
+        assert_eq!(next_seqs(&mut live, 3).await, [lagged, Ok(7), Ok(9)]);
+
+        let mut resumed = stdout.subscribe_from(2);
+        assert_eq!(
+            next_seqs(&mut resumed, 4).await,
+            [Ok(3), Ok(5), Ok(7), Ok(9)]
+        );
+    }
+}
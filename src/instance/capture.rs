@@ -0,0 +1,69 @@
+use std::{
+    fmt::{self, Debug},
+    sync::Arc,
+    time::Duration,
+};
+
+type LineMatcher = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Controls how long `execute_and_capture` collects console output after a command.
+///
+/// Collection ends when `quiet_window` passes without a new line, when the matcher
+/// accepts a line, or when `timeout` elapses.
+#[derive(Clone)]
+pub struct CaptureOptions {
+    pub quiet_window: Duration,
+    pub timeout: Duration,
+    matcher: Option<LineMatcher>,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            quiet_window: Duration::from_millis(250),
+            timeout: Duration::from_secs(5),
+            matcher: None,
+        }
+    }
+}
+
+impl Debug for CaptureOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureOptions")
+            .field("quiet_window", &self.quiet_window)
+            .field("timeout", &self.timeout)
+            .field("matcher", &self.matcher.is_some())
+            .finish()
+    }
+}
+
+impl CaptureOptions {
+    pub fn quiet_window(mut self, quiet_window: Duration) -> Self {
+        self.quiet_window = quiet_window;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Stops collecting at the first line `matcher` accepts, including that line.
+    ///
+    /// With a matcher set the quiet window is ignored and reaching the timeout is an error.
+    pub fn until<F>(mut self, matcher: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.matcher = Some(Arc::new(matcher));
+        self
+    }
+
+    pub(crate) fn has_matcher(&self) -> bool {
+        self.matcher.is_some()
+    }
+
+    pub(crate) fn matches(&self, line: &str) -> bool {
+        self.matcher.as_ref().is_some_and(|matcher| matcher(line))
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind},
+    os::fd::{AsRawFd, OwnedFd},
+    path::{Path, PathBuf},
+};
+
+use tokio::process;
+use uuid::Uuid;
+
+use crate::config::CgroupLimits;
+
+/// Period `cpu.max` quotas are given for, in microseconds.
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// The cgroup of one server, holding its whole process group.
+#[derive(Debug, Clone)]
+pub(crate) struct Cgroup {
+    path: PathBuf,
+    /// `oom_kill` count of `memory.events` when the run started.
+    oom_kills: u64,
+}
+
+impl Cgroup {
+    /// Creates the cgroup of the instance `id`, or reuses it, and writes `limits`.
+    pub(crate) fn prepare(id: Uuid, limits: &CgroupLimits) -> io::Result<Self> {
+        let path = cgroup_path(&limits.parent, id);
+
+        enable_controllers(&limits.parent, limits)?;
+        match fs::create_dir(&path) {
+            Err(err) if err.kind() != ErrorKind::AlreadyExists => return Err(err),
+            _ => {}
+        }
+
+        let cgroup = Self {
+            oom_kills: oom_kills(&path),
+            path,
+        };
+        cgroup.apply(limits)?;
+        Ok(cgroup)
+    }
+
+    /// The cgroup of a server that is already running in it.
+    pub(crate) fn existing(id: Uuid, limits: &CgroupLimits) -> io::Result<Self> {
+        let path = cgroup_path(&limits.parent, id);
+        if !path.is_dir() {
+            return Err(ErrorKind::NotFound.into());
+        }
+
+        Ok(Self {
+            oom_kills: oom_kills(&path),
+            path,
+        })
+    }
+
+    /// Writes every limit, resetting those left unset to the kernel default in case a
+    /// previous run of the cgroup had them.
+    fn apply(&self, limits: &CgroupLimits) -> io::Result<()> {
+        let max = |value: Option<u64>| value.map_or("max".to_string(), |v| v.to_string());
+        let quota = limits
+            .cpu_quota_percent
+            .map(|percent| u64::from(percent) * CPU_PERIOD_US / 100);
+
+        self.write(
+            "memory.max",
+            limits.memory_max_bytes.is_some(),
+            &max(limits.memory_max_bytes),
+        )?;
+        self.write("pids.max", limits.pids_max.is_some(), &max(limits.pids_max))?;
+        self.write(
+            "cpu.max",
+            quota.is_some(),
+            &format!("{} {CPU_PERIOD_US}", max(quota)),
+        )?;
+        self.write(
+            "cpu.weight",
+            limits.cpu_weight.is_some(),
+            &limits.cpu_weight.unwrap_or(100).to_string(),
+        )?;
+        self.write(
+            "io.weight",
+            limits.io_weight.is_some(),
+            &format!("default {}", limits.io_weight.unwrap_or(100)),
+        )
+    }
+
+    /// Writes `value` to `file`. Unless `required`, a file missing because its
+    /// controller is not enabled is skipped.
+    fn write(&self, file: &str, required: bool, value: &str) -> io::Result<()> {
+        let path = self.path.join(file);
+        if !required && !path.exists() {
+            return Ok(());
+        }
+        fs::write(path, value).map_err(|err| io::Error::new(err.kind(), format!("{file}: {err}")))
+    }
+
+    /// Makes `command` move itself into the cgroup before it executes the server, so
+    /// everything it starts is limited from the first instruction on.
+    pub(crate) fn attach(&self, command: &mut process::Command) -> io::Result<()> {
+        let procs: OwnedFd = OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.procs"))?
+            .into();
+
+        // SAFETY: write is async-signal-safe and only reads the static buffer. The
+        // descriptor is kept open by the closure until the command is dropped.
+        unsafe {
+            command.pre_exec(move || {
+                // Writing 0 moves the writing process.
+                if libc::write(procs.as_raw_fd(), b"0".as_ptr().cast(), 1) != 1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the kernel OOM-killed a process of the cgroup since the run started.
+    pub(crate) fn oom_killed(&self) -> bool {
+        oom_kills(&self.path) > self.oom_kills
+    }
+
+    /// Removes the cgroup once it is empty.
+    pub(crate) fn remove(&self) {
+        _ = fs::remove_dir(&self.path);
+    }
+}
+
+fn cgroup_path(parent: &Path, id: Uuid) -> PathBuf {
+    parent.join(format!("mineguard-{id}"))
+}
+
+/// Enables in `parent` the controllers needed for the limits that are set.
+fn enable_controllers(parent: &Path, limits: &CgroupLimits) -> io::Result<()> {
+    let controllers = [
+        ("memory", limits.memory_max_bytes.is_some()),
+        ("pids", limits.pids_max.is_some()),
+        (
+            "cpu",
+            limits.cpu_weight.is_some() || limits.cpu_quota_percent.is_some(),
+        ),
+        ("io", limits.io_weight.is_some()),
+    ];
+    let subtree_control = parent.join("cgroup.subtree_control");
+    let enabled = fs::read_to_string(&subtree_control)?;
+
+    for (controller, needed) in controllers {
+        if needed && !enabled.split_whitespace().any(|c| c == controller) {
+            // Controllers are enabled one by one so the error names the one missing.
+            fs::write(&subtree_control, format!("+{controller}")).map_err(|err| {
+                let reason = if err.raw_os_error() == Some(libc::EBUSY) {
+                    format!("{} has processes of its own", parent.display())
+                } else {
+                    err.to_string()
+                };
+                io::Error::new(err.kind(), format!("{controller} controller: {reason}"))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn oom_kills(path: &Path) -> u64 {
+    fs::read_to_string(path.join("memory.events"))
+        .ok()
+        .and_then(|events| oom_kill_field(&events))
+        .unwrap_or(0)
+}
+
+fn oom_kill_field(events: &str) -> Option<u64> {
+    // memory.events lines are `key value`, without the colon of /proc files.
+    events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill ")?.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory laid out like a delegated cgroup v2 parent, removed when dropped.
+    struct Hierarchy(PathBuf);
+
+    impl Hierarchy {
+        fn new(name: &str, enabled: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "mineguard-cgroup-{}-{}",
+                name,
+                std::process::id()
+            ));
+            _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            fs::write(root.join("cgroup.subtree_control"), enabled).unwrap();
+            Self(root)
+        }
+
+        /// Creates the cgroup of `id` with the interface files of the enabled
+        /// controllers at their defaults, as the kernel would.
+        fn child(&self, id: Uuid) -> PathBuf {
+            let path = cgroup_path(&self.0, id);
+            fs::create_dir(&path).unwrap();
+            for (file, default) in [
+                ("cgroup.procs", ""),
+                ("memory.max", "max"),
+                ("memory.events", "low 0\nhigh 0\nmax 0\noom 0\noom_kill 0\n"),
+                ("pids.max", "max"),
+                ("cpu.max", "max 100000"),
+                ("cpu.weight", "100"),
+            ] {
+                fs::write(path.join(file), default).unwrap();
+            }
+            path
+        }
+
+        fn read(&self, path: &Path, file: &str) -> String {
+            fs::read_to_string(path.join(file)).unwrap()
+        }
+    }
+
+    impl Drop for Hierarchy {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn reads_oom_kills_from_memory_events() {
+        let events = "low 0\nhigh 12\nmax 40\noom 3\noom_kill 2\noom_group_kill 1\n";
+        assert_eq!(oom_kill_field(events), Some(2));
+        assert_eq!(oom_kill_field("low 0\nhigh 0\n"), None);
+        assert_eq!(oom_kill_field("oom_kill many\n"), None);
+        assert_eq!(oom_kill_field("oom_group_kill 4\n"), None);
+    }
+
+    #[test]
+    fn names_the_cgroup_after_the_instance() {
+        let id = Uuid::new_v4();
+        assert_eq!(
+            cgroup_path(Path::new("/sys/fs/cgroup/mineguard"), id),
+            PathBuf::from(format!("/sys/fs/cgroup/mineguard/mineguard-{id}"))
+        );
+    }
+
+    #[test]
+    fn writes_limits_and_resets_unset_ones() {
+        let hierarchy = Hierarchy::new("apply", "cpu pids");
+        let id = Uuid::new_v4();
+        let path = hierarchy.child(id);
+        fs::write(path.join("pids.max"), "64").unwrap();
+        fs::write(path.join("memory.events"), "oom_kill 3\n").unwrap();
+
+        let limits = CgroupLimits {
+            memory_max_bytes: Some(1 << 30),
+            cpu_quota_percent: Some(150),
+            ..CgroupLimits::new(&hierarchy.0)
+        };
+        let cgroup = Cgroup::prepare(id, &limits).unwrap();
+
+        assert_eq!(
+            hierarchy.read(&hierarchy.0, "cgroup.subtree_control"),
+            "+memory"
+        );
+        assert_eq!(hierarchy.read(&path, "memory.max"), "1073741824");
+        assert_eq!(hierarchy.read(&path, "cpu.max"), "150000 100000");
+        assert_eq!(hierarchy.read(&path, "pids.max"), "max");
+        assert_eq!(hierarchy.read(&path, "cpu.weight"), "100");
+        // io is not enabled, so its file is skipped rather than created.
+        assert!(!path.join("io.weight").exists());
+
+        // Only kills after the run started count.
+        assert!(!cgroup.oom_killed());
+        fs::write(path.join("memory.events"), "oom_kill 4\n").unwrap();
+        assert!(cgroup.oom_killed());
+    }
+
+    #[test]
+    fn missing_cgroup_is_not_existing() {
+        let hierarchy = Hierarchy::new("existing", "");
+        let id = Uuid::new_v4();
+        let limits = CgroupLimits::new(&hierarchy.0);
+        assert!(Cgroup::existing(id, &limits).is_err());
+
+        hierarchy.child(id);
+        assert!(Cgroup::existing(id, &limits).is_ok());
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    ffi::CString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Seek, SeekFrom},
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use tokio::{net::unix::pipe, process::Command, sync::mpsc, time::sleep};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
+
+use super::procfs::ProcStat;
+
+/// Directory, relative to the server root, holding the stdio and PID file of a
+/// detached server.
+pub const RUN_DIR: &str = ".mineguard/run";
+
+const STDIN_FIFO: &str = "stdin";
+const STDOUT_FILE: &str = "stdout.log";
+const STDERR_FILE: &str = "stderr.log";
+const PID_FILE: &str = "server.pid";
+const STARTED_FILE: &str = "started";
+const EXIT_STATUS_FILE: &str = "exit_status";
+
+/// Runs the server given as its arguments and records its exit status in the file
+/// named by `$1`, since only the parent of a process learns how it exited. The shell
+/// ignores the signals sent to the process group so that it outlives the server, which
+/// gets the default dispositions back before it is executed.
+const EXIT_STATUS_WRAPPER: &str = r#"status="$1"; shift
+trap '' TERM INT HUP
+(trap - TERM INT HUP; exec "$@")
+code=$?
+echo "$code" > "$status"
+exit "$code"
+"#;
+
+/// Size at which an output file is emptied once everything in it has been read, as
+/// the log sink keeps the console log.
+const MAX_OUTPUT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// How often a tailed output file is checked for new lines.
+const TAIL_POLL: Duration = Duration::from_millis(100);
+/// How often a process that is not our child is checked for having exited.
+const LIVENESS_POLL: Duration = Duration::from_millis(500);
+
+/// A process identified by its pid and its start time from `/proc/<pid>/stat`, so
+/// that a recycled pid is not mistaken for the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProcessFingerprint {
+    pub(crate) pid: u32,
+    start_time: u64,
+}
+
+impl ProcessFingerprint {
+    pub(crate) fn of(pid: u32) -> Option<Self> {
+        let stat = ProcStat::read(pid)?;
+        Some(Self {
+            pid,
+            start_time: stat.start_time,
+        })
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        ProcStat::read(self.pid)
+            .is_some_and(|stat| stat.start_time == self.start_time && stat.is_running())
+    }
+
+    /// Resolves once the process is gone.
+    pub(crate) async fn exited(&self) {
+        while self.is_alive() {
+            sleep(LIVENESS_POLL).await;
+        }
+    }
+}
+
+pub(crate) fn stdout_path(root_dir: &Path) -> PathBuf {
+    root_dir.join(RUN_DIR).join(STDOUT_FILE)
+}
+
+pub(crate) fn stderr_path(root_dir: &Path) -> PathBuf {
+    root_dir.join(RUN_DIR).join(STDERR_FILE)
+}
+
+/// Stdin, stdout and stderr for a new detached server: the stdin FIFO and freshly
+/// truncated output files, appended to so that they can be emptied while it runs.
+pub(crate) fn detached_stdio(root_dir: &Path) -> io::Result<(Stdio, Stdio, Stdio)> {
+    let dir = root_dir.join(RUN_DIR);
+    fs::create_dir_all(&dir)?;
+
+    let fifo = dir.join(STDIN_FIFO);
+    ensure_fifo(&fifo)?;
+    // Holding a write end itself, the server never reads EOF while no supervisor is attached.
+    let stdin = OpenOptions::new().read(true).write(true).open(&fifo)?;
+    let stdout = create_output(&dir.join(STDOUT_FILE))?;
+    let stderr = create_output(&dir.join(STDERR_FILE))?;
+    // Left over from the previous run.
+    remove_if_exists(&dir.join(STARTED_FILE))?;
+    remove_if_exists(&dir.join(EXIT_STATUS_FILE))?;
+
+    Ok((stdin.into(), stdout.into(), stderr.into()))
+}
+
+fn create_output(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.set_len(0)?;
+    Ok(file)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn ensure_fifo(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => return Ok(()),
+        Ok(_) => fs::remove_file(path)?,
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::from(ErrorKind::InvalidInput))?;
+    // SAFETY: `c_path` is a NUL terminated string that outlives the call.
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Writer for the stdin FIFO of a detached server.
+pub(crate) fn open_stdin(root_dir: &Path) -> io::Result<pipe::Sender> {
+    // Read-write so that opening never fails or blocks for lack of a reader.
+    pipe::OpenOptions::new()
+        .read_write(true)
+        .open_sender(root_dir.join(RUN_DIR).join(STDIN_FIFO))
+}
+
+/// Lines appended to `path` after `offset`. Reading stops at the end of the file once
+/// `done` is cancelled, so everything written before that is still delivered.
+///
+/// The file is emptied whenever it has grown past `MAX_OUTPUT_BYTES` and been read to
+/// its end. A line the server writes between the size check and the truncation is
+/// lost, which is as close as a file shared with a running process gets.
+pub(crate) fn tail(path: PathBuf, offset: u64, done: CancellationToken) -> ReceiverStream<String> {
+    tail_capped(path, offset, MAX_OUTPUT_BYTES, done)
+}
+
+fn tail_capped(
+    path: PathBuf,
+    offset: u64,
+    max_bytes: u64,
+    done: CancellationToken,
+) -> ReceiverStream<String> {
+    let (tx, rx) = mpsc::channel(1024);
+
+    tokio::task::spawn_blocking(move || {
+        let Ok(mut file) = OpenOptions::new().read(true).write(true).open(&path) else {
+            return;
+        };
+        if file.seek(SeekFrom::Start(offset)).is_err() {
+            return;
+        }
+        let mut reader = BufReader::new(file);
+        let mut line = Vec::new();
+
+        loop {
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) if done.is_cancelled() => break,
+                Ok(0) => {
+                    if line.is_empty() && truncate_read(&mut reader, max_bytes).is_err() {
+                        break;
+                    }
+                    std::thread::sleep(TAIL_POLL);
+                }
+                // A line without its newline is still being written.
+                Ok(_) if !line.ends_with(b"\n") => {}
+                Ok(_) => {
+                    if tx.blocking_send(take_line(&mut line)).is_err() {
+                        return;
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+
+        if !line.is_empty() {
+            _ = tx.blocking_send(take_line(&mut line));
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// Empties a file read to its end once it holds at least `max_bytes`, unless more was
+/// written since the end was reached.
+fn truncate_read(reader: &mut BufReader<File>, max_bytes: u64) -> io::Result<()> {
+    let position = reader.stream_position()?;
+    if position < max_bytes || reader.get_ref().metadata()?.len() != position {
+        return Ok(());
+    }
+    reader.get_ref().set_len(0)?;
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(())
+}
+
+fn take_line(line: &mut Vec<u8>) -> String {
+    let text = String::from_utf8_lossy(line)
+        .trim_end_matches(['\n', '\r'])
+        .to_string();
+    line.clear();
+    text
+}
+
+/// Command running `program` under a shell that records how it exits, read back with
+/// `exit_status` once a reattached server is gone. Arguments added to the command are
+/// passed on to `program`.
+pub(crate) fn recording_command(root_dir: &Path, program: &Path) -> Command {
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg(EXIT_STATUS_WRAPPER)
+        .arg("sh")
+        .arg(root_dir.join(RUN_DIR).join(EXIT_STATUS_FILE))
+        .arg(program);
+    command
+}
+
+/// Exit code and signal of the last detached server, as recorded by the shell it ran
+/// under. A status above 128 is a death by signal, as the shell reports it.
+pub(crate) fn exit_status(root_dir: &Path) -> (Option<i32>, Option<i32>) {
+    let status = fs::read_to_string(root_dir.join(RUN_DIR).join(EXIT_STATUS_FILE))
+        .ok()
+        .and_then(|content| content.trim().parse::<i32>().ok());
+    match status {
+        Some(status) if status > 128 => (None, Some(status - 128)),
+        Some(code) => (Some(code), None),
+        None => (None, None),
+    }
+}
+
+pub(crate) fn write_pid_file(root_dir: &Path, process: &ProcessFingerprint) -> io::Result<()> {
+    fs::write(
+        root_dir.join(RUN_DIR).join(PID_FILE),
+        format!("{} {}\n", process.pid, process.start_time),
+    )
+}
+
+fn read_pid_file(root_dir: &Path) -> Option<ProcessFingerprint> {
+    let content = fs::read_to_string(root_dir.join(RUN_DIR).join(PID_FILE)).ok()?;
+    let (pid, start_time) = content.trim().split_once(' ')?;
+    Some(ProcessFingerprint {
+        pid: pid.parse().ok()?,
+        start_time: start_time.parse().ok()?,
+    })
+}
+
+/// The detached server recorded in the PID file, if that process is still running.
+pub(crate) fn live_process(root_dir: &Path) -> Option<ProcessFingerprint> {
+    read_pid_file(root_dir).filter(ProcessFingerprint::is_alive)
+}
+
+/// Removes the PID file if it still names `pid`.
+pub(crate) fn clear_pid_file(root_dir: &Path, pid: u32) {
+    if read_pid_file(root_dir).is_some_and(|process| process.pid == pid) {
+        _ = fs::remove_file(root_dir.join(RUN_DIR).join(PID_FILE));
+        _ = fs::remove_file(root_dir.join(RUN_DIR).join(STARTED_FILE));
+    }
+}
+
+/// Records that the detached server `pid` has started, since its output files may no
+/// longer hold the line saying so by the time it is reattached. Does nothing when the
+/// PID file names another process, as for a server that is not detached.
+pub(crate) fn mark_started(root_dir: &Path, pid: u32) {
+    if read_pid_file(root_dir).is_some_and(|process| process.pid == pid) {
+        _ = fs::write(root_dir.join(RUN_DIR).join(STARTED_FILE), "");
+    }
+}
+
+pub(crate) fn marked_started(root_dir: &Path) -> bool {
+    root_dir.join(RUN_DIR).join(STARTED_FILE).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tokio::time::timeout;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+
+    /// A fresh server root under the system temp dir, removed when dropped.
+    struct TempRoot(PathBuf);
+
+    impl TempRoot {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "mineguard-detach-{}-{}",
+                name,
+                std::process::id()
+            ));
+            _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join(RUN_DIR)).unwrap();
+            Self(root)
+        }
+    }
+
+    impl Drop for TempRoot {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn next_line(lines: &mut ReceiverStream<String>) -> Option<String> {
+        timeout(Duration::from_secs(5), lines.next())
+            .await
+            .expect("no line within 5s")
+    }
+
+    #[tokio::test]
+    async fn tail_empties_an_output_file_past_the_limit() {
+        let root = TempRoot::new("tail");
+        let path = stdout_path(&root.0);
+        let mut server = create_output(&path).unwrap();
+        for n in 0..10 {
+            writeln!(server, "line {n}").unwrap();
+        }
+
+        let done = CancellationToken::new();
+        let mut lines = tail_capped(path.clone(), 0, 32, done.clone());
+        for n in 0..10 {
+            assert_eq!(next_line(&mut lines).await.unwrap(), format!("line {n}"));
+        }
+
+        timeout(Duration::from_secs(5), async {
+            while fs::metadata(&path).unwrap().len() != 0 {
+                sleep(TAIL_POLL).await;
+            }
+        })
+        .await
+        .expect("output file was never emptied");
+
+        // The server keeps appending at the new end of the file.
+        writeln!(server, "after").unwrap();
+        assert_eq!(next_line(&mut lines).await.unwrap(), "after");
+        assert_eq!(fs::metadata(&path).unwrap().len(), 6);
+
+        done.cancel();
+        assert_eq!(next_line(&mut lines).await, None);
+    }
+
+    #[tokio::test]
+    async fn tail_keeps_an_output_file_below_the_limit() {
+        let root = TempRoot::new("below");
+        let path = stdout_path(&root.0);
+        let mut server = create_output(&path).unwrap();
+        writeln!(server, "short").unwrap();
+
+        let done = CancellationToken::new();
+        let mut lines = tail_capped(path.clone(), 0, 1024, done.clone());
+        assert_eq!(next_line(&mut lines).await.unwrap(), "short");
+        sleep(TAIL_POLL * 2).await;
+        assert_eq!(fs::metadata(&path).unwrap().len(), 6);
+
+        done.cancel();
+        assert_eq!(next_line(&mut lines).await, None);
+    }
+
+    #[test]
+    fn started_marker_belongs_to_the_recorded_process() {
+        let root = TempRoot::new("started");
+        let process = ProcessFingerprint::of(std::process::id()).unwrap();
+
+        // Not a detached server: nothing is recorded.
+        mark_started(&root.0, process.pid);
+        assert!(!marked_started(&root.0));
+
+        write_pid_file(&root.0, &process).unwrap();
+        mark_started(&root.0, process.pid + 1);
+        assert!(!marked_started(&root.0));
+        mark_started(&root.0, process.pid);
+        assert!(marked_started(&root.0));
+
+        clear_pid_file(&root.0, process.pid);
+        assert!(!marked_started(&root.0));
+    }
+
+    #[test]
+    fn new_run_clears_the_previous_started_marker() {
+        let root = TempRoot::new("relaunch");
+        let process = ProcessFingerprint::of(std::process::id()).unwrap();
+        write_pid_file(&root.0, &process).unwrap();
+        mark_started(&root.0, process.pid);
+        fs::write(stdout_path(&root.0), "old output\n").unwrap();
+
+        detached_stdio(&root.0).unwrap();
+        assert!(!marked_started(&root.0));
+        assert_eq!(fs::metadata(stdout_path(&root.0)).unwrap().len(), 0);
+    }
+}
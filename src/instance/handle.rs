@@ -1,57 +1,70 @@
-use std::{path::PathBuf, process::Stdio, sync::Arc, time::Duration};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
-    process::{self, Child},
-    sync::{RwLock, broadcast, mpsc},
-    time::sleep,
+    sync::{Mutex, mpsc, watch},
+    task::JoinHandle,
 };
-use tokio_stream::StreamExt;
-use tokio_stream::wrappers::BroadcastStream;
-use tokio_util::sync::CancellationToken;
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
 use uuid::Uuid;
 
+#[cfg(feature = "cgroups")]
+use crate::config::CgroupLimits;
 #[cfg(feature = "events")]
-use crate::config::stream::InstanceEvent;
+use crate::config::stream::{EventPayload, InstanceEvent};
 use crate::{
     config::{
-        MinecraftType, MinecraftVersion, StreamSource,
-        stream::{EventPayload, InternalEvent},
+        BacklogPolicy, EventRule, HealthPolicy, LaunchProfile, LogSinkPolicy, MetricsPolicy,
+        MinecraftType, MinecraftVersion, RestartPolicy, StreamSource,
     },
-    error::{HandleError, ServerError, SubscribeError},
+    error::{HandleError, ParserError, RconError, ServerError, SubscribeError},
+    net::RconClient,
+    parser::RuleSet,
     server::domain::MineGuardConfig,
 };
 
-use super::{InstanceData, InstanceStatus};
+use super::{
+    BacklogStream, CaptureOptions, ExitInfo, InstanceData, InstanceStatus, LogRecord, PlayerRoster,
+    PlaytimeLedger, ResourceSample, ServerHealth, StopOutcome, detach,
+    health::HealthMonitor,
+    logs::{LogSink, read_logs},
+    metrics::MetricsSampler,
+    restart::RestartSupervisor,
+    roster::RosterTracker,
+    runtime::InstanceRuntime,
+    status::wait_for_status,
+};
 
 #[derive(Debug)]
 pub struct InstanceHandle {
     pub data: InstanceData,
-    pub status: Arc<RwLock<InstanceStatus>>,
-    stdout_tx: broadcast::Sender<InstanceEvent>,
-    stderr_tx: Option<broadcast::Sender<InstanceEvent>>,
-    #[cfg(feature = "events")]
-    events_tx: broadcast::Sender<InstanceEvent>,
-    #[cfg(feature = "events")]
-    internal_events_tx: mpsc::Sender<InstanceEvent>,
+    runtime: InstanceRuntime,
     #[cfg(feature = "events")]
     internal_events_rx: Option<mpsc::Receiver<InstanceEvent>>,
-    stdin_tx: mpsc::Sender<String>,
-    stdin_rx: Option<mpsc::Receiver<String>>,
-    child: Option<Arc<RwLock<Child>>>,
-    shutdown: CancellationToken,
-    internal_bus_tx: broadcast::Sender<InternalEvent>,
+    restart: RestartSupervisor,
+    restart_task: Option<JoinHandle<()>>,
+    roster: RosterTracker,
+    roster_task: Option<JoinHandle<()>>,
+    log_sink: LogSink,
+    log_sink_task: Option<JoinHandle<()>>,
+    metrics: MetricsSampler,
+    metrics_task: Option<JoinHandle<()>>,
+    health: HealthMonitor,
+    health_task: Option<JoinHandle<()>>,
+    rcon: Arc<Mutex<Option<RconClient>>>,
 }
 
 impl InstanceHandle {
     pub fn new_with_config(config: MineGuardConfig) -> Result<Self, HandleError> {
-        InstanceHandle::new_with_params(
-            config.server_dir,
-            config.jar_path,
-            config.mc_version,
-            config.mc_type,
-        )
+        let data = InstanceHandle::validate_data(
+            config.server_dir.clone(),
+            config.jar_path.clone(),
+            config.mc_version.clone(),
+            config.mc_type.clone(),
+        )?;
+        let rules = RuleSet::compile(&config.event_rules)
+            .map_err(|e| HandleError::InvalidRule(e.to_string()))?;
+        Ok(InstanceHandle::from_parts(data, &config, rules))
     }
     pub fn new_with_params(
         root_dir: PathBuf,
@@ -59,16 +72,30 @@ impl InstanceHandle {
         mc_version: MinecraftVersion,
         mc_type: MinecraftType,
     ) -> Result<Self, HandleError> {
+        let data = InstanceHandle::validate_data(root_dir, jar_path, mc_version, mc_type)?;
+        Ok(InstanceHandle::from_parts(
+            data,
+            &MineGuardConfig::new(),
+            RuleSet::default(),
+        ))
+    }
+
+    fn validate_data(
+        root_dir: PathBuf,
+        jar_path: PathBuf,
+        mc_version: MinecraftVersion,
+        mc_type: MinecraftType,
+    ) -> Result<InstanceData, HandleError> {
         let parsed_version: MinecraftVersion = mc_version;
 
-        let root: PathBuf = root_dir.clone().into();
+        let root: PathBuf = root_dir.clone();
         if !root.exists() || !root.is_dir() {
             return Err(HandleError::InvalidDirectory(
                 root_dir.to_str().unwrap().to_string(),
             ));
         }
 
-        let path: PathBuf = jar_path.clone().into();
+        let path: PathBuf = jar_path.clone();
         let conc = root.join(path.clone());
         if !path.is_relative() || !conc.is_file() {
             return Err(HandleError::InvalidPathJAR(
@@ -76,248 +103,251 @@ impl InstanceHandle {
             ));
         }
 
-        let data = InstanceData {
+        Ok(InstanceData {
             root_dir: root,
             jar_path: path,
             mc_version: parsed_version,
             mc_type,
-        };
+        })
+    }
 
-        let status = InstanceStatus::Stopped;
+    fn from_parts(data: InstanceData, config: &MineGuardConfig, rules: RuleSet) -> Self {
+        let (runtime, internal_rx) = InstanceRuntime::new(data.clone(), config, rules);
+        let restart = RestartSupervisor::new(runtime.clone(), config.restart.clone());
+        let health = HealthMonitor::new(runtime.clone(), restart.clone(), config.health.clone());
+        let rcon = Arc::new(Mutex::new(None));
+        let roster = RosterTracker::new(runtime.clone(), rcon.clone(), health.probes());
+        let log_sink = LogSink::new(runtime.clone(), config.log_sink.clone());
+        let metrics = MetricsSampler::new(runtime.clone(), config.metrics.clone());
 
-        let (stdin_tx, stdin_rx) = mpsc::channel(1024);
-        let (internal_tx, internal_rx) = mpsc::channel(1024);
-        Ok(Self {
+        Self {
             data,
-            status: Arc::new(RwLock::new(status)),
-            stdout_tx: broadcast::Sender::new(2048),
-            stderr_tx: None,
-            #[cfg(feature = "events")]
-            events_tx: broadcast::Sender::new(2048),
-            #[cfg(feature = "events")]
-            internal_events_tx: internal_tx,
+            runtime,
             #[cfg(feature = "events")]
             internal_events_rx: Some(internal_rx),
-            stdin_tx,
-            stdin_rx: Some(stdin_rx),
-            child: None,
-            shutdown: CancellationToken::new(),
-            internal_bus_tx: broadcast::Sender::new(2048),
-        })
+            restart,
+            restart_task: None,
+            roster,
+            roster_task: None,
+            log_sink,
+            log_sink_task: None,
+            metrics,
+            metrics_task: None,
+            health,
+            health_task: None,
+            rcon,
+        }
     }
 
     pub async fn send_command<S: Into<String>>(&self, cmd: S) -> Result<(), ServerError> {
-        let mut command = cmd.into();
-        if !command.ends_with('\n') {
-            command.push('\n');
-        }
+        self.runtime.send_command(cmd.into()).await
+    }
 
-        self.stdin_tx
-            .send(command)
-            .await
-            .map_err(|_| ServerError::StdinWriteFailed)?;
+    /// Sends `cmd` over stdin and returns the stdout events it produced, for servers
+    /// without RCON. See `CaptureOptions` for when collection stops.
+    pub async fn execute_and_capture<S: Into<String>>(
+        &self,
+        cmd: S,
+        options: CaptureOptions,
+    ) -> Result<Vec<InstanceEvent>, ServerError> {
+        self.runtime.execute_and_capture(cmd.into(), &options).await
+    }
 
-        Ok(())
+    /// Sets the RCON client used by `execute`, replacing any previous connection.
+    pub async fn set_rcon(&self, client: Option<RconClient>) {
+        *self.rcon.lock().await = client;
+    }
+
+    /// Runs `cmd` over RCON and returns the output the server produced for it.
+    ///
+    /// Concurrent callers are serialized over the single RCON connection.
+    pub async fn execute<S: Into<String>>(&self, cmd: S) -> Result<String, RconError> {
+        if self.status() != InstanceStatus::Running {
+            return Err(RconError::NotRunning);
+        }
+
+        let mut rcon = self.rcon.lock().await;
+        let client = rcon.as_mut().ok_or(RconError::NotConfigured)?;
+        client.execute(&cmd.into()).await
     }
 
     pub async fn start(&mut self) -> Result<(), ServerError> {
-        self.validate_start_parameters().await?;
         self.setup_loopback()?;
+        self.setup_log_sink();
+        self.setup_metrics_sampler();
+        self.setup_health_monitor();
+        self.setup_restart_supervisor();
+        self.setup_roster_tracker();
+        self.restart.cancel_pending().await;
+
+        self.runtime.launch().await
+    }
 
-        self.transition_status(InstanceStatus::Starting).await;
-
-        let command = self.build_start_command();
-        let child = self.spawn_child_process(command)?;
+    /// Takes over a detached server still running from an earlier supervisor, with
+    /// its status and streams. Returns `false` when there is none. A failure is also
+    /// published as `ReattachFailed`.
+    pub async fn reattach(&mut self) -> Result<bool, ServerError> {
+        if detach::live_process(&self.data.root_dir).is_none() {
+            return Ok(false);
+        }
 
-        self.setup_stream_pumps(child)?;
+        self.setup_loopback()?;
+        self.setup_log_sink();
+        self.setup_metrics_sampler();
+        self.setup_health_monitor();
+        self.setup_restart_supervisor();
+        self.setup_roster_tracker();
+
+        let reattached = self.runtime.reattach().await;
+        #[cfg(feature = "events")]
+        if let Err(err) = &reattached {
+            self.runtime
+                .emit(EventPayload::ReattachFailed {
+                    error: err.to_string(),
+                })
+                .await;
+        }
+        reattached
+    }
 
-        self.setup_parser()?;
+    pub fn status(&self) -> InstanceStatus {
+        self.runtime.status()
+    }
 
-        let mut rx = self.internal_bus_tx.subscribe();
+    /// Receiver that sees every status change of this instance.
+    pub fn status_watch(&self) -> watch::Receiver<InstanceStatus> {
+        self.runtime.status_watch()
+    }
 
-        loop {
-            match rx.recv().await {
-                Ok(event) => {
-                    if event == InternalEvent::ServerStarted {
-                        self.transition_status(InstanceStatus::Running).await;
-                        break;
-                    }
-                    continue;
-                }
-                _ => continue,
-            }
-        }
+    /// Waits up to `timeout` for a status matching `predicate`, which is checked
+    /// against the current status first.
+    pub async fn wait_for<F>(
+        &self,
+        predicate: F,
+        timeout: Duration,
+    ) -> Result<InstanceStatus, ServerError>
+    where
+        F: FnMut(&InstanceStatus) -> bool,
+    {
+        wait_for_status(self.status_watch(), predicate, timeout).await
+    }
 
-        Ok(())
+    pub async fn wait_until_running(&self, timeout: Duration) -> Result<(), ServerError> {
+        self.wait_for(|status| *status == InstanceStatus::Running, timeout)
+            .await
+            .map(|_| ())
     }
 
-    async fn validate_start_parameters(&self) -> Result<(), ServerError> {
-        if self.child.is_some() {
-            return Err(ServerError::AlreadyRunning);
-        }
+    /// Waits until no server process is running and returns how it ended.
+    pub async fn wait_until_stopped(
+        &self,
+        timeout: Duration,
+    ) -> Result<InstanceStatus, ServerError> {
+        self.wait_for(InstanceStatus::is_stopped, timeout).await
+    }
 
-        Ok(())
+    /// Exit details of the most recent process run, if any has exited yet.
+    pub async fn last_exit(&self) -> Option<ExitInfo> {
+        self.runtime.last_exit().await
     }
 
-    async fn transition_status(&self, status: InstanceStatus) {
-        let r_guard = self.status.read().await;
-        let old = r_guard.clone();
-        drop(r_guard);
+    pub async fn startup_timeout(&self) -> Duration {
+        self.runtime.startup_timeout().await
+    }
 
-        let new = status.clone();
+    /// Sets how long `start` waits for the server to finish starting before killing it.
+    pub async fn set_startup_timeout(&self, startup_timeout: Duration) {
+        self.runtime.set_startup_timeout(startup_timeout).await;
+    }
 
-        let mut guard = self.status.write().await;
-        *guard = status;
-        drop(guard);
+    pub async fn launch_profile(&self) -> LaunchProfile {
+        self.runtime.launch_profile().await
+    }
 
-        let event = InstanceEvent {
-            id: Uuid::new_v4(),
+    /// Sets the launch profile used from the next start on.
+    pub async fn set_launch_profile(&self, profile: LaunchProfile) {
+        self.runtime.set_launch_profile(profile).await;
+    }
 
-            timestamp: Utc::now(),
+    #[cfg(feature = "cgroups")]
+    pub async fn cgroup_limits(&self) -> Option<CgroupLimits> {
+        self.runtime.cgroup_limits().await
+    }
 
-            payload: EventPayload::StateChange { old, new },
-        };
+    /// Sets the cgroup limits applied from the next start on; `None` runs the server
+    /// without a cgroup of its own.
+    #[cfg(feature = "cgroups")]
+    pub async fn set_cgroup_limits(&self, limits: Option<CgroupLimits>) {
+        self.runtime.set_cgroup_limits(limits).await;
+    }
 
-        _ = self.internal_events_tx.send(event).await;
+    /// Compiles and installs `rules`, which apply from the next start on.
+    pub async fn set_event_rules(&self, rules: &[EventRule]) -> Result<(), ParserError> {
+        let rules = RuleSet::compile(rules)?;
+        self.runtime.set_rules(rules).await;
+        Ok(())
     }
 
-    fn build_start_command(&self) -> process::Command {
-        let mut command = process::Command::new("java");
-        command
-            .arg("-jar")
-            .arg(&self.data.jar_path)
-            .arg("nogui")
-            .current_dir(&self.data.root_dir)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .stdin(Stdio::piped());
+    pub async fn log_sink_policy(&self) -> LogSinkPolicy {
+        self.log_sink.policy().await
+    }
 
-        command.process_group(0);
-        command
+    /// Sets how the console log is rotated, compressed and pruned.
+    pub async fn set_log_sink_policy(&self, policy: LogSinkPolicy) {
+        self.log_sink.set_policy(policy).await;
     }
 
-    fn spawn_child_process(&self, mut command: process::Command) -> Result<Child, ServerError> {
-        command.spawn().map_err(|_| ServerError::CommandFailed)
+    pub async fn metrics_policy(&self) -> MetricsPolicy {
+        self.metrics.policy().await
     }
 
-    fn setup_stream_pumps(&mut self, mut child: Child) -> Result<(), ServerError> {
-        let stdout = child.stdout.take().ok_or(ServerError::NoStdoutPipe)?;
-        let stderr = child.stderr.take().ok_or(ServerError::NoStderrPipe)?;
-        let stdin = child.stdin.take().ok_or(ServerError::NoStdinPipe)?;
+    /// Sets whether and how often resource usage is sampled; sampling is off by default.
+    pub async fn set_metrics_policy(&self, policy: MetricsPolicy) {
+        self.metrics.set_policy(policy).await;
+    }
 
-        let child = Arc::new(RwLock::new(child));
-        self.child = Some(child);
+    /// Most recent resource usage of the running server, `None` while it is not running.
+    pub fn resource_sample(&self) -> Option<ResourceSample> {
+        self.metrics.subscribe().borrow().clone()
+    }
 
-        let stdout_tx = self.stdout_tx.clone();
-        let stderr_tx = broadcast::Sender::new(2048);
-        self.stderr_tx = Some(stderr_tx.clone());
-        let shutdown = self.shutdown.clone();
+    /// Receiver that sees every resource sample, also published as `ResourceSample` events.
+    pub fn resource_samples(&self) -> watch::Receiver<Option<ResourceSample>> {
+        self.metrics.subscribe()
+    }
 
-        let stdout_status = self.status.clone();
-        let stderr_status = self.status.clone();
-        let internal_tx1 = self.internal_events_tx.clone();
-        let internal_tx2 = self.internal_events_tx.clone();
+    pub async fn health_policy(&self) -> HealthPolicy {
+        self.health.policy().await
+    }
 
-        tokio::spawn(async move {
-            let mut stdout_reader = BufReader::new(stdout).lines();
-            loop {
-                match stdout_reader.next_line().await {
-                    Ok(Some(line)) => {
-                        let _ = stdout_tx.send(InstanceEvent::stdout(line));
-                    }
-                    _ => {
-                        let status_guard = stdout_status.read().await;
-                        let state = status_guard.clone();
-                        if state == InstanceStatus::Running && state == InstanceStatus::Starting {
-                            let old = status_guard.clone();
-                            drop(status_guard);
-                            let mut status = stdout_status.write().await;
-                            *status = InstanceStatus::Crashed;
-                            let event = InstanceEvent {
-                                id: Uuid::new_v4(),
-
-                                timestamp: Utc::now(),
-
-                                payload: EventPayload::StateChange {
-                                    old,
-                                    new: status.clone(),
-                                },
-                            };
-
-                            _ = internal_tx1.send(event).await;
-                            drop(status);
-                            break;
-                        }
-                        drop(status_guard);
-                    }
-                }
-            }
-        });
-
-        tokio::spawn(async move {
-            let mut stderr_reader = BufReader::new(stderr).lines();
-            loop {
-                match stderr_reader.next_line().await {
-                    Ok(Some(line)) => {
-                        let _ = stderr_tx.send(InstanceEvent::stderr(line));
-                    }
-                    _ => {
-                        let status_guard = stderr_status.read().await;
-                        let state = status_guard.clone();
-                        if state == InstanceStatus::Running && state == InstanceStatus::Starting {
-                            let old = status_guard.clone();
-                            drop(status_guard);
-                            let mut status = stderr_status.write().await;
-                            *status = InstanceStatus::Crashed;
-                            let event = InstanceEvent {
-                                id: Uuid::new_v4(),
-
-                                timestamp: Utc::now(),
-
-                                payload: EventPayload::StateChange {
-                                    old,
-                                    new: status.clone(),
-                                },
-                            };
-
-                            _ = internal_tx2.send(event).await;
-                            drop(status);
-                            break;
-                        }
-                        drop(status_guard);
-                    }
-                }
-            }
-        });
+    /// Sets the tick health thresholds, `tick query` polling and the watchdog.
+    pub async fn set_health_policy(&self, policy: HealthPolicy) {
+        self.health.set_policy(policy).await;
+    }
 
-        let mut stdin_rx = self.stdin_rx.take().ok_or(ServerError::NoStdinPipe)?;
+    /// Current tick rate estimate, reset whenever the server starts.
+    pub fn health(&self) -> ServerHealth {
+        self.health.subscribe().borrow().clone()
+    }
 
-        tokio::spawn(async move {
-            let mut writer = BufWriter::new(stdin);
+    /// Receiver that sees every update of the tick rate estimate.
+    pub fn health_watch(&self) -> watch::Receiver<ServerHealth> {
+        self.health.subscribe()
+    }
 
-            loop {
-                tokio::select! {
-                    _ = shutdown.cancelled() => {
-                        break;
-                    }
-                    maybe_cmd = stdin_rx.recv() => {
-                        if let Some(cmd) = maybe_cmd {
-                            _ = writer.write_all(cmd.as_bytes()).await;
-                            _ = writer.flush().await;
-                        }
-                    }
-                }
-            }
-        });
+    pub async fn restart_policy(&self) -> RestartPolicy {
+        self.restart.policy().await
+    }
 
-        Ok(())
+    pub async fn set_restart_policy(&self, policy: RestartPolicy) {
+        self.restart.set_policy(policy).await;
     }
 
-    #[cfg(all(feature = "events", any(feature = "mc-vanilla")))]
+    #[cfg(all(feature = "events", feature = "mc-vanilla"))]
     fn setup_loopback(&mut self) -> Result<(), ServerError> {
-        let shutdown1 = self.shutdown.clone();
+        let shutdown1 = self.runtime.shutdown.clone();
 
-        let event_tx1 = self.events_tx.clone();
+        let event_tx1 = self.runtime.events_sender();
         //internal mpsc to broadcast loopback
         if let Some(mut internal_rx) = self.internal_events_rx.take() {
             tokio::spawn(async move {
@@ -330,7 +360,7 @@ impl InstanceHandle {
 
                         maybe_event = internal_rx.recv() => {
                             if let Some(event) = maybe_event {
-                                _ = tx.send(event);
+                                tx.send(event);
                             }
                         }
                     }
@@ -340,112 +370,179 @@ impl InstanceHandle {
         Ok(())
     }
 
-    #[cfg(all(feature = "events", any(feature = "mc-vanilla")))]
-    fn setup_parser(&mut self) -> Result<(), ServerError> {
-        use crate::config::LogMeta;
+    fn setup_restart_supervisor(&mut self) {
+        if self.restart_task.is_none() {
+            self.restart_task = Some(self.restart.spawn());
+        }
+    }
 
-        let stdout_stream = self
-            .subscribe(StreamSource::Stdout)
-            .map_err(|_| ServerError::NoStdoutPipe)?;
-        let shutdown2 = self.shutdown.clone();
-        let bus_tx = self.internal_bus_tx.clone();
+    fn setup_log_sink(&mut self) {
+        if self.log_sink_task.is_none() {
+            self.log_sink_task = Some(self.log_sink.spawn());
+        }
+    }
 
-        #[cfg(feature = "mc-vanilla")]
-        if self.data.mc_type == MinecraftType::Vanilla {
-            tokio::spawn(async move {
-                let mut rx = stdout_stream;
-                let tx = bus_tx;
+    fn setup_metrics_sampler(&mut self) {
+        if self.metrics_task.is_none() {
+            self.metrics_task = Some(self.metrics.spawn());
+        }
+    }
 
-                loop {
-                    tokio::select! {
-                        _ = shutdown2.cancelled() => {
-                            break;
-                        }
-                        next_line = rx.next() => {
-                            if let Some(Ok(val)) = next_line {
-                                let event_line = match val.payload {
-                                    EventPayload::StdLine{line} => {
-                                        line
-                                    },
-                                    _ => continue,
-                                };
-
-                                let meta = match LogMeta::new(event_line.line) {
-                                    Ok(Some(log_meta)) => {
-                                        log_meta
-                                    },
-                                    _ => continue,
-                                };
-
-                                match meta.parse_event() {
-                                    Ok(Some(event)) => _ = tx.send(event),
-                                    _ => continue,
-                                }
-                            }
-                        }
-                    }
-                }
-            });
+    fn setup_health_monitor(&mut self) {
+        if self.health_task.is_none() {
+            self.health_task = Some(self.health.spawn());
         }
-        Ok(())
+    }
+
+    fn setup_roster_tracker(&mut self) {
+        if self.roster_task.is_none() {
+            self.roster_task = Some(self.roster.spawn());
+        }
+    }
+
+    /// Players currently online, updated as they join and leave.
+    pub fn roster(&self) -> watch::Receiver<PlayerRoster> {
+        self.roster.subscribe()
+    }
+
+    /// Checks the roster against the `list` command and corrects it.
+    pub async fn reconcile_roster(&self) -> Result<PlayerRoster, ServerError> {
+        self.roster.reconcile().await
+    }
+
+    /// Replaces the roster with an externally observed list of online players, such
+    /// as a complete Server List Ping sample.
+    pub async fn reconcile_roster_with(&self, online: Vec<(String, Option<Uuid>)>) -> PlayerRoster {
+        self.roster.apply(online).await
+    }
+
+    /// Playtime totals of all completed sessions on this server.
+    pub async fn playtime(&self) -> Result<PlaytimeLedger, ServerError> {
+        PlaytimeLedger::load(&self.data.root_dir).await
+    }
+
+    /// Console lines and events recorded on disk with `from <= timestamp < to`,
+    /// including those from earlier runs of MineGuard.
+    pub async fn console_history(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<ReceiverStream<LogRecord>, ServerError> {
+        read_logs(&self.data.root_dir, from, to).await
     }
 
     pub async fn kill(&mut self) -> Result<(), ServerError> {
-        if let Some(child_arc) = self.child.clone() {
-            self.transition_status(InstanceStatus::Killing).await;
-            let mut child = child_arc.write().await;
-
-            child.kill().await.map_err(|_| ServerError::CommandFailed)?;
-
-            self.transition_status(InstanceStatus::Killed).await;
-            sleep(Duration::from_secs(1)).await;
-            self.shutdown.cancel();
-            self.child = None;
-            Ok(())
-        } else {
-            Err(ServerError::NotRunning)
+        if self.restart.cancel_pending().await {
+            return Ok(());
         }
+        self.runtime.kill().await
     }
 
     pub async fn stop(&mut self) -> Result<(), ServerError> {
-        if let Some(child_arc) = self.child.clone() {
-            self.transition_status(InstanceStatus::Stopping).await;
-
-            _ = self.send_command("stop").await;
-            let mut child = child_arc.write().await;
-            child.wait().await.map_err(|_| ServerError::CommandFailed)?;
-
-            self.transition_status(InstanceStatus::Stopped).await;
-            sleep(Duration::from_secs(1)).await;
-            self.shutdown.cancel();
-            self.child = None;
-            Ok(())
-        } else {
-            Err(ServerError::NotRunning)
+        if self.restart.cancel_pending().await {
+            return Ok(());
         }
+        self.runtime.stop().await
+    }
+
+    /// Sends `stop` and waits up to `graceful` for the server to exit, then sends SIGTERM
+    /// to its process group and waits up to `terminate` before killing it.
+    pub async fn stop_with_timeout(
+        &mut self,
+        graceful: Duration,
+        terminate: Duration,
+    ) -> Result<StopOutcome, ServerError> {
+        if self.restart.cancel_pending().await {
+            return Ok(StopOutcome::Graceful);
+        }
+        self.runtime.stop_with_timeout(graceful, terminate).await
     }
 
     pub fn subscribe(
         &self,
         stream: StreamSource,
     ) -> Result<BroadcastStream<InstanceEvent>, SubscribeError> {
-        match stream {
-            StreamSource::Stdout => {
-                let rx = self.stdout_tx.subscribe();
-                Ok(BroadcastStream::new(rx))
-            }
-            StreamSource::Stderr => {
-                let rx = match &self.stderr_tx {
-                    Some(value) => value.subscribe(),
-                    None => return Err(SubscribeError::NoStderr),
-                };
-                Ok(BroadcastStream::new(rx))
-            }
-            #[cfg(feature = "events")]
-            StreamSource::Event => {
-                let rx = self.events_tx.subscribe();
-                Ok(BroadcastStream::new(rx))
-            }
+        self.runtime.subscribe(stream)
+    }
+
+    /// Yields up to `n` of the most recent events of `stream`, then the live ones,
+    /// without gaps or duplicates between the two.
+    pub fn subscribe_with_backlog(
+        &self,
+        stream: StreamSource,
+        n: usize,
+    ) -> Result<BacklogStream, SubscribeError> {
+        self.runtime.subscribe_with_backlog(stream, n)
+    }
+
+    /// Yields every event of `stream` from sequence number `seq` on, starting with the
+    /// backlog. Events no longer kept are reported as one `StreamError::Lagged`.
+    pub fn resume_from(
+        &self,
+        stream: StreamSource,
+        seq: u64,
+    ) -> Result<BacklogStream, SubscribeError> {
+        self.runtime.resume_from(stream, seq)
+    }
+
+    /// Sets how many recent events each stream keeps for `subscribe_with_backlog`.
+    pub fn set_backlog_policy(&self, policy: BacklogPolicy) {
+        self.runtime.set_backlog_policy(policy);
+    }
+}
+
+impl Drop for InstanceHandle {
+    fn drop(&mut self) {
+        self.runtime.shutdown.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::instance::testing::TempRoot;
+
+    fn config(root: &TempRoot, rules: Vec<EventRule>) -> MineGuardConfig {
+        fs::write(root.0.join("server.jar"), b"").unwrap();
+        let mut config = MineGuardConfig::new();
+        config.server_dir = root.0.clone();
+        config.jar_path = "server.jar".into();
+        config.event_rules = rules;
+        config
+    }
+
+    #[tokio::test]
+    async fn loading_a_config_compiles_its_rules() {
+        let root = TempRoot::new("handle-rules-valid");
+        let rules = vec![EventRule::new("saved", r"^Saved the game$").with_thread("^Server")];
+
+        assert!(InstanceHandle::new_with_config(config(&root, rules)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn loading_a_config_rejects_invalid_rules() {
+        let root = TempRoot::new("handle-rules-invalid");
+        let rules = vec![
+            EventRule::new("saved", r"^Saved the game$"),
+            EventRule::new("broken", r"(?P<player>\w+"),
+        ];
+
+        match InstanceHandle::new_with_config(config(&root, rules)) {
+            Err(HandleError::InvalidRule(error)) => assert!(error.contains("broken"), "{error}"),
+            other => panic!("expected an invalid rule, got {other:?}"),
         }
     }
+
+    #[tokio::test]
+    async fn replacing_rules_rejects_invalid_ones() {
+        let root = TempRoot::new("handle-rules-replace");
+        let handle = InstanceHandle::new_with_config(config(&root, Vec::new())).unwrap();
+
+        let result = handle
+            .set_event_rules(&[EventRule::new("saved", "ok").with_thread("(")])
+            .await;
+        assert!(matches!(result, Err(ParserError::InvalidRule { name, .. }) if name == "saved"));
+    }
 }
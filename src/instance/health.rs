@@ -0,0 +1,252 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tokio::{
+    sync::{RwLock, watch},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior, interval},
+};
+use tokio_stream::StreamExt;
+
+use crate::{
+    config::{HealthPolicy, StreamSource, stream::EventPayload},
+    parser::parse_tick_query,
+};
+
+use super::{CaptureOptions, InstanceStatus, restart::RestartSupervisor, runtime::InstanceRuntime};
+
+/// Milliseconds per tick at the vanilla target of 20 TPS.
+const TARGET_MSPT: f64 = 50.0;
+/// Vanilla warns about lag at most every 15 seconds, which is 300 ticks.
+const LAG_WARNING_TICKS: f64 = 300.0;
+/// How often expired measurements are dropped and polls are checked for being due.
+const EVALUATE_INTERVAL: Duration = Duration::from_secs(1);
+/// Time a hung server gets to stop, and then to exit on SIGTERM, before a watchdog
+/// restart kills it.
+const WATCHDOG_STOP_GRACE: Duration = Duration::from_secs(10);
+
+/// Rolling tick rate estimate of a running server.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
+pub struct ServerHealth {
+    /// Average milliseconds per tick over the measurements in the window, `None`
+    /// without any.
+    pub mspt: Option<f64>,
+    /// Ticks per second implied by `mspt`, at most 20.
+    pub tps: Option<f64>,
+    pub degraded: bool,
+    /// Number of measurements the estimate is based on.
+    pub samples: usize,
+    /// When the server last answered a `tick query` or watchdog probe.
+    pub last_response: Option<DateTime<Utc>>,
+}
+
+/// Keeps the `ServerHealth` of an instance from lag warnings and optional `tick query`
+/// polls, and restarts servers that stop answering when the watchdog is enabled.
+#[derive(Debug, Clone)]
+pub(crate) struct HealthMonitor {
+    runtime: InstanceRuntime,
+    restart: RestartSupervisor,
+    policy: Arc<RwLock<HealthPolicy>>,
+    health_tx: Arc<watch::Sender<ServerHealth>>,
+    probed_tx: Arc<watch::Sender<Option<Instant>>>,
+}
+
+/// Measurements in milliseconds per tick, oldest first.
+type Samples = VecDeque<(Instant, f64)>;
+
+impl HealthMonitor {
+    pub(crate) fn new(
+        runtime: InstanceRuntime,
+        restart: RestartSupervisor,
+        policy: HealthPolicy,
+    ) -> Self {
+        Self {
+            runtime,
+            restart,
+            policy: Arc::new(RwLock::new(policy)),
+            health_tx: Arc::new(watch::Sender::new(ServerHealth::default())),
+            probed_tx: Arc::new(watch::Sender::new(None)),
+        }
+    }
+
+    pub(crate) async fn policy(&self) -> HealthPolicy {
+        self.policy.read().await.clone()
+    }
+
+    pub(crate) async fn set_policy(&self, policy: HealthPolicy) {
+        *self.policy.write().await = policy;
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<ServerHealth> {
+        self.health_tx.subscribe()
+    }
+
+    /// When the watchdog last sent `list` to the console.
+    pub(crate) fn probes(&self) -> watch::Receiver<Option<Instant>> {
+        self.probed_tx.subscribe()
+    }
+
+    pub(crate) fn spawn(&self) -> JoinHandle<()> {
+        let monitor = self.clone();
+        tokio::spawn(async move { monitor.run().await })
+    }
+
+    async fn run(self) {
+        let Ok(mut events) = self.runtime.subscribe(StreamSource::Event) else {
+            return;
+        };
+        let shutdown = self.runtime.shutdown.clone();
+
+        let mut evaluate = interval(EVALUATE_INTERVAL);
+        evaluate.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut samples = Samples::new();
+        let mut last_query = Instant::now();
+        let mut last_probe = Instant::now();
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = evaluate.tick() => {}
+                next = events.next() => match next {
+                    Some(Ok(event)) => match event.payload {
+                        EventPayload::LagWarning { behind, .. } => {
+                            // Falling `behind` further over the ticks since the last warning.
+                            let mspt = TARGET_MSPT + behind.as_secs_f64() * 1000.0 / LAG_WARNING_TICKS;
+                            samples.push_back((Instant::now(), mspt));
+                        }
+                        EventPayload::StateChange { new: InstanceStatus::Starting, .. } => {
+                            samples.clear();
+                            self.health_tx.send_replace(ServerHealth::default());
+                            last_query = Instant::now();
+                            last_probe = Instant::now();
+                            continue;
+                        }
+                        _ => continue,
+                    },
+                    Some(Err(_)) => continue,
+                    None => break,
+                },
+            }
+
+            let policy = self.policy().await;
+            if !policy.enabled || self.runtime.status() != InstanceStatus::Running {
+                continue;
+            }
+
+            if let Some(every) = policy.tick_query_interval()
+                && last_query.elapsed() >= every
+            {
+                last_query = Instant::now();
+                if let Some(mspt) = self.query_mspt().await {
+                    samples.push_back((Instant::now(), mspt));
+                    self.responded();
+                }
+            }
+
+            if let Some(limit) = policy.watchdog_timeout()
+                && last_probe.elapsed() >= policy.watchdog_interval()
+            {
+                if self.probe(limit).await {
+                    self.responded();
+                } else {
+                    self.restart_unresponsive(limit).await;
+                }
+                last_probe = Instant::now();
+            }
+
+            self.evaluate(&policy, &mut samples).await;
+        }
+    }
+
+    /// Updates the estimate and reports crossing a threshold.
+    async fn evaluate(&self, policy: &HealthPolicy, samples: &mut Samples) {
+        while samples
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() > policy.window())
+        {
+            samples.pop_front();
+        }
+        let mspt = (!samples.is_empty())
+            .then(|| samples.iter().map(|(_, mspt)| mspt).sum::<f64>() / samples.len() as f64);
+
+        let mut change = None;
+        self.health_tx.send_modify(|health| {
+            // Separate thresholds keep the state from flapping around a single one.
+            let degraded = match mspt {
+                Some(mspt) if health.degraded => mspt >= policy.recovered_mspt,
+                Some(mspt) => mspt >= policy.degraded_mspt,
+                None => false,
+            };
+
+            health.mspt = mspt;
+            health.tps = mspt.map(|mspt| (1000.0 / mspt).min(1000.0 / TARGET_MSPT));
+            health.samples = samples.len();
+            if degraded != health.degraded {
+                health.degraded = degraded;
+                change = Some(health.clone());
+            }
+        });
+
+        let payload = match change {
+            Some(health) if health.degraded => EventPayload::HealthDegraded { health },
+            Some(health) => EventPayload::HealthRecovered { health },
+            None => return,
+        };
+        self.runtime.emit(payload).await;
+    }
+
+    fn responded(&self) {
+        self.health_tx
+            .send_modify(|health| health.last_response = Some(Utc::now()));
+    }
+
+    async fn query_mspt(&self) -> Option<f64> {
+        let options = CaptureOptions::default().until(|line| parse_tick_query(line).is_some());
+        let events = self
+            .runtime
+            .execute_and_capture("tick query".to_string(), &options)
+            .await
+            .ok()?;
+
+        events.iter().find_map(|event| match &event.payload {
+            EventPayload::StdLine { line } => parse_tick_query(&line.line),
+            _ => None,
+        })
+    }
+
+    /// Whether the server answers `list` within `limit`; only its main thread does.
+    async fn probe(&self, limit: Duration) -> bool {
+        let options = CaptureOptions::default()
+            .timeout(limit)
+            .until(|line| line.contains("players online"));
+        self.probed_tx.send_replace(Some(Instant::now()));
+        self.runtime
+            .execute_and_capture("list".to_string(), &options)
+            .await
+            .is_ok()
+    }
+
+    /// Stops the hung server and leaves relaunching it to the restart supervisor, so a
+    /// server that hangs on every run is given up on like one that keeps crashing.
+    async fn restart_unresponsive(&self, unresponsive: Duration) {
+        self.runtime
+            .emit(EventPayload::WatchdogRestart { unresponsive })
+            .await;
+
+        // Stopping may take both grace periods, during which this loop keeps running.
+        let runtime = self.runtime.clone();
+        let restart = self.restart.clone();
+        tokio::spawn(async move {
+            // A failed stop means the server exited or was stopped meanwhile, which the
+            // supervisor or the caller stopping it already handles.
+            if runtime
+                .stop_with_timeout(WATCHDOG_STOP_GRACE, WATCHDOG_STOP_GRACE)
+                .await
+                .is_ok()
+            {
+                restart.request_restart();
+            }
+        });
+    }
+}
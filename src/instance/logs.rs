@@ -0,0 +1,608 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{RwLock, mpsc},
+    task::{JoinHandle, spawn_blocking},
+};
+use tokio_stream::{
+    Stream, StreamExt,
+    wrappers::{ReceiverStream, errors::BroadcastStreamRecvError},
+};
+
+use crate::{
+    config::{
+        LogSinkPolicy, StreamSource,
+        stream::{EventPayload, InstanceEvent},
+    },
+    error::ServerError,
+};
+
+#[cfg(feature = "wire")]
+use crate::error::WireError;
+
+use super::runtime::InstanceRuntime;
+
+/// Directory inside the server directory holding the console log segments.
+pub const LOG_DIR: &str = ".mineguard/logs";
+
+const SEGMENT_PREFIX: &str = "console-";
+
+/// One line of console output or one event, as written to the console log.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LogRecord {
+    /// Timestamp of the event: for a console line the time it was logged at when the
+    /// line carries one, otherwise when MineGuard received it.
+    pub timestamp: DateTime<Utc>,
+    pub source: StreamSource,
+    /// The console line, or for an `Event` record the event in its wire JSON format;
+    /// without the `wire` feature events are only described, for reading.
+    pub text: String,
+}
+
+impl LogRecord {
+    /// The event an `Event` record was written from, `None` for console lines.
+    #[cfg(feature = "wire")]
+    pub fn event(&self) -> Option<Result<InstanceEvent, WireError>> {
+        (self.source == StreamSource::Event).then(|| InstanceEvent::from_json(&self.text))
+    }
+}
+
+#[cfg(feature = "wire")]
+fn event_text(event: &InstanceEvent) -> String {
+    event
+        .to_json()
+        .unwrap_or_else(|_| format!("{:?}", event.payload))
+}
+
+#[cfg(not(feature = "wire"))]
+fn event_text(event: &InstanceEvent) -> String {
+    format!("{:?}", event.payload)
+}
+
+/// A segment file named `console-<date>.<index>.log`, gzipped when it ends in `.gz`.
+#[derive(Debug, Clone)]
+struct Segment {
+    path: PathBuf,
+    date: NaiveDate,
+    index: u32,
+    compressed: bool,
+    size: u64,
+}
+
+impl Segment {
+    fn parse(path: PathBuf, size: u64) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let (stem, compressed) = match name.strip_suffix(".gz") {
+            Some(stem) => (stem, true),
+            None => (name, false),
+        };
+        let (date, index) = stem
+            .strip_prefix(SEGMENT_PREFIX)?
+            .strip_suffix(".log")?
+            .rsplit_once('.')?;
+
+        Some(Self {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?,
+            index: index.parse().ok()?,
+            path,
+            compressed,
+            size,
+        })
+    }
+
+    fn file_name(date: NaiveDate, index: u32) -> String {
+        format!("{SEGMENT_PREFIX}{}.{index}.log", date.format("%Y-%m-%d"))
+    }
+}
+
+/// Segments in `dir` from oldest to newest.
+fn list_segments(dir: &Path) -> io::Result<Vec<Segment>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut segments = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let size = entry.metadata()?.len();
+        segments.extend(Segment::parse(entry.path(), size));
+    }
+    segments.sort_by_key(|s| (s.date, s.index));
+    Ok(segments)
+}
+
+fn compress(segment: &Segment) -> io::Result<()> {
+    let mut gz_path = segment.path.clone().into_os_string();
+    gz_path.push(".gz");
+    let mut part_path = gz_path.clone();
+    part_path.push(".part");
+
+    let mut encoder = GzEncoder::new(File::create(&part_path)?, Compression::default());
+    io::copy(&mut File::open(&segment.path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+
+    fs::rename(&part_path, &gz_path)?;
+    fs::remove_file(&segment.path)
+}
+
+/// Removes the `.gz.part` files of compressions interrupted by a crash; the segments
+/// they were made from are still there.
+fn remove_partial_compressions(dir: &Path) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(SEGMENT_PREFIX) && name.ends_with(".gz.part"))
+        {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Compresses closed segments and deletes the ones the policy no longer keeps.
+/// The segment at `active` is never touched.
+fn maintain(dir: &Path, active: &Path, policy: &LogSinkPolicy) -> io::Result<()> {
+    let mut segments = list_segments(dir)?;
+
+    if policy.compress {
+        for segment in segments
+            .iter()
+            .filter(|s| !s.compressed && s.path != active)
+        {
+            compress(segment)?;
+        }
+        segments = list_segments(dir)?;
+    }
+
+    if let Some(days) = policy.retention_days {
+        let cutoff = Utc::now().date_naive() - chrono::Days::new(days.into());
+        for segment in segments
+            .iter()
+            .filter(|s| s.date < cutoff && s.path != active)
+        {
+            fs::remove_file(&segment.path)?;
+        }
+        segments.retain(|s| s.date >= cutoff || s.path == active);
+    }
+
+    if let Some(max) = policy.max_total_bytes {
+        let mut total: u64 = segments.iter().map(|s| s.size).sum();
+        for segment in segments.iter().filter(|s| s.path != active) {
+            if total <= max {
+                break;
+            }
+            fs::remove_file(&segment.path)?;
+            total -= segment.size;
+        }
+    }
+
+    Ok(())
+}
+
+struct ActiveSegment {
+    file: File,
+    path: PathBuf,
+    date: NaiveDate,
+    size: u64,
+}
+
+/// Appends records to the active segment, rotating and pruning segments as the
+/// policy says. All file access happens on a blocking thread.
+struct SegmentWriter {
+    dir: PathBuf,
+    active: Option<ActiveSegment>,
+}
+
+impl SegmentWriter {
+    /// Appends `record`. Segments are maintained whenever a new one is opened, and a
+    /// failure to do so is returned once the record has been written.
+    fn write(&mut self, record: &LogRecord, policy: &LogSinkPolicy) -> io::Result<()> {
+        let date = record.timestamp.date_naive();
+        if self.active.as_ref().is_some_and(|segment| {
+            segment.size >= policy.max_segment_bytes
+                || (policy.rotate_daily && segment.date != date)
+        }) {
+            self.active = None;
+        }
+
+        let opened = self.active.is_none();
+        let segment = match self.active.as_mut() {
+            Some(segment) => segment,
+            None => self.active.insert(open_segment(&self.dir, date)?),
+        };
+
+        let mut json = serde_json::to_vec(record).map_err(io::Error::other)?;
+        json.push(b'\n');
+        segment.file.write_all(&json)?;
+        segment.size += json.len() as u64;
+
+        if opened {
+            // Also picks up segments left uncompressed by an earlier run.
+            maintain(&self.dir, &segment.path, policy)?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens a new segment for `date` after the last existing one.
+fn open_segment(dir: &Path, date: NaiveDate) -> io::Result<ActiveSegment> {
+    fs::create_dir_all(dir)?;
+
+    let index = list_segments(dir)?
+        .iter()
+        .filter(|s| s.date == date)
+        .map(|s| s.index + 1)
+        .max()
+        .unwrap_or(0);
+
+    let path = dir.join(Segment::file_name(date, index));
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+    Ok(ActiveSegment {
+        file,
+        path,
+        date,
+        size: 0,
+    })
+}
+
+/// Writes every stdout, stderr and event line of an instance to segment files in
+/// `LOG_DIR`, so console history survives a restart of MineGuard.
+#[derive(Debug, Clone)]
+pub(crate) struct LogSink {
+    runtime: InstanceRuntime,
+    policy: Arc<RwLock<LogSinkPolicy>>,
+}
+
+impl LogSink {
+    pub(crate) fn new(runtime: InstanceRuntime, policy: LogSinkPolicy) -> Self {
+        Self {
+            runtime,
+            policy: Arc::new(RwLock::new(policy)),
+        }
+    }
+
+    pub(crate) async fn policy(&self) -> LogSinkPolicy {
+        self.policy.read().await.clone()
+    }
+
+    pub(crate) async fn set_policy(&self, policy: LogSinkPolicy) {
+        *self.policy.write().await = policy;
+    }
+
+    /// Subscribes before returning, so nothing sent after the call is missed.
+    pub(crate) fn spawn(&self) -> JoinHandle<()> {
+        let subscribe = |source: StreamSource| {
+            self.runtime
+                .subscribe(source.clone())
+                .map(|stream| stream.map(move |event| (source.clone(), event)))
+        };
+        let streams = (
+            subscribe(StreamSource::Stdout),
+            subscribe(StreamSource::Stderr),
+            subscribe(StreamSource::Event),
+        );
+
+        let sink = self.clone();
+        tokio::spawn(async move {
+            if let (Ok(stdout), Ok(stderr), Ok(events)) = streams {
+                sink.run(stdout.merge(stderr).merge(events)).await;
+            }
+        })
+    }
+
+    async fn run<S>(self, mut lines: S)
+    where
+        S: Stream<
+                Item = (
+                    StreamSource,
+                    Result<InstanceEvent, BroadcastStreamRecvError>,
+                ),
+            > + Unpin,
+    {
+        let shutdown = self.runtime.shutdown.clone();
+        let (tx, mut rx) = mpsc::channel::<(LogRecord, LogSinkPolicy)>(1024);
+        let (failures_tx, mut failures) = mpsc::unbounded_channel::<String>();
+
+        let mut writer = SegmentWriter {
+            dir: self.runtime.data.root_dir.join(LOG_DIR),
+            active: None,
+        };
+        spawn_blocking(move || {
+            // The event reporting a failure is logged too, so a failure is only reported
+            // again once a write succeeded in between.
+            let mut failing = false;
+            let mut report = |result: io::Result<()>| match result {
+                Ok(()) => failing = false,
+                Err(err) => {
+                    if !failing {
+                        _ = failures_tx.send(err.to_string());
+                    }
+                    failing = true;
+                }
+            };
+
+            report(remove_partial_compressions(&writer.dir));
+            while let Some((record, policy)) = rx.blocking_recv() {
+                report(writer.write(&record, &policy));
+            }
+        });
+
+        loop {
+            let (source, next) = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                Some(error) = failures.recv() => {
+                    self.runtime.emit(EventPayload::LogSinkFailed { error }).await;
+                    continue;
+                }
+                next = lines.next() => match next {
+                    Some(next) => next,
+                    None => break,
+                },
+            };
+
+            let policy = self.policy().await;
+            if !policy.enabled {
+                continue;
+            }
+
+            let (source, event) = match next {
+                Ok(event) => (source, event),
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    let lagged = InstanceEvent::new(EventPayload::StreamLagged {
+                        stream: source,
+                        missed,
+                    });
+                    (StreamSource::Event, lagged)
+                }
+            };
+            let text = match &event.payload {
+                EventPayload::StdLine { line } => line.line.clone(),
+                // Samples would crowd out the console lines.
+                EventPayload::ResourceSample { .. } => continue,
+                _ => event_text(&event),
+            };
+            let record = LogRecord {
+                timestamp: event.timestamp,
+                source,
+                text,
+            };
+
+            if tx.send((record, policy)).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Streams the records of the console log in `root_dir` with `from <= timestamp < to`,
+/// oldest first, reading compressed segments transparently.
+pub(crate) async fn read_logs(
+    root_dir: &Path,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<ReceiverStream<LogRecord>, ServerError> {
+    let dir = root_dir.join(LOG_DIR);
+    let segments = spawn_blocking(move || list_segments(&dir))
+        .await
+        .map_err(|_| ServerError::FileIO)?
+        .map_err(|_| ServerError::FileIO)?;
+
+    // A segment holds records from its own date until the date of the next one.
+    let (first, last) = (from.date_naive(), to.date_naive());
+    let selected: Vec<Segment> = segments
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| {
+            s.date <= last && segments.get(i + 1).is_none_or(|next| next.date >= first)
+        })
+        .map(|(_, s)| s.clone())
+        .collect();
+
+    let (tx, rx) = mpsc::channel(256);
+    spawn_blocking(move || {
+        for segment in selected {
+            let Ok(file) = File::open(&segment.path) else {
+                continue;
+            };
+            let reader: Box<dyn Read> = if segment.compressed {
+                Box::new(GzDecoder::new(file))
+            } else {
+                Box::new(file)
+            };
+
+            // A partially written last line of the active segment fails to parse and is skipped.
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let Ok(record) = serde_json::from_str::<LogRecord>(&line) else {
+                    continue;
+                };
+                if record.timestamp >= from
+                    && record.timestamp < to
+                    && tx.blocking_send(record).is_err()
+                {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(ReceiverStream::new(rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration as StdDuration;
+
+    use chrono::Duration;
+    use tokio::time::{sleep, timeout};
+    use uuid::Uuid;
+
+    use crate::{
+        instance::testing::{self, TempRoot},
+        server::domain::MineGuardConfig,
+    };
+
+    use super::*;
+
+    fn record(source: StreamSource, text: String) -> LogRecord {
+        LogRecord {
+            timestamp: Utc::now(),
+            source,
+            text,
+        }
+    }
+
+    /// Records written through rotated, compressed segments as the sink does, and read
+    /// back with `read_logs`.
+    async fn write_and_read(name: &str, records: &[LogRecord]) -> Vec<LogRecord> {
+        let root =
+            std::env::temp_dir().join(format!("mineguard-logs-{}-{}", name, std::process::id()));
+        _ = fs::remove_dir_all(&root);
+        let policy = LogSinkPolicy {
+            max_segment_bytes: 1,
+            ..LogSinkPolicy::default()
+        };
+
+        let mut writer = SegmentWriter {
+            dir: root.join(LOG_DIR),
+            active: None,
+        };
+        for record in records {
+            writer.write(record, &policy).unwrap();
+        }
+        drop(writer);
+
+        let from = Utc::now() - Duration::hours(1);
+        let read = read_logs(&root, from, Utc::now() + Duration::hours(1))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        _ = fs::remove_dir_all(&root);
+        read
+    }
+
+    #[tokio::test]
+    async fn console_lines_read_back_unchanged() {
+        let records = [
+            record(
+                StreamSource::Stdout,
+                "[12:00:00] [Server thread/INFO]: Done".to_string(),
+            ),
+            record(
+                StreamSource::Stderr,
+                "Exception in thread \"main\"".to_string(),
+            ),
+        ];
+        assert_eq!(write_and_read("console", &records).await, records);
+    }
+
+    #[cfg(feature = "wire")]
+    #[tokio::test]
+    async fn events_read_back_as_events() {
+        let joined = InstanceEvent::new(EventPayload::PlayerJoined {
+            name: "Notch".to_string(),
+            uuid: None,
+            ip: Some("10.0.0.5".to_string()),
+        });
+        let lagged = InstanceEvent::new(EventPayload::StreamLagged {
+            stream: StreamSource::Stdout,
+            missed: 12,
+        });
+        let records = [
+            record(StreamSource::Stdout, "line".to_string()),
+            record(StreamSource::Event, event_text(&joined)),
+            record(StreamSource::Event, event_text(&lagged)),
+        ];
+
+        let read = write_and_read("events", &records).await;
+        assert_eq!(read.len(), 3);
+        assert!(read[0].event().is_none());
+        assert_eq!(read[1].event().unwrap().unwrap(), joined);
+        assert_eq!(read[2].event().unwrap().unwrap(), lagged);
+    }
+
+    /// A sink writing the events of a never launched runtime in `root`.
+    fn sink(root: &TempRoot) -> InstanceRuntime {
+        let runtime = testing::runtime(&root.0, &MineGuardConfig::new());
+        LogSink::new(runtime.clone(), LogSinkPolicy::default()).spawn();
+        runtime
+    }
+
+    #[tokio::test]
+    async fn records_keep_the_event_timestamp() {
+        let root = TempRoot::new("logs-timestamp");
+        let dir = root.0.join(LOG_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        let part = dir.join("console-2024-06-01.0.log.gz.part");
+        fs::write(&part, "partial").unwrap();
+
+        let runtime = sink(&root);
+        let event = InstanceEvent {
+            id: Uuid::new_v4(),
+            seq: 0,
+            timestamp: Utc::now() - Duration::days(2),
+            payload: EventPayload::WorldSaved,
+        };
+        runtime.publish(event.clone()).await;
+
+        let from = event.timestamp - Duration::hours(1);
+        let record = timeout(StdDuration::from_secs(5), async {
+            loop {
+                let mut records = read_logs(&root.0, from, Utc::now()).await.unwrap();
+                if let Some(record) = records.next().await {
+                    return record;
+                }
+                sleep(StdDuration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("event never written");
+
+        assert_eq!(record.timestamp, event.timestamp);
+        assert!(!part.exists(), "stale .gz.part file left behind");
+    }
+
+    #[tokio::test]
+    async fn write_failures_are_reported_once() {
+        let root = TempRoot::new("logs-failure");
+        // The log directory cannot be created below a file.
+        fs::write(root.0.join(".mineguard"), "").unwrap();
+        let runtime = testing::runtime(&root.0, &MineGuardConfig::new());
+        let mut events = runtime.subscribe(StreamSource::Event).unwrap();
+        LogSink::new(runtime.clone(), LogSinkPolicy::default()).spawn();
+
+        for _ in 0..3 {
+            runtime.emit(EventPayload::WorldSaved).await;
+        }
+
+        let mut failures = 0;
+        while let Ok(Some(event)) = timeout(StdDuration::from_millis(500), events.next()).await {
+            if let EventPayload::LogSinkFailed { .. } = event.unwrap().payload {
+                failures += 1;
+            }
+        }
+        assert_eq!(failures, 1);
+    }
+}
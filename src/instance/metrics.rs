@@ -0,0 +1,339 @@
+use std::{collections::HashMap, fs, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use tokio::{
+    sync::{RwLock, watch},
+    task::{JoinHandle, spawn_blocking},
+    time::{Instant, sleep},
+};
+
+use crate::config::{MetricsPolicy, stream::EventPayload};
+
+use super::{
+    procfs::{self, ProcStat},
+    runtime::InstanceRuntime,
+};
+
+/// Resource usage of the server process group at one point in time.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
+pub struct ResourceSample {
+    pub timestamp: DateTime<Utc>,
+    /// Processes in the group: the JVM and anything it started.
+    pub processes: u32,
+    /// CPU time used since the previous sample, where 100.0 is one fully used core.
+    /// `None` for the first sample of a run.
+    pub cpu_percent: Option<f64>,
+    /// Resident memory, from `VmRSS`.
+    pub rss_bytes: u64,
+    pub threads: u64,
+    pub open_fds: u64,
+    /// Bytes read from storage by the running processes; `None` when `/proc/<pid>/io`
+    /// is not readable.
+    pub read_bytes: Option<u64>,
+    /// Bytes written to storage by the running processes.
+    pub write_bytes: Option<u64>,
+}
+
+/// CPU ticks of every process seen in the previous sample, keyed by pid and start
+/// time so that a reused pid is not confused with an earlier process.
+#[derive(Debug, Default)]
+struct CpuTicks {
+    taken_at: Option<Instant>,
+    ticks: HashMap<(u32, u64), u64>,
+}
+
+/// Reads the usage of every process in the group led by `pgid`, or `None` when the
+/// group no longer has any.
+fn collect(pgid: u32, previous: &mut CpuTicks) -> Option<ResourceSample> {
+    let now = Instant::now();
+    let mut current = HashMap::new();
+    let mut used_ticks = 0;
+    let mut sample = ResourceSample {
+        timestamp: Utc::now(),
+        processes: 0,
+        cpu_percent: None,
+        rss_bytes: 0,
+        threads: 0,
+        open_fds: 0,
+        read_bytes: None,
+        write_bytes: None,
+    };
+
+    for pid in procfs::pids() {
+        let Some(stat) = ProcStat::read(pid).filter(|stat| stat.pgrp == pgid) else {
+            continue;
+        };
+        if !stat.is_running() {
+            continue;
+        }
+
+        // A process missing from the previous sample started after it.
+        let key = (pid, stat.start_time);
+        let before = previous.ticks.get(&key).copied().unwrap_or(0);
+        used_ticks += stat.cpu_ticks.saturating_sub(before);
+        current.insert(key, stat.cpu_ticks);
+
+        sample.processes += 1;
+        sample.threads += stat.threads;
+        if let Ok(status) = fs::read_to_string(format!("/proc/{pid}/status")) {
+            sample.rss_bytes += procfs::field(&status, "VmRSS").unwrap_or(0) * 1024;
+        }
+        if let Ok(fds) = fs::read_dir(format!("/proc/{pid}/fd")) {
+            sample.open_fds += fds.count() as u64;
+        }
+        if let Ok(io) = fs::read_to_string(format!("/proc/{pid}/io")) {
+            if let Some(read) = procfs::field(&io, "read_bytes") {
+                *sample.read_bytes.get_or_insert(0) += read;
+            }
+            if let Some(written) = procfs::field(&io, "write_bytes") {
+                *sample.write_bytes.get_or_insert(0) += written;
+            }
+        }
+    }
+
+    if let Some(taken_at) = previous.taken_at {
+        let elapsed = now.duration_since(taken_at).as_secs_f64();
+        if elapsed > 0.0 {
+            let seconds = used_ticks as f64 / procfs::clock_ticks() as f64;
+            sample.cpu_percent = Some(seconds / elapsed * 100.0);
+        }
+    }
+    *previous = CpuTicks {
+        taken_at: Some(now),
+        ticks: current,
+    };
+
+    (sample.processes > 0).then_some(sample)
+}
+
+/// Samples the server process group from `/proc` while it runs and publishes each
+/// sample as a `ResourceSample` event.
+#[derive(Debug, Clone)]
+pub(crate) struct MetricsSampler {
+    runtime: InstanceRuntime,
+    policy: Arc<RwLock<MetricsPolicy>>,
+    latest_tx: Arc<watch::Sender<Option<ResourceSample>>>,
+}
+
+impl MetricsSampler {
+    pub(crate) fn new(runtime: InstanceRuntime, policy: MetricsPolicy) -> Self {
+        Self {
+            runtime,
+            policy: Arc::new(RwLock::new(policy)),
+            latest_tx: Arc::new(watch::Sender::new(None)),
+        }
+    }
+
+    pub(crate) async fn policy(&self) -> MetricsPolicy {
+        self.policy.read().await.clone()
+    }
+
+    /// Applies from the next sample on.
+    pub(crate) async fn set_policy(&self, policy: MetricsPolicy) {
+        *self.policy.write().await = policy;
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Option<ResourceSample>> {
+        self.latest_tx.subscribe()
+    }
+
+    pub(crate) fn spawn(&self) -> JoinHandle<()> {
+        let sampler = self.clone();
+        tokio::spawn(async move { sampler.run().await })
+    }
+
+    async fn run(self) {
+        let shutdown = self.runtime.shutdown.clone();
+        let mut sampled_pid = None;
+        let mut cpu = CpuTicks::default();
+
+        loop {
+            let policy = self.policy().await;
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = sleep(policy.interval()) => {}
+            }
+
+            let pid = self.runtime.pid().await;
+            if pid != sampled_pid {
+                sampled_pid = pid;
+                cpu = CpuTicks::default();
+            }
+            let Some(pid) = pid.filter(|_| policy.enabled) else {
+                // The last sample of an exited run no longer describes the server.
+                self.latest_tx
+                    .send_if_modified(|latest| latest.take().is_some());
+                continue;
+            };
+
+            let Ok((sample, ticks)) = spawn_blocking(move || {
+                let sample = collect(pid, &mut cpu);
+                (sample, cpu)
+            })
+            .await
+            else {
+                break;
+            };
+            cpu = ticks;
+
+            if let Some(sample) = sample {
+                self.latest_tx.send_replace(Some(sample.clone()));
+                self.runtime
+                    .emit(EventPayload::ResourceSample { sample })
+                    .await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::process::CommandExt,
+        process::{Child, Command},
+        thread,
+        time::Duration,
+    };
+
+    use crate::instance::testing::{self, DONE_LINE, TempRoot};
+
+    use super::*;
+
+    /// A shell leading its own process group with two children, like a JVM with helpers.
+    fn spawn_group() -> Child {
+        spawn_shell("sleep 30 & sleep 30 & wait")
+    }
+
+    fn spawn_shell(script: &str) -> Child {
+        Command::new("sh")
+            .args(["-c", script])
+            .process_group(0)
+            .spawn()
+            .unwrap()
+    }
+
+    fn kill_group(mut child: Child) {
+        // SAFETY: kill has no memory safety preconditions; the negated pid names the
+        // process group the child leads, which it cannot leave before it is reaped.
+        unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL) };
+        child.wait().unwrap();
+    }
+
+    /// Samples until the group has `processes` members, as the shell forks them.
+    fn collect_until(pgid: u32, cpu: &mut CpuTicks, processes: u32) -> Option<ResourceSample> {
+        for _ in 0..100 {
+            let sample = collect(pgid, cpu);
+            if sample.as_ref().map(|sample| sample.processes) == Some(processes) {
+                return sample;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        None
+    }
+
+    #[test]
+    fn samples_the_whole_process_group() {
+        let child = spawn_group();
+        let pgid = child.id();
+        assert!(collect_until(pgid, &mut CpuTicks::default(), 3).is_some());
+
+        let mut cpu = CpuTicks::default();
+        let first = collect(pgid, &mut cpu).unwrap();
+        assert_eq!(first.processes, 3);
+        assert_eq!(first.cpu_percent, None);
+        assert!(first.rss_bytes > 0);
+        assert!(first.threads >= 3);
+        // Each process has at least stdin, stdout and stderr open.
+        assert!(first.open_fds >= 9);
+
+        thread::sleep(Duration::from_millis(50));
+        let second = collect(pgid, &mut cpu).unwrap();
+        assert_eq!(second.processes, 3);
+        assert!(
+            second.cpu_percent.is_some(),
+            "no cpu usage on the second sample"
+        );
+
+        kill_group(child);
+    }
+
+    #[test]
+    fn busy_group_uses_cpu() {
+        let child = spawn_shell("while :; do :; done");
+        let pgid = child.id();
+        let mut cpu = CpuTicks::default();
+        assert!(collect_until(pgid, &mut cpu, 1).is_some());
+
+        thread::sleep(Duration::from_millis(300));
+        let cpu_percent = collect(pgid, &mut cpu).unwrap().cpu_percent.unwrap();
+        kill_group(child);
+        // A spinning shell uses about one core, less on a loaded machine.
+        assert!(cpu_percent > 10.0, "{cpu_percent}% CPU");
+    }
+
+    #[test]
+    fn nothing_to_sample_once_the_group_exits() {
+        let child = spawn_group();
+        let pgid = child.id();
+        let mut cpu = CpuTicks::default();
+        assert!(collect_until(pgid, &mut cpu, 3).is_some());
+
+        kill_group(child);
+        // The orphaned children are reaped by init some time after the kill.
+        let mut exited = false;
+        for _ in 0..100 {
+            if collect(pgid, &mut cpu).is_none() {
+                exited = true;
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(exited, "exited group still sampled");
+    }
+
+    /// A sampler every 100ms of a launched stand-in server, and the runtime it samples.
+    async fn sampler(root: &TempRoot, enabled: bool) -> (MetricsSampler, InstanceRuntime) {
+        let java = root.java(&format!("echo '{DONE_LINE}'\nread line"));
+        let runtime = testing::runtime(&root.0, &testing::config(java));
+        runtime.launch().await.unwrap();
+
+        let policy = MetricsPolicy {
+            enabled,
+            interval_ms: 100,
+        };
+        let sampler = MetricsSampler::new(runtime.clone(), policy);
+        sampler.spawn();
+        (sampler, runtime)
+    }
+
+    #[tokio::test]
+    async fn samples_a_running_server() {
+        let root = TempRoot::new("metrics-enabled");
+        let (sampler, runtime) = sampler(&root, true).await;
+
+        let mut latest = sampler.subscribe();
+        let sample = tokio::time::timeout(Duration::from_secs(5), latest.wait_for(Option::is_some))
+            .await
+            .expect("no sample within 5s")
+            .unwrap()
+            .clone()
+            .unwrap();
+        runtime.kill().await.unwrap();
+
+        assert_eq!(sample.processes, 1);
+        assert!(sample.rss_bytes > 0);
+        assert!(sample.threads >= 1);
+    }
+
+    #[tokio::test]
+    async fn disabled_sampler_takes_no_samples() {
+        let root = TempRoot::new("metrics-disabled");
+        let (sampler, runtime) = sampler(&root, false).await;
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let latest = sampler.subscribe().borrow().clone();
+        runtime.kill().await.unwrap();
+        assert_eq!(latest, None);
+    }
+}
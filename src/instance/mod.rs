@@ -1,5 +1,30 @@
+mod backlog;
+mod capture;
+#[cfg(feature = "cgroups")]
+mod cgroup;
+mod detach;
 mod handle;
+mod health;
+mod logs;
+mod metrics;
+mod playtime;
+mod procfs;
+mod restart;
+mod roster;
+mod runtime;
+mod status;
+#[cfg(test)]
+mod testing;
 mod types;
 
+pub use backlog::BacklogStream;
+pub use capture::CaptureOptions;
+pub use detach::RUN_DIR;
 pub use handle::InstanceHandle;
-pub use types::{InstanceData, InstanceStatus};
+pub use health::ServerHealth;
+pub use logs::{LOG_DIR, LogRecord};
+pub use metrics::ResourceSample;
+pub use playtime::{PLAYTIME_FILE, PlaytimeEntry, PlaytimeLedger};
+pub use roster::{OnlinePlayer, PlayerRoster};
+pub(crate) use status::wait_for_status;
+pub use types::{ExitInfo, InstanceData, InstanceStatus, StopOutcome};
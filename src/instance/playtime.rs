@@ -0,0 +1,92 @@
+use std::{io::ErrorKind, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{create_dir_all, read, write};
+use uuid::Uuid;
+
+use crate::error::ServerError;
+
+use super::OnlinePlayer;
+
+/// Location of the ledger inside the server directory.
+pub const PLAYTIME_FILE: &str = ".mineguard/playtime.json";
+
+/// Accumulated playtime of one player over all completed sessions.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PlaytimeEntry {
+    pub name: String,
+    pub uuid: Option<Uuid>,
+    pub total_secs: u64,
+    pub sessions: u32,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Persistent per-server playtime totals, updated whenever a session ends.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PlaytimeLedger {
+    pub players: Vec<PlaytimeEntry>,
+}
+
+impl PlaytimeLedger {
+    /// Reads the ledger of the server in `root_dir`, empty if none was written yet.
+    pub async fn load(root_dir: &Path) -> Result<Self, ServerError> {
+        match read(root_dir.join(PLAYTIME_FILE)).await {
+            Ok(data) => serde_json::from_slice(&data).map_err(|_| ServerError::FileIO),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(_) => Err(ServerError::FileIO),
+        }
+    }
+
+    pub async fn save(&self, root_dir: &Path) -> Result<(), ServerError> {
+        let path = root_dir.join(PLAYTIME_FILE);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .await
+                .map_err(|_| ServerError::FileIO)?;
+        }
+
+        let json = serde_json::to_vec_pretty(self).map_err(|_| ServerError::FileIO)?;
+        write(path, json).await.map_err(|_| ServerError::FileIO)
+    }
+
+    /// Entry matching `uuid` when known, otherwise the name case-insensitively.
+    pub fn get(&self, name: &str, uuid: Option<Uuid>) -> Option<&PlaytimeEntry> {
+        self.players.iter().find(|entry| matches(entry, name, uuid))
+    }
+
+    /// Adds the session of `player` that ended at `left_at`.
+    pub fn record(&mut self, player: &OnlinePlayer, left_at: DateTime<Utc>) {
+        let secs = (left_at - player.joined_at).num_seconds().max(0) as u64;
+
+        match self
+            .players
+            .iter_mut()
+            .find(|entry| matches(entry, &player.name, player.uuid))
+        {
+            Some(entry) => {
+                entry.name = player.name.clone();
+                entry.uuid = entry.uuid.or(player.uuid);
+                entry.total_secs += secs;
+                entry.sessions += 1;
+                entry.last_seen = left_at;
+            }
+            None => self.players.push(PlaytimeEntry {
+                name: player.name.clone(),
+                uuid: player.uuid,
+                total_secs: secs,
+                sessions: 1,
+                first_seen: player.joined_at,
+                last_seen: left_at,
+            }),
+        }
+    }
+}
+
+fn matches(entry: &PlaytimeEntry, name: &str, uuid: Option<Uuid>) -> bool {
+    match (entry.uuid, uuid) {
+        (Some(a), Some(b)) => a == b,
+        _ => entry.name.eq_ignore_ascii_case(name),
+    }
+}
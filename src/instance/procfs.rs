@@ -0,0 +1,69 @@
+use std::fs;
+
+/// Fields of `/proc/<pid>/stat` used by MineGuard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProcStat {
+    pub(crate) state: char,
+    pub(crate) pgrp: u32,
+    /// User and system CPU time, in clock ticks.
+    pub(crate) cpu_ticks: u64,
+    pub(crate) threads: u64,
+    /// Clock ticks between boot and the start of the process.
+    pub(crate) start_time: u64,
+}
+
+impl ProcStat {
+    pub(crate) fn read(pid: u32) -> Option<Self> {
+        Self::parse(&fs::read_to_string(format!("/proc/{pid}/stat")).ok()?)
+    }
+
+    fn parse(stat: &str) -> Option<Self> {
+        // The command name may contain spaces and parentheses, so split after the last
+        // `)`. The first field left is field 3 of proc(5).
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let field = |n: usize| -> Option<u64> { fields.get(n - 3)?.parse().ok() };
+
+        Some(Self {
+            state: fields.first()?.chars().next()?,
+            pgrp: u32::try_from(field(5)?).ok()?,
+            cpu_ticks: field(14)? + field(15)?,
+            threads: field(20)?,
+            start_time: field(22)?,
+        })
+    }
+
+    /// Zombies and dead processes are gone apart from their pid.
+    pub(crate) fn is_running(&self) -> bool {
+        self.state != 'Z' && self.state != 'X'
+    }
+}
+
+/// Pids of all processes currently listed in `/proc`.
+pub(crate) fn pids() -> Vec<u32> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect()
+}
+
+/// Value of a `Key: value` line in a `/proc/<pid>` file such as `status` or `io`,
+/// without any unit.
+pub(crate) fn field(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name != key {
+            return None;
+        }
+        value.split_whitespace().next()?.parse().ok()
+    })
+}
+
+/// Clock ticks per second, the unit of CPU times in `/proc/<pid>/stat`.
+pub(crate) fn clock_ticks() -> u64 {
+    // SAFETY: sysconf only reads a system constant.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    u64::try_from(ticks).ok().filter(|&t| t > 0).unwrap_or(100)
+}
@@ -0,0 +1,337 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use tokio::{
+    sync::{Mutex, Notify, RwLock},
+    task::JoinHandle,
+    time::{Instant, sleep},
+};
+use tokio_stream::{StreamExt, wrappers::BroadcastStream};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{
+    RestartPolicy, StreamSource,
+    stream::{EventPayload, InstanceEvent},
+};
+
+use super::{InstanceStatus, runtime::InstanceRuntime};
+
+/// Watches state changes of an instance and relaunches it according to its `RestartPolicy`.
+#[derive(Debug, Clone)]
+pub(crate) struct RestartSupervisor {
+    runtime: InstanceRuntime,
+    policy: Arc<RwLock<RestartPolicy>>,
+    pending: Arc<Mutex<Option<CancellationToken>>>,
+    requested: Arc<Notify>,
+}
+
+impl RestartSupervisor {
+    pub(crate) fn new(runtime: InstanceRuntime, policy: RestartPolicy) -> Self {
+        Self {
+            runtime,
+            policy: Arc::new(RwLock::new(policy)),
+            pending: Arc::new(Mutex::new(None)),
+            requested: Arc::new(Notify::new()),
+        }
+    }
+
+    pub(crate) async fn policy(&self) -> RestartPolicy {
+        self.policy.read().await.clone()
+    }
+
+    pub(crate) async fn set_policy(&self, policy: RestartPolicy) {
+        *self.policy.write().await = policy;
+    }
+
+    /// Cancels a restart waiting for its backoff to elapse, returning true if one was pending.
+    pub(crate) async fn cancel_pending(&self) -> bool {
+        match self.pending.lock().await.take() {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Relaunches the stopped server whatever the restart mode, counting the attempt
+    /// against the same retry limit and backoff as crashes.
+    pub(crate) fn request_restart(&self) {
+        self.requested.notify_one();
+    }
+
+    /// Subscribes before returning, so no state change after the call is missed.
+    pub(crate) fn spawn(&self) -> JoinHandle<()> {
+        let events = self.runtime.subscribe(StreamSource::Event);
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            if let Ok(events) = events {
+                supervisor.run(events).await;
+            }
+        })
+    }
+
+    async fn run(self, mut events: BroadcastStream<InstanceEvent>) {
+        let shutdown = self.runtime.shutdown.clone();
+        let mut attempts: VecDeque<Instant> = VecDeque::new();
+        // Status of the last state change seen, or read after falling behind.
+        let mut last_seen = self.runtime.status();
+        // Set when the retry limit is hit, until the server is started some other way.
+        let mut gave_up = false;
+
+        loop {
+            let policy = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = self.requested.notified() => self.policy().await,
+                next = events.next() => {
+                    let (old, new) = match next {
+                        Some(Ok(event)) => match event.payload {
+                            // Two state changes in a row never end in the same status, so
+                            // this one was already read after falling behind.
+                            EventPayload::StateChange { new, .. } if new == last_seen => continue,
+                            EventPayload::StateChange { old, new } => (old, new),
+                            _ => continue,
+                        },
+                        Some(Err(_)) => {
+                            // The skipped events may include a crash. The current status
+                            // stands in for them, and the events still queued from before
+                            // it are dropped by subscribing again.
+                            let Ok(resubscribed) = self.runtime.subscribe(StreamSource::Event) else {
+                                break;
+                            };
+                            events = resubscribed;
+                            // Whether a stop was requested went with the skipped events, so
+                            // only a crash restarts.
+                            (InstanceStatus::Stopping, self.runtime.status())
+                        }
+                        None => break,
+                    };
+                    last_seen = new.clone();
+
+                    if gave_up && new == InstanceStatus::Running {
+                        attempts.clear();
+                        gave_up = false;
+                    }
+
+                    let policy = self.policy().await;
+                    if !policy.should_restart(&old, &new) {
+                        continue;
+                    }
+                    policy
+                }
+            };
+
+            if gave_up {
+                continue;
+            }
+
+            let now = Instant::now();
+            while attempts
+                .front()
+                .is_some_and(|at| now.duration_since(*at) > policy.window())
+            {
+                attempts.pop_front();
+            }
+
+            if attempts.len() as u32 >= policy.max_retries {
+                gave_up = true;
+                self.runtime
+                    .emit(EventPayload::RestartGaveUp {
+                        attempts: attempts.len() as u32,
+                    })
+                    .await;
+                continue;
+            }
+
+            attempts.push_back(now);
+            let attempt = attempts.len() as u32;
+            let delay = policy.backoff(attempt);
+
+            self.runtime
+                .emit(EventPayload::RestartAttempt {
+                    attempt,
+                    max_retries: policy.max_retries,
+                    delay,
+                })
+                .await;
+
+            let token = shutdown.child_token();
+            *self.pending.lock().await = Some(token.clone());
+
+            tokio::select! {
+                _ = token.cancelled() => continue,
+                _ = sleep(delay) => {}
+            }
+
+            if self.pending.lock().await.take().is_none() {
+                continue;
+            }
+
+            // Launching waits for the server to finish starting, which must not block
+            // this loop from observing the next crash.
+            let runtime = self.runtime.clone();
+            tokio::spawn(async move {
+                _ = runtime.launch().await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{sync::mpsc, time::timeout};
+
+    use crate::{config::RestartMode, instance::testing, server::domain::MineGuardConfig};
+
+    use super::*;
+
+    /// A runtime that is never launched.
+    fn runtime() -> InstanceRuntime {
+        testing::runtime(&std::env::temp_dir(), &MineGuardConfig::new())
+    }
+
+    /// A supervisor whose backoff outlasts every test, so that no restart launches.
+    fn supervisor(runtime: &InstanceRuntime) -> RestartSupervisor {
+        let supervisor = RestartSupervisor::new(
+            runtime.clone(),
+            RestartPolicy {
+                max_retries: 2,
+                initial_backoff_ms: 60_000,
+                jitter: 0.0,
+                ..RestartPolicy::new(RestartMode::OnCrash)
+            },
+        );
+        supervisor.spawn();
+        supervisor
+    }
+
+    /// The restart attempts and give-ups published on the event stream, in order.
+    fn decisions(runtime: &InstanceRuntime) -> mpsc::UnboundedReceiver<EventPayload> {
+        let mut events = runtime.subscribe(StreamSource::Event).unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(next) = events.next().await {
+                let Ok(InstanceEvent { payload, .. }) = next else {
+                    continue;
+                };
+                if matches!(
+                    payload,
+                    EventPayload::RestartAttempt { .. } | EventPayload::RestartGaveUp { .. }
+                ) && tx.send(payload).is_err()
+                {
+                    return;
+                }
+            }
+        });
+        rx
+    }
+
+    async fn next_decision(decisions: &mut mpsc::UnboundedReceiver<EventPayload>) -> EventPayload {
+        timeout(Duration::from_secs(5), decisions.recv())
+            .await
+            .expect("no restart decision within 5s")
+            .unwrap()
+    }
+
+    fn attempt(decision: &EventPayload) -> Option<u32> {
+        match decision {
+            EventPayload::RestartAttempt { attempt, .. } => Some(*attempt),
+            _ => None,
+        }
+    }
+
+    /// Cancels the restart waiting for its backoff, once the supervisor has scheduled it.
+    async fn cancel(supervisor: &RestartSupervisor) {
+        timeout(Duration::from_secs(5), async {
+            while !supervisor.cancel_pending().await {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("no restart pending");
+    }
+
+    async fn crash(runtime: &InstanceRuntime) {
+        runtime
+            .transition_status(InstanceStatus::Starting)
+            .await
+            .unwrap();
+        runtime
+            .transition_status(InstanceStatus::Crashed)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn gives_up_once_until_started() {
+        let runtime = runtime();
+        let mut decisions = decisions(&runtime);
+        let supervisor = supervisor(&runtime);
+
+        for expected in 1..=2 {
+            crash(&runtime).await;
+            assert_eq!(
+                attempt(&next_decision(&mut decisions).await),
+                Some(expected)
+            );
+            cancel(&supervisor).await;
+        }
+        crash(&runtime).await;
+        assert_eq!(
+            next_decision(&mut decisions).await,
+            EventPayload::RestartGaveUp { attempts: 2 }
+        );
+
+        // Crashing again is not reported as giving up again.
+        crash(&runtime).await;
+
+        // A start that reaches running begins counting anew.
+        runtime
+            .transition_status(InstanceStatus::Starting)
+            .await
+            .unwrap();
+        runtime
+            .transition_status(InstanceStatus::Running)
+            .await
+            .unwrap();
+        runtime
+            .transition_status(InstanceStatus::Crashed)
+            .await
+            .unwrap();
+        assert_eq!(attempt(&next_decision(&mut decisions).await), Some(1));
+        cancel(&supervisor).await;
+    }
+
+    #[tokio::test]
+    async fn restarts_a_crash_it_fell_behind_on() {
+        let runtime = runtime();
+        let mut decisions = decisions(&runtime);
+        let supervisor = supervisor(&runtime);
+
+        crash(&runtime).await;
+        assert_eq!(attempt(&next_decision(&mut decisions).await), Some(1));
+
+        // While the supervisor waits out the backoff, the crash is buried under more
+        // events than its receiver holds.
+        crash(&runtime).await;
+        for missed in 0..4096 {
+            runtime
+                .emit(EventPayload::StreamLagged {
+                    stream: StreamSource::Stdout,
+                    missed,
+                })
+                .await;
+        }
+        cancel(&supervisor).await;
+
+        assert_eq!(attempt(&next_decision(&mut decisions).await), Some(2));
+        cancel(&supervisor).await;
+        assert!(
+            timeout(Duration::from_millis(200), decisions.recv())
+                .await
+                .is_err(),
+            "the crash was restarted twice"
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use std::{pin::Pin, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tokio::{
+    sync::{Mutex, watch},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior, interval},
+};
+use tokio_stream::StreamExt;
+use uuid::Uuid;
+
+use crate::{
+    config::{
+        LogMeta, StreamSource,
+        stream::{EventPayload, InstanceEvent},
+    },
+    error::ServerError,
+    net::RconClient,
+    parser::parse_player_list,
+};
+
+use super::{CaptureOptions, InstanceStatus, PlaytimeLedger, runtime::InstanceRuntime};
+
+/// How often the roster is checked against `list` while the server runs, to recover
+/// from lines that were missed or never logged. Also how recent a watchdog probe must
+/// be to skip the check on the console.
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// A player currently connected to the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnlinePlayer {
+    pub name: String,
+    pub uuid: Option<Uuid>,
+    pub joined_at: DateTime<Utc>,
+    pub last_ip: Option<String>,
+}
+
+/// Players currently online, in join order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerRoster {
+    players: Vec<OnlinePlayer>,
+}
+
+impl PlayerRoster {
+    pub fn players(&self) -> &[OnlinePlayer] {
+        &self.players
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OnlinePlayer> {
+        self.players
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    fn remove(&mut self, name: &str) -> Option<OnlinePlayer> {
+        let index = self
+            .players
+            .iter()
+            .position(|p| p.name.eq_ignore_ascii_case(name))?;
+        Some(self.players.remove(index))
+    }
+}
+
+/// Keeps the `PlayerRoster` of an instance up to date from parsed log events and
+/// records finished sessions in the `PlaytimeLedger`.
+#[derive(Debug, Clone)]
+pub(crate) struct RosterTracker {
+    runtime: InstanceRuntime,
+    rcon: Arc<Mutex<Option<RconClient>>>,
+    /// When the health watchdog last sent `list`, which makes a periodic poll redundant.
+    probes: watch::Receiver<Option<Instant>>,
+    roster_tx: Arc<watch::Sender<PlayerRoster>>,
+    ledger_lock: Arc<Mutex<()>>,
+}
+
+/// A `list` in flight, run alongside the event loop.
+type Listing<'a> = Pin<Box<dyn Future<Output = Result<Vec<String>, ServerError>> + Send + 'a>>;
+
+impl RosterTracker {
+    pub(crate) fn new(
+        runtime: InstanceRuntime,
+        rcon: Arc<Mutex<Option<RconClient>>>,
+        probes: watch::Receiver<Option<Instant>>,
+    ) -> Self {
+        Self {
+            runtime,
+            rcon,
+            probes,
+            roster_tx: Arc::new(watch::Sender::new(PlayerRoster::default())),
+            ledger_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<PlayerRoster> {
+        self.roster_tx.subscribe()
+    }
+
+    pub(crate) fn spawn(&self) -> JoinHandle<()> {
+        let tracker = self.clone();
+        tokio::spawn(async move { tracker.run().await })
+    }
+
+    async fn run(self) {
+        let Ok(mut events) = self.runtime.subscribe(StreamSource::Event) else {
+            return;
+        };
+        let shutdown = self.runtime.shutdown.clone();
+
+        let mut reconcile = interval(RECONCILE_INTERVAL);
+        reconcile.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        reconcile.reset();
+
+        // Joins and leaves seen while `list` runs are applied after its result, which
+        // would otherwise overwrite them.
+        let mut listing: Option<Listing<'_>> = None;
+        let mut held = Vec::new();
+
+        loop {
+            let event = tokio::select! {
+                // Events already received are held before a finished list is applied.
+                biased;
+                _ = shutdown.cancelled() => break,
+                next = events.next() => match next {
+                    Some(Ok(event)) => event,
+                    // Join or leave events may have been dropped.
+                    Some(Err(_)) => {
+                        listing.get_or_insert_with(|| Box::pin(self.list_players()));
+                        continue;
+                    }
+                    None => break,
+                },
+                listed = async { listing.as_mut().unwrap().await }, if listing.is_some() => {
+                    listing = None;
+                    let mut added = match listed {
+                        Ok(names) if self.runtime.status() == InstanceStatus::Running => {
+                            self.replace(names.into_iter().map(|name| (name, None)).collect())
+                                .await
+                        }
+                        _ => Vec::new(),
+                    };
+                    for event in held.drain(..) {
+                        self.handle(event, &mut added).await;
+                    }
+                    continue;
+                }
+                _ = reconcile.tick() => {
+                    if listing.is_none()
+                        && self.runtime.status() == InstanceStatus::Running
+                        && self.should_poll().await
+                    {
+                        listing = Some(Box::pin(self.list_players()));
+                    }
+                    continue;
+                }
+            };
+
+            match &event.payload {
+                EventPayload::PlayerJoined { .. } | EventPayload::PlayerLeft { .. }
+                    if listing.is_some() =>
+                {
+                    held.push(event);
+                }
+                // Players that joined before a restart of MineGuard are picked up here.
+                EventPayload::StateChange {
+                    new: InstanceStatus::Running,
+                    ..
+                } => {
+                    listing.get_or_insert_with(|| Box::pin(self.list_players()));
+                }
+                EventPayload::StateChange {
+                    new: InstanceStatus::Stopped | InstanceStatus::Crashed | InstanceStatus::Killed,
+                    ..
+                } => {
+                    // The list can no longer be trusted, but what was seen before it still
+                    // counts.
+                    listing = None;
+                    for event in held.drain(..) {
+                        self.handle(event, &mut Vec::new()).await;
+                    }
+                    self.end_all(event.timestamp).await;
+                }
+                _ => self.handle(event, &mut Vec::new()).await,
+            }
+        }
+    }
+
+    /// Applies a join or leave. `listed` are players a `list` just added, whose join
+    /// lines only fill in the session instead of replacing it.
+    async fn handle(&self, event: InstanceEvent, listed: &mut Vec<String>) {
+        match event.payload {
+            EventPayload::PlayerJoined { name, uuid, ip } => {
+                let index = listed.iter().position(|n| n.eq_ignore_ascii_case(&name));
+                let player = OnlinePlayer {
+                    name,
+                    uuid,
+                    joined_at: event.timestamp,
+                    last_ip: ip,
+                };
+                match index {
+                    Some(index) => {
+                        listed.swap_remove(index);
+                        self.roster_tx.send_modify(|roster| {
+                            match roster
+                                .players
+                                .iter_mut()
+                                .find(|p| p.name.eq_ignore_ascii_case(&player.name))
+                            {
+                                Some(existing) => *existing = player,
+                                None => roster.players.push(player),
+                            }
+                        });
+                    }
+                    None => self.join(player, event.timestamp).await,
+                }
+            }
+            EventPayload::PlayerLeft { name, .. } => self.leave(&name, event.timestamp).await,
+            _ => {}
+        }
+    }
+
+    /// Whether the periodic `list` poll should run. It goes over RCON when configured;
+    /// on the console it is skipped when the watchdog probed within the interval.
+    async fn should_poll(&self) -> bool {
+        if self.rcon.lock().await.is_some() {
+            return true;
+        }
+        self.probes
+            .borrow()
+            .is_none_or(|probed| probed.elapsed() >= RECONCILE_INTERVAL)
+    }
+
+    async fn join(&self, player: OnlinePlayer, at: DateTime<Utc>) {
+        let mut ended = Vec::new();
+        self.roster_tx.send_modify(|roster| {
+            // A second join means the leave line of the previous session was missed.
+            ended.extend(roster.remove(&player.name));
+            roster.players.push(player);
+        });
+        self.record(ended, at).await;
+    }
+
+    async fn leave(&self, name: &str, at: DateTime<Utc>) {
+        let mut ended = Vec::new();
+        self.roster_tx.send_if_modified(|roster| {
+            ended.extend(roster.remove(name));
+            !ended.is_empty()
+        });
+        self.record(ended, at).await;
+    }
+
+    async fn end_all(&self, at: DateTime<Utc>) {
+        let mut ended = Vec::new();
+        self.roster_tx.send_if_modified(|roster| {
+            ended.append(&mut roster.players);
+            !ended.is_empty()
+        });
+        self.record(ended, at).await;
+    }
+
+    /// Replaces the roster with `online`, ending the sessions of players missing from it
+    /// and starting sessions for players that were not known yet.
+    pub(crate) async fn apply(&self, online: Vec<(String, Option<Uuid>)>) -> PlayerRoster {
+        self.replace(online).await;
+        self.roster_tx.borrow().clone()
+    }
+
+    /// `apply`, returning the names of the players it added.
+    async fn replace(&self, online: Vec<(String, Option<Uuid>)>) -> Vec<String> {
+        let now = Utc::now();
+        let mut ended = Vec::new();
+        let mut added = Vec::new();
+
+        self.roster_tx.send_if_modified(|roster| {
+            let before = roster.clone();
+
+            let (kept, gone): (Vec<_>, Vec<_>) = roster.players.drain(..).partition(|p| {
+                online
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case(&p.name))
+            });
+            roster.players = kept;
+            ended = gone;
+
+            for (name, uuid) in &online {
+                match roster
+                    .players
+                    .iter_mut()
+                    .find(|p| p.name.eq_ignore_ascii_case(name))
+                {
+                    Some(player) => player.uuid = player.uuid.or(*uuid),
+                    None => {
+                        added.push(name.clone());
+                        roster.players.push(OnlinePlayer {
+                            name: name.clone(),
+                            uuid: *uuid,
+                            joined_at: now,
+                            last_ip: None,
+                        });
+                    }
+                }
+            }
+
+            *roster != before
+        });
+
+        self.record(ended, now).await;
+        added
+    }
+
+    /// Runs `list` over RCON when configured, otherwise on the console, and applies it.
+    pub(crate) async fn reconcile(&self) -> Result<PlayerRoster, ServerError> {
+        let names = self.list_players().await?;
+        Ok(self
+            .apply(names.into_iter().map(|name| (name, None)).collect())
+            .await)
+    }
+
+    async fn list_players(&self) -> Result<Vec<String>, ServerError> {
+        if let Some(client) = self.rcon.lock().await.as_mut()
+            && let Ok(output) = client.execute("list").await
+            && let Some(names) = parse_player_list(&output)
+        {
+            return Ok(names);
+        }
+
+        let events = self
+            .runtime
+            .execute_and_capture("list".to_string(), &CaptureOptions::default())
+            .await?;
+        let output: Vec<String> = events
+            .into_iter()
+            .filter_map(|event| match event.payload {
+                EventPayload::StdLine { line } => Some(match LogMeta::new(&line.line) {
+                    Ok(Some(meta)) => meta.msg,
+                    _ => line.line,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        parse_player_list(&output.join("\n")).ok_or(ServerError::PlayerListUnavailable)
+    }
+
+    async fn record(&self, ended: Vec<OnlinePlayer>, at: DateTime<Utc>) {
+        if ended.is_empty() {
+            return;
+        }
+
+        let _guard = self.ledger_lock.lock().await;
+        let root_dir = &self.runtime.data.root_dir;
+        let Ok(mut ledger) = PlaytimeLedger::load(root_dir).await else {
+            return;
+        };
+        for player in &ended {
+            ledger.record(player, at);
+        }
+        _ = ledger.save(root_dir).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::testing::{self, TempRoot};
+
+    fn tracker(
+        runtime: &InstanceRuntime,
+        probes: watch::Receiver<Option<Instant>>,
+    ) -> RosterTracker {
+        RosterTracker::new(runtime.clone(), Arc::new(Mutex::new(None)), probes)
+    }
+
+    /// Waits up to 10s for the roster to satisfy `ready`.
+    async fn roster_where(
+        tracker: &RosterTracker,
+        ready: impl FnMut(&PlayerRoster) -> bool,
+    ) -> PlayerRoster {
+        let mut roster = tracker.subscribe();
+        tokio::time::timeout(Duration::from_secs(10), roster.wait_for(ready))
+            .await
+            .expect("roster never got there")
+            .unwrap()
+            .clone()
+    }
+
+    #[tokio::test]
+    async fn joins_during_a_list_fill_in_the_listed_session() {
+        let root = TempRoot::new("roster-held-join");
+        // The join is logged while the tracker waits for the `list` it sends on start.
+        let java = root.java(&format!(
+            "echo '{}'\n\
+             while read command; do\n\
+             if [ \"$command\" = list ]; then\n\
+             echo '[12:00:02] [Server thread/INFO]: Notch[/127.0.0.1:51234] logged in with entity id 1 at (0.5, 64.0, 0.5)'\n\
+             echo '[12:00:02] [Server thread/INFO]: Notch joined the game'\n\
+             echo '[12:00:02] [Server thread/INFO]: There are 1 of a max of 20 players online: Notch'\n\
+             fi\n\
+             [ \"$command\" = stop ] && exit 0\n\
+             done",
+            testing::DONE_LINE
+        ));
+        let runtime = testing::runtime(&root.0, &testing::config(java));
+        let tracker = tracker(&runtime, watch::channel(None).1);
+        let task = tracker.spawn();
+
+        runtime.launch().await.unwrap();
+        let roster = roster_where(&tracker, |roster| {
+            roster.get("Notch").is_some_and(|p| p.last_ip.is_some())
+        })
+        .await;
+        assert_eq!(roster.len(), 1);
+        assert_eq!(roster.players()[0].last_ip.as_deref(), Some("127.0.0.1"));
+
+        runtime
+            .stop_with_timeout(Duration::from_secs(2), Duration::from_millis(300))
+            .await
+            .unwrap();
+        roster_where(&tracker, PlayerRoster::is_empty).await;
+
+        // The session ends on the stop, which is recorded before the tracker shuts down.
+        runtime.shutdown.cancel();
+        task.await.unwrap();
+
+        // One session, not a zero-length one for the listed player before it.
+        let ledger = PlaytimeLedger::load(&root.0).await.unwrap();
+        assert_eq!(ledger.players.len(), 1);
+        assert_eq!(ledger.get("Notch", None).unwrap().sessions, 1);
+    }
+
+    #[tokio::test]
+    async fn console_polls_are_skipped_after_a_recent_probe() {
+        let root = TempRoot::new("roster-probe");
+        let runtime = testing::runtime(&root.0, &testing::config(root.java("exit 0")));
+        let (probed_tx, probes) = watch::channel(None);
+        let tracker = tracker(&runtime, probes);
+
+        assert!(tracker.should_poll().await);
+
+        probed_tx.send_replace(Some(Instant::now()));
+        assert!(!tracker.should_poll().await);
+
+        let long_ago = Instant::now().checked_sub(RECONCILE_INTERVAL).unwrap();
+        probed_tx.send_replace(Some(long_ago));
+        assert!(tracker.should_poll().await);
+    }
+}
@@ -0,0 +1,1429 @@
+use std::{
+    collections::VecDeque, io, os::unix::process::ExitStatusExt, path::Path, pin::Pin,
+    process::Stdio, sync::Arc, time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
+    net::unix::pipe,
+    process::{self, Child},
+    sync::{Mutex, RwLock, broadcast, mpsc, watch},
+    task::JoinHandle,
+    time::{Instant, sleep, timeout},
+};
+use tokio_stream::wrappers::{BroadcastStream, LinesStream, errors::BroadcastStreamRecvError};
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+#[cfg(feature = "cgroups")]
+use super::cgroup::Cgroup;
+#[cfg(feature = "cgroups")]
+use crate::config::CgroupLimits;
+#[cfg(feature = "events")]
+use crate::config::stream::InstanceEvent;
+use crate::{
+    config::{
+        BacklogPolicy, LaunchProfile, MinecraftType, StreamSource,
+        stream::{EventPayload, InternalEvent, TimestampResolver},
+    },
+    error::{JavaError, ServerError, SubscribeError},
+    java::JavaRuntime,
+    parser::RuleSet,
+    server::domain::MineGuardConfig,
+};
+
+use super::{
+    CaptureOptions, ExitInfo, InstanceData, InstanceStatus, StopOutcome,
+    backlog::{BacklogStream, EventChannel},
+    detach::{self, ProcessFingerprint},
+};
+
+/// Number of trailing stderr lines kept for the `ExitInfo` of a run.
+const STDERR_TAIL_LINES: usize = 50;
+const PIPE_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+/// Number of trailing output lines attached to startup errors.
+const STARTUP_OUTPUT_LINES: usize = 100;
+
+/// Why the current process is expected to exit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitIntent {
+    Unrequested,
+    Stop,
+    Kill,
+}
+
+/// The currently running server process, whose `Child` is owned by the exit watcher.
+#[derive(Debug, Clone)]
+struct ProcessRun {
+    pid: Option<u32>,
+    started_at: Instant,
+    intent: Arc<Mutex<ExitIntent>>,
+    kill: CancellationToken,
+    exit_rx: watch::Receiver<Option<ExitInfo>>,
+    shutdown: CancellationToken,
+    #[cfg(feature = "cgroups")]
+    cgroup: Option<Cgroup>,
+}
+
+impl ProcessRun {
+    async fn wait_exit(&self) -> Result<ExitInfo, ServerError> {
+        let mut rx = self.exit_rx.clone();
+        let info = rx
+            .wait_for(Option::is_some)
+            .await
+            .map_err(|_| ServerError::CommandFailed)?;
+
+        info.clone().ok_or(ServerError::CommandFailed)
+    }
+}
+
+/// Output lines of a server process.
+type LineStream = Pin<Box<dyn Stream<Item = String> + Send>>;
+
+/// The server process: our own child, or a detached server started by an earlier
+/// supervisor, which can only be watched through `/proc`.
+#[derive(Debug)]
+enum ServerProcess {
+    Child(Child),
+    Detached(ProcessFingerprint),
+}
+
+impl ServerProcess {
+    fn pid(&self) -> Option<u32> {
+        match self {
+            ServerProcess::Child(child) => child.id(),
+            ServerProcess::Detached(process) => Some(process.pid),
+        }
+    }
+}
+
+/// Stdio of a server process, either piped or through the files of a detached server.
+struct ProcessIo {
+    process: ServerProcess,
+    stdout: LineStream,
+    stderr: LineStream,
+    stdin: Box<dyn AsyncWrite + Send + Unpin>,
+    /// Lets the output tails of a detached server finish once it has exited.
+    output_done: CancellationToken,
+    #[cfg(feature = "cgroups")]
+    cgroup: Option<Cgroup>,
+}
+
+impl ProcessIo {
+    fn piped(mut child: Child) -> Result<Self, ServerError> {
+        let stdout = child.stdout.take().ok_or(ServerError::NoStdoutPipe)?;
+        let stderr = child.stderr.take().ok_or(ServerError::NoStderrPipe)?;
+        let stdin = child.stdin.take().ok_or(ServerError::NoStdinPipe)?;
+
+        Ok(Self {
+            process: ServerProcess::Child(child),
+            stdout: Box::pin(
+                LinesStream::new(BufReader::new(stdout).lines()).map_while(Result::ok),
+            ),
+            stderr: Box::pin(
+                LinesStream::new(BufReader::new(stderr).lines()).map_while(Result::ok),
+            ),
+            stdin: Box::new(stdin),
+            output_done: CancellationToken::new(),
+            #[cfg(feature = "cgroups")]
+            cgroup: None,
+        })
+    }
+
+    /// Io of a detached server whose output is read from the given offsets on.
+    fn detached(
+        root_dir: &Path,
+        process: ServerProcess,
+        stdin: pipe::Sender,
+        (stdout_offset, stderr_offset): (u64, u64),
+        output_done: CancellationToken,
+    ) -> Self {
+        let stdout = detach::tail(
+            detach::stdout_path(root_dir),
+            stdout_offset,
+            output_done.clone(),
+        );
+        let stderr = detach::tail(
+            detach::stderr_path(root_dir),
+            stderr_offset,
+            output_done.clone(),
+        );
+
+        Self {
+            process,
+            stdout: Box::pin(stdout),
+            stderr: Box::pin(stderr),
+            stdin: Box::new(stdin),
+            output_done,
+            #[cfg(feature = "cgroups")]
+            cgroup: None,
+        }
+    }
+}
+
+/// Launch state shared between an `InstanceHandle` and the tasks supervising it.
+#[derive(Debug, Clone)]
+pub(crate) struct InstanceRuntime {
+    pub(crate) data: InstanceData,
+    status_tx: Arc<watch::Sender<InstanceStatus>>,
+    transition_lock: Arc<Mutex<()>>,
+    stdout_tx: EventChannel,
+    stderr_tx: EventChannel,
+    #[cfg(feature = "events")]
+    events_tx: EventChannel,
+    #[cfg(feature = "events")]
+    internal_events_tx: mpsc::Sender<InstanceEvent>,
+    stdin_tx: mpsc::Sender<String>,
+    stdin_rx: Arc<Mutex<mpsc::Receiver<String>>>,
+    run: Arc<Mutex<Option<ProcessRun>>>,
+    last_exit: Arc<RwLock<Option<ExitInfo>>>,
+    startup_timeout: Arc<RwLock<Duration>>,
+    profile: Arc<RwLock<LaunchProfile>>,
+    /// Identifier of the instance, naming its cgroup.
+    #[cfg(feature = "cgroups")]
+    id: Uuid,
+    #[cfg(feature = "cgroups")]
+    cgroup_limits: Arc<RwLock<Option<CgroupLimits>>>,
+    rules: Arc<RwLock<RuleSet>>,
+    timestamps: Arc<std::sync::Mutex<TimestampResolver>>,
+    required_java: u32,
+    launch_lock: Arc<Mutex<()>>,
+    capture_lock: Arc<Mutex<()>>,
+    pub(crate) shutdown: CancellationToken,
+    internal_bus_tx: broadcast::Sender<InternalEvent>,
+}
+
+impl InstanceRuntime {
+    pub(crate) fn new(
+        data: InstanceData,
+        config: &MineGuardConfig,
+        rules: RuleSet,
+    ) -> (Self, mpsc::Receiver<InstanceEvent>) {
+        let (stdin_tx, stdin_rx) = mpsc::channel(1024);
+        let (internal_tx, internal_rx) = mpsc::channel(1024);
+        let shutdown = CancellationToken::new();
+        let seq = EventChannel::sequence();
+
+        let runtime = Self {
+            data,
+            status_tx: Arc::new(watch::Sender::new(InstanceStatus::Stopped)),
+            transition_lock: Arc::new(Mutex::new(())),
+            stdout_tx: EventChannel::new(2048, config.backlog.clone(), seq.clone()),
+            stderr_tx: EventChannel::new(2048, config.backlog.clone(), seq.clone()),
+            #[cfg(feature = "events")]
+            events_tx: EventChannel::new(2048, config.backlog.clone(), seq.clone()),
+            #[cfg(feature = "events")]
+            internal_events_tx: internal_tx,
+            stdin_tx,
+            stdin_rx: Arc::new(Mutex::new(stdin_rx)),
+            run: Arc::new(Mutex::new(None)),
+            last_exit: Arc::new(RwLock::new(None)),
+            startup_timeout: Arc::new(RwLock::new(config.startup_timeout())),
+            profile: Arc::new(RwLock::new(config.launch.clone())),
+            #[cfg(feature = "cgroups")]
+            id: config.uuid(),
+            #[cfg(feature = "cgroups")]
+            cgroup_limits: Arc::new(RwLock::new(config.cgroup.clone())),
+            rules: Arc::new(RwLock::new(rules)),
+            timestamps: Arc::new(std::sync::Mutex::new(TimestampResolver::new())),
+            required_java: config.required_java_major(),
+            launch_lock: Arc::new(Mutex::new(())),
+            capture_lock: Arc::new(Mutex::new(())),
+            shutdown,
+            internal_bus_tx: broadcast::Sender::new(2048),
+        };
+
+        (runtime, internal_rx)
+    }
+
+    /// Writes `command` to stdin once no capture is in progress, so that its output
+    /// does not end up in the capture.
+    pub(crate) async fn send_command(&self, command: String) -> Result<(), ServerError> {
+        let _capture_guard = self.capture_lock.lock().await;
+        self.write_stdin(command).await
+    }
+
+    async fn write_stdin(&self, mut command: String) -> Result<(), ServerError> {
+        if !command.ends_with('\n') {
+            command.push('\n');
+        }
+
+        self.stdin_tx
+            .send(command)
+            .await
+            .map_err(|_| ServerError::StdinWriteFailed)?;
+
+        Ok(())
+    }
+
+    /// Writes `command` to stdin and collects the stdout events that follow it.
+    ///
+    /// Captures and other commands are serialized so that one caller's output cannot
+    /// end up in another's. Falling behind the stdout stream fails the capture, as the
+    /// output would be incomplete.
+    pub(crate) async fn execute_and_capture(
+        &self,
+        command: String,
+        options: &CaptureOptions,
+    ) -> Result<Vec<InstanceEvent>, ServerError> {
+        let _capture_guard = self.capture_lock.lock().await;
+
+        if self.current_run().await.is_none() {
+            return Err(ServerError::NotRunning);
+        }
+
+        // Subscribe before writing so that no line of the response is missed.
+        let mut stdout_rx = self.stdout_tx.subscribe();
+        self.write_stdin(command).await?;
+
+        let deadline = sleep(options.timeout);
+        tokio::pin!(deadline);
+        let mut captured: Vec<InstanceEvent> = Vec::new();
+
+        loop {
+            let quiet = sleep(options.quiet_window);
+
+            tokio::select! {
+                event = stdout_rx.recv() => {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            return Err(ServerError::CaptureIncomplete {
+                                missed,
+                                output: captured_output(captured),
+                            });
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+
+                    let matched = match &event.payload {
+                        EventPayload::StdLine { line } => options.matches(&line.line),
+                        _ => continue,
+                    };
+                    captured.push(event);
+
+                    if matched {
+                        break;
+                    }
+                }
+                _ = quiet, if !options.has_matcher() => break,
+                _ = &mut deadline => {
+                    if options.has_matcher() {
+                        return Err(ServerError::CaptureTimeout {
+                            output: captured_output(captured),
+                        });
+                    }
+                    break;
+                }
+            }
+        }
+
+        Ok(captured)
+    }
+
+    /// Spawns the server process and waits until it reports that it finished starting.
+    pub(crate) async fn launch(&self) -> Result<(), ServerError> {
+        let launch_guard = self.launch_lock.lock().await;
+        self.validate_start_parameters().await?;
+        self.validate_java().await?;
+
+        self.transition_status(InstanceStatus::Starting).await?;
+
+        let bus_rx = self.internal_bus_tx.subscribe();
+        let stdout_rx = self.stdout_tx.subscribe();
+        let stderr_rx = self.stderr_tx.subscribe();
+
+        let run_shutdown = self.shutdown.child_token();
+        let io = match self.spawn_process(run_shutdown.child_token()).await {
+            Ok(io) => io,
+            Err(err) => {
+                self.transition_status(InstanceStatus::Crashed).await?;
+                return Err(err);
+            }
+        };
+
+        let run = self.setup_stream_pumps(io, run_shutdown.clone()).await?;
+
+        self.setup_parser(run_shutdown)?;
+        drop(launch_guard);
+
+        self.wait_for_startup(run, bus_rx, stdout_rx, stderr_rx)
+            .await
+    }
+
+    /// Takes over a detached server left running by an earlier supervisor, returning
+    /// `false` when there is none. Output written while no supervisor was attached is
+    /// not streamed again.
+    pub(crate) async fn reattach(&self) -> Result<bool, ServerError> {
+        let root_dir = self.data.root_dir.clone();
+        let Some(process) = detach::live_process(&root_dir) else {
+            return Ok(false);
+        };
+
+        let launch_guard = self.launch_lock.lock().await;
+        self.validate_start_parameters().await?;
+
+        let offsets = (
+            file_len(&detach::stdout_path(&root_dir)).await,
+            file_len(&detach::stderr_path(&root_dir)).await,
+        );
+        let started = detach::marked_started(&root_dir)
+            || output_shows_started(&detach::stdout_path(&root_dir)).await;
+
+        let stdin = detach::open_stdin(&root_dir).map_err(detached_io_error)?;
+
+        self.transition_status(InstanceStatus::Starting).await?;
+
+        let bus_rx = self.internal_bus_tx.subscribe();
+        let stdout_rx = self.stdout_tx.subscribe();
+        let stderr_rx = self.stderr_tx.subscribe();
+
+        let run_shutdown = self.shutdown.child_token();
+        let io = ProcessIo::detached(
+            &root_dir,
+            ServerProcess::Detached(process),
+            stdin,
+            offsets,
+            run_shutdown.child_token(),
+        );
+        #[cfg(feature = "cgroups")]
+        let io = ProcessIo {
+            cgroup: self
+                .cgroup_limits
+                .read()
+                .await
+                .as_ref()
+                .and_then(|limits| Cgroup::existing(self.id, limits).ok()),
+            ..io
+        };
+
+        let run = self.setup_stream_pumps(io, run_shutdown.clone()).await?;
+        self.setup_parser(run_shutdown)?;
+        drop(launch_guard);
+
+        if started {
+            self.transition_status(InstanceStatus::Running).await?;
+        } else {
+            let runtime = self.clone();
+            tokio::spawn(async move {
+                _ = runtime
+                    .wait_for_startup(run, bus_rx, stdout_rx, stderr_rx)
+                    .await;
+            });
+        }
+
+        Ok(true)
+    }
+
+    /// Races the "Done" log line against the process exiting and the startup deadline.
+    ///
+    /// A process that does not start in time is killed and reported as crashed.
+    async fn wait_for_startup(
+        &self,
+        run: ProcessRun,
+        mut bus_rx: broadcast::Receiver<InternalEvent>,
+        mut stdout_rx: broadcast::Receiver<InstanceEvent>,
+        mut stderr_rx: broadcast::Receiver<InstanceEvent>,
+    ) -> Result<(), ServerError> {
+        let startup_timeout = *self.startup_timeout.read().await;
+        let deadline = sleep(startup_timeout);
+        tokio::pin!(deadline);
+
+        let mut output = VecDeque::with_capacity(STARTUP_OUTPUT_LINES);
+        let (mut stdout_open, mut stderr_open) = (true, true);
+
+        loop {
+            tokio::select! {
+                event = bus_rx.recv() => {
+                    if let Ok(InternalEvent::ServerStarted) = event {
+                        if let Some(pid) = run.pid {
+                            detach::mark_started(&self.data.root_dir, pid);
+                        }
+                        // Fails when a stop or kill was requested while starting.
+                        return self.transition_status(InstanceStatus::Running).await;
+                    }
+                }
+                // Lines skipped by lagging are older than the ones kept for the error.
+                event = stdout_rx.recv(), if stdout_open => match event {
+                    Ok(event) => push_output(&mut output, event),
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => stdout_open = false,
+                },
+                event = stderr_rx.recv(), if stderr_open => match event {
+                    Ok(event) => push_output(&mut output, event),
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => stderr_open = false,
+                },
+                info = run.wait_exit() => {
+                    let info = info?;
+                    drain_output(&mut output, &mut stdout_rx);
+                    drain_output(&mut output, &mut stderr_rx);
+
+                    return Err(ServerError::EarlyCrash {
+                        code: info.code,
+                        output: output.into(),
+                    });
+                }
+                _ = &mut deadline => {
+                    run.kill.cancel();
+                    _ = run.wait_exit().await;
+                    drain_output(&mut output, &mut stdout_rx);
+                    drain_output(&mut output, &mut stderr_rx);
+
+                    return Err(ServerError::StartTimeout {
+                        timeout: startup_timeout,
+                        output: output.into(),
+                    });
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn startup_timeout(&self) -> Duration {
+        *self.startup_timeout.read().await
+    }
+
+    pub(crate) async fn set_startup_timeout(&self, startup_timeout: Duration) {
+        *self.startup_timeout.write().await = startup_timeout;
+    }
+
+    pub(crate) async fn launch_profile(&self) -> LaunchProfile {
+        self.profile.read().await.clone()
+    }
+
+    pub(crate) async fn set_launch_profile(&self, profile: LaunchProfile) {
+        *self.profile.write().await = profile;
+    }
+
+    #[cfg(feature = "cgroups")]
+    pub(crate) async fn cgroup_limits(&self) -> Option<CgroupLimits> {
+        self.cgroup_limits.read().await.clone()
+    }
+
+    #[cfg(feature = "cgroups")]
+    pub(crate) async fn set_cgroup_limits(&self, limits: Option<CgroupLimits>) {
+        *self.cgroup_limits.write().await = limits;
+    }
+
+    async fn validate_start_parameters(&self) -> Result<(), ServerError> {
+        if self.run.lock().await.is_some() {
+            return Err(ServerError::AlreadyRunning);
+        }
+
+        if self.status().is_stopped() {
+            Ok(())
+        } else {
+            Err(ServerError::AlreadyRunning)
+        }
+    }
+
+    /// Checks that the configured java executable runs and is new enough for the server.
+    async fn validate_java(&self) -> Result<(), ServerError> {
+        let java = self.profile.read().await.java.clone();
+
+        match JavaRuntime::probe(&java).await {
+            Ok(runtime) if !runtime.satisfies(self.required_java) => {
+                Err(ServerError::IncompatibleJava {
+                    required: self.required_java,
+                    found: runtime.version.major,
+                })
+            }
+            Ok(_) => Ok(()),
+            Err(err @ (JavaError::ProbeFailed(_) | JavaError::ProbeTimeout(_))) => {
+                Err(ServerError::JavaNotFound(err.to_string()))
+            }
+            // Wrappers with unusual banners are given the benefit of the doubt.
+            Err(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn status(&self) -> InstanceStatus {
+        self.status_tx.borrow().clone()
+    }
+
+    pub(crate) fn status_watch(&self) -> watch::Receiver<InstanceStatus> {
+        self.status_tx.subscribe()
+    }
+
+    /// Moves to `new` if the status machine allows it and publishes the change.
+    ///
+    /// Transitions are serialized so `StateChange` events arrive in the order applied.
+    pub(crate) async fn transition_status(&self, new: InstanceStatus) -> Result<(), ServerError> {
+        let _guard = self.transition_lock.lock().await;
+
+        let old = self.status();
+        if !old.can_transition_to(&new) {
+            return Err(ServerError::IllegalTransition { from: old, to: new });
+        }
+        self.status_tx.send_replace(new.clone());
+
+        self.emit(EventPayload::StateChange { old, new }).await;
+        Ok(())
+    }
+
+    #[cfg(feature = "events")]
+    pub(crate) async fn emit(&self, payload: EventPayload) {
+        let event = InstanceEvent {
+            id: Uuid::new_v4(),
+
+            seq: 0,
+
+            timestamp: Utc::now(),
+
+            payload,
+        };
+
+        self.publish(event).await;
+    }
+
+    /// Sends an already built event to the `Event` stream subscribers.
+    pub(crate) async fn publish(&self, event: InstanceEvent) {
+        _ = self.internal_events_tx.send(event).await;
+    }
+
+    async fn build_start_command(&self) -> process::Command {
+        let profile = self.profile.read().await;
+
+        let mut command = if profile.detached {
+            detach::recording_command(&self.data.root_dir, &profile.java)
+        } else {
+            process::Command::new(&profile.java)
+        };
+        command
+            .args(profile.args(&self.data.jar_path))
+            .envs(&profile.env)
+            .current_dir(&self.data.root_dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .stdin(Stdio::piped());
+
+        if profile.detached {
+            // A new session keeps the server out of our process group and terminal, so
+            // it outlives us. Its pgid is still its pid, as with `process_group(0)`.
+            // SAFETY: setsid is async-signal-safe and touches no memory of the parent.
+            unsafe {
+                command.pre_exec(|| {
+                    if libc::setsid() == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        } else {
+            command.process_group(0);
+        }
+        command
+    }
+
+    fn spawn_child_process(&self, mut command: process::Command) -> Result<Child, ServerError> {
+        command.spawn().map_err(|_| ServerError::CommandFailed)
+    }
+
+    /// Spawns the server with piped stdio, or detached with a PID file when the launch
+    /// profile asks for it.
+    async fn spawn_process(
+        &self,
+        output_done: CancellationToken,
+    ) -> Result<ProcessIo, ServerError> {
+        let command = self.build_start_command().await;
+        #[cfg(feature = "cgroups")]
+        let (command, cgroup) = self.setup_cgroup(command).await?;
+
+        let io = if self.profile.read().await.detached {
+            self.spawn_detached(command, output_done)?
+        } else {
+            ProcessIo::piped(self.spawn_child_process(command)?)?
+        };
+        #[cfg(feature = "cgroups")]
+        let io = ProcessIo { cgroup, ..io };
+        Ok(io)
+    }
+
+    /// Creates the cgroup of the server when limits are configured and makes `command`
+    /// start in it.
+    #[cfg(feature = "cgroups")]
+    async fn setup_cgroup(
+        &self,
+        mut command: process::Command,
+    ) -> Result<(process::Command, Option<Cgroup>), ServerError> {
+        let Some(limits) = self.cgroup_limits.read().await.clone() else {
+            return Ok((command, None));
+        };
+
+        let cgroup = Cgroup::prepare(self.id, &limits)
+            .and_then(|cgroup| cgroup.attach(&mut command).map(|_| cgroup))
+            .map_err(|err| ServerError::Cgroup(err.to_string()))?;
+        Ok((command, Some(cgroup)))
+    }
+
+    fn spawn_detached(
+        &self,
+        mut command: process::Command,
+        output_done: CancellationToken,
+    ) -> Result<ProcessIo, ServerError> {
+        let root_dir = &self.data.root_dir;
+        let (stdin, stdout, stderr) =
+            detach::detached_stdio(root_dir).map_err(detached_io_error)?;
+        let writer = detach::open_stdin(root_dir).map_err(detached_io_error)?;
+        command.stdin(stdin).stdout(stdout).stderr(stderr);
+        let mut child = self.spawn_child_process(command)?;
+
+        let recorded = child
+            .id()
+            .and_then(ProcessFingerprint::of)
+            .ok_or(ServerError::CommandFailed)
+            .and_then(|process| {
+                detach::write_pid_file(root_dir, &process).map_err(detached_io_error)
+            });
+        if let Err(err) = recorded {
+            // Without a PID file the server could never be reattached.
+            _ = child.start_kill();
+            return Err(err);
+        }
+
+        Ok(ProcessIo::detached(
+            root_dir,
+            ServerProcess::Child(child),
+            writer,
+            (0, 0),
+            output_done,
+        ))
+    }
+
+    async fn setup_stream_pumps(
+        &self,
+        io: ProcessIo,
+        shutdown: CancellationToken,
+    ) -> Result<ProcessRun, ServerError> {
+        let ProcessIo {
+            process,
+            mut stdout,
+            mut stderr,
+            stdin,
+            output_done,
+            #[cfg(feature = "cgroups")]
+            cgroup,
+        } = io;
+
+        let stdout_tx = self.stdout_tx.clone();
+        let stderr_tx = self.stderr_tx.clone();
+        let stdout_timestamps = self.timestamps.clone();
+        let stderr_timestamps = self.timestamps.clone();
+        let stderr_tail = Arc::new(Mutex::new(VecDeque::with_capacity(STDERR_TAIL_LINES)));
+        let pump_tail = stderr_tail.clone();
+
+        let stdout_pump = tokio::spawn(async move {
+            while let Some(line) = stdout.next().await {
+                let timestamp = resolve_timestamp(&stdout_timestamps, &line);
+                stdout_tx.send(InstanceEvent::stdout(line, timestamp));
+            }
+        });
+
+        let stderr_pump = tokio::spawn(async move {
+            while let Some(line) = stderr.next().await {
+                let mut tail = pump_tail.lock().await;
+                if tail.len() == STDERR_TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line.clone());
+                drop(tail);
+
+                let timestamp = resolve_timestamp(&stderr_timestamps, &line);
+                stderr_tx.send(InstanceEvent::stderr(line, timestamp));
+            }
+        });
+
+        let stdin_rx = self.stdin_rx.clone();
+        let stdin_shutdown = shutdown.clone();
+
+        tokio::spawn(async move {
+            let mut writer = BufWriter::new(stdin);
+            let mut stdin_rx = stdin_rx.lock().await;
+
+            loop {
+                tokio::select! {
+                    _ = stdin_shutdown.cancelled() => {
+                        break;
+                    }
+                    maybe_cmd = stdin_rx.recv() => {
+                        if let Some(cmd) = maybe_cmd {
+                            _ = writer.write_all(cmd.as_bytes()).await;
+                            _ = writer.flush().await;
+                        }
+                    }
+                }
+            }
+        });
+
+        let (exit_tx, exit_rx) = watch::channel(None);
+        let run = ProcessRun {
+            pid: process.pid(),
+            started_at: Instant::now(),
+            intent: Arc::new(Mutex::new(ExitIntent::Unrequested)),
+            kill: CancellationToken::new(),
+            exit_rx,
+            shutdown,
+            #[cfg(feature = "cgroups")]
+            cgroup,
+        };
+        *self.run.lock().await = Some(run.clone());
+
+        let runtime = self.clone();
+        let watched = run.clone();
+        tokio::spawn(async move {
+            runtime
+                .watch_exit(
+                    process,
+                    output_done,
+                    watched,
+                    [stdout_pump, stderr_pump],
+                    stderr_tail,
+                    exit_tx,
+                )
+                .await;
+        });
+
+        Ok(run)
+    }
+
+    /// Owns the child process until it exits, then records how it ended and settles the status.
+    async fn watch_exit(
+        &self,
+        process: ServerProcess,
+        output_done: CancellationToken,
+        run: ProcessRun,
+        pumps: [JoinHandle<()>; 2],
+        stderr_tail: Arc<Mutex<VecDeque<String>>>,
+        exit_tx: watch::Sender<Option<ExitInfo>>,
+    ) {
+        let (code, signal) = match process {
+            ServerProcess::Child(mut child) => {
+                let exit_status = tokio::select! {
+                    status = child.wait() => status,
+                    _ = run.kill.cancelled() => {
+                        _ = child.start_kill();
+                        child.wait().await
+                    }
+                };
+                match exit_status {
+                    Ok(status) => (status.code(), status.signal()),
+                    Err(_) => (None, None),
+                }
+            }
+            ServerProcess::Detached(process) => {
+                tokio::select! {
+                    _ = process.exited() => {}
+                    _ = run.kill.cancelled() => {
+                        _ = signal_process_group(process.pid, libc::SIGKILL);
+                        process.exited().await;
+                    }
+                }
+                detach::exit_status(&self.data.root_dir)
+            }
+        };
+        let uptime = run.started_at.elapsed();
+        output_done.cancel();
+        if let Some(pid) = run.pid {
+            detach::clear_pid_file(&self.data.root_dir, pid);
+        }
+
+        // Grandchildren may keep the pipes open, so do not wait forever for the last lines.
+        let drain = async {
+            for pump in pumps {
+                _ = pump.await;
+            }
+        };
+        _ = timeout(PIPE_DRAIN_TIMEOUT, drain).await;
+
+        #[cfg(feature = "cgroups")]
+        let oom_killed = run.cgroup.as_ref().is_some_and(Cgroup::oom_killed);
+        #[cfg(not(feature = "cgroups"))]
+        let oom_killed = false;
+        #[cfg(feature = "cgroups")]
+        if let Some(cgroup) = &run.cgroup {
+            cgroup.remove();
+        }
+
+        let info = ExitInfo {
+            code,
+            signal,
+            uptime,
+            stderr_tail: stderr_tail.lock().await.iter().cloned().collect(),
+            oom_killed,
+        };
+
+        // A clean exit before the server finished starting is still a failed start.
+        let starting = self.status() == InstanceStatus::Starting;
+        let intent = *run.intent.lock().await;
+        let status = match intent {
+            ExitIntent::Kill => InstanceStatus::Killed,
+            ExitIntent::Stop => InstanceStatus::Stopped,
+            ExitIntent::Unrequested if info.success() && !starting => InstanceStatus::Stopped,
+            ExitIntent::Unrequested => InstanceStatus::Crashed,
+        };
+
+        *self.run.lock().await = None;
+        run.shutdown.cancel();
+        *self.last_exit.write().await = Some(info.clone());
+
+        self.emit(EventPayload::Exited { info: info.clone() }).await;
+        // Every status with a live process may end this way.
+        _ = self.transition_status(status).await;
+
+        _ = exit_tx.send(Some(info));
+    }
+
+    #[cfg(all(feature = "events", feature = "mc-vanilla"))]
+    fn setup_parser(&self, shutdown: CancellationToken) -> Result<(), ServerError> {
+        use crate::{config::LogMeta, parser::LogParser};
+
+        let stdout_stream = self
+            .subscribe(StreamSource::Stdout)
+            .map_err(|_| ServerError::NoStdoutPipe)?;
+        let bus_tx = self.internal_bus_tx.clone();
+        let runtime = self.clone();
+
+        // User rules apply to every server type; modded and plugin servers log lines
+        // the vanilla parser does not know.
+        let vanilla = self.data.mc_type == MinecraftType::Vanilla;
+        tokio::spawn(async move {
+            let mut rx = stdout_stream;
+            let tx = bus_tx;
+            let mut parser = LogParser::new();
+            let rules = runtime.rules.read().await.clone();
+
+            loop {
+                tokio::select! {
+                    _ = shutdown.cancelled() => {
+                        break;
+                    }
+                    next_line = rx.next() => {
+                        // Lines skipped here were never parsed, so tell event consumers.
+                        if let Some(Err(BroadcastStreamRecvError::Lagged(missed))) = next_line {
+                            runtime
+                                .emit(EventPayload::StreamLagged {
+                                    stream: StreamSource::Stdout,
+                                    missed,
+                                })
+                                .await;
+                        }
+                        if let Some(Ok(val)) = next_line {
+                            let timestamp = val.timestamp;
+                            let event_line = match val.payload {
+                                EventPayload::StdLine{line} => {
+                                    line
+                                },
+                                _ => continue,
+                            };
+
+                            let meta = LogMeta::new(event_line.line.as_str()).ok().flatten();
+
+                            let mut payloads = rules.apply(meta.as_ref(), &event_line.line);
+                            if let Some(meta) = &meta
+                                && vanilla
+                            {
+                                if let Ok(Some(event)) = meta.parse_event() {
+                                    _ = tx.send(event);
+                                }
+                                payloads.extend(parser.parse(meta));
+                            }
+
+                            for payload in payloads {
+                                runtime
+                                    .publish(InstanceEvent {
+                                        id: Uuid::new_v4(),
+                                        seq: 0,
+                                        timestamp,
+                                        payload,
+                                    })
+                                    .await;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    async fn current_run(&self) -> Option<ProcessRun> {
+        self.run.lock().await.clone()
+    }
+
+    /// Pid of the running server, which also leads its process group.
+    pub(crate) async fn pid(&self) -> Option<u32> {
+        self.current_run().await.and_then(|run| run.pid)
+    }
+
+    pub(crate) async fn last_exit(&self) -> Option<ExitInfo> {
+        self.last_exit.read().await.clone()
+    }
+
+    /// Moves to `status` with `intent` recorded first. The exit watcher reads the intent
+    /// under the same lock, so an exit racing the transition is not taken for a crash.
+    /// The intent is left as it was when the transition is refused.
+    async fn request_exit(
+        &self,
+        run: &ProcessRun,
+        status: InstanceStatus,
+        intent: ExitIntent,
+    ) -> Result<(), ServerError> {
+        let mut current = run.intent.lock().await;
+        let previous = std::mem::replace(&mut *current, intent);
+        if let Err(err) = self.transition_status(status).await {
+            *current = previous;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn kill(&self) -> Result<(), ServerError> {
+        let Some(run) = self.current_run().await else {
+            return Err(ServerError::NotRunning);
+        };
+
+        self.request_exit(&run, InstanceStatus::Killing, ExitIntent::Kill)
+            .await?;
+
+        run.kill.cancel();
+        run.wait_exit().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn stop(&self) -> Result<(), ServerError> {
+        let Some(run) = self.current_run().await else {
+            return Err(ServerError::NotRunning);
+        };
+
+        self.request_exit(&run, InstanceStatus::Stopping, ExitIntent::Stop)
+            .await?;
+
+        // Not held up by a capture in progress.
+        _ = self.write_stdin("stop".to_string()).await;
+        run.wait_exit().await?;
+        Ok(())
+    }
+
+    /// Stops the server, escalating to SIGTERM on its process group once `graceful`
+    /// elapses and to SIGKILL once `terminate` elapses after that.
+    pub(crate) async fn stop_with_timeout(
+        &self,
+        graceful: Duration,
+        terminate: Duration,
+    ) -> Result<StopOutcome, ServerError> {
+        let Some(run) = self.current_run().await else {
+            return Err(ServerError::NotRunning);
+        };
+
+        self.request_exit(&run, InstanceStatus::Stopping, ExitIntent::Stop)
+            .await?;
+
+        // Not held up by a capture in progress.
+        _ = self.write_stdin("stop".to_string()).await;
+        if timeout(graceful, run.wait_exit()).await.is_ok() {
+            return Ok(StopOutcome::Graceful);
+        }
+
+        // The process may have exited on its own in the meantime.
+        if self
+            .transition_status(InstanceStatus::Terminating)
+            .await
+            .is_err()
+        {
+            run.wait_exit().await?;
+            return Ok(StopOutcome::Graceful);
+        }
+        if let Some(pid) = run.pid {
+            signal_process_group(pid, libc::SIGTERM)?;
+        }
+        if timeout(terminate, run.wait_exit()).await.is_ok() {
+            return Ok(StopOutcome::Terminated);
+        }
+
+        if self
+            .request_exit(&run, InstanceStatus::Killing, ExitIntent::Kill)
+            .await
+            .is_err()
+        {
+            run.wait_exit().await?;
+            return Ok(StopOutcome::Terminated);
+        }
+        if let Some(pid) = run.pid {
+            _ = signal_process_group(pid, libc::SIGKILL);
+        }
+        run.kill.cancel();
+        run.wait_exit().await?;
+        Ok(StopOutcome::Killed)
+    }
+
+    pub(crate) fn subscribe(
+        &self,
+        stream: StreamSource,
+    ) -> Result<BroadcastStream<InstanceEvent>, SubscribeError> {
+        let rx = self.channel(&stream).subscribe();
+        Ok(BroadcastStream::new(rx))
+    }
+
+    pub(crate) fn subscribe_with_backlog(
+        &self,
+        stream: StreamSource,
+        n: usize,
+    ) -> Result<BacklogStream, SubscribeError> {
+        Ok(self.channel(&stream).subscribe_with_backlog(n))
+    }
+
+    pub(crate) fn resume_from(
+        &self,
+        stream: StreamSource,
+        seq: u64,
+    ) -> Result<BacklogStream, SubscribeError> {
+        Ok(self.channel(&stream).subscribe_from(seq))
+    }
+
+    /// Applies new backlog limits to all streams, dropping events beyond them.
+    pub(crate) fn set_backlog_policy(&self, policy: BacklogPolicy) {
+        self.stdout_tx.set_policy(policy.clone());
+        self.stderr_tx.set_policy(policy.clone());
+        #[cfg(feature = "events")]
+        self.events_tx.set_policy(policy);
+    }
+
+    fn channel(&self, stream: &StreamSource) -> &EventChannel {
+        match stream {
+            StreamSource::Stdout => &self.stdout_tx,
+            StreamSource::Stderr => &self.stderr_tx,
+            #[cfg(feature = "events")]
+            StreamSource::Event => &self.events_tx,
+        }
+    }
+
+    #[cfg(feature = "events")]
+    /// Replaces the event rules, used from the next start on.
+    pub(crate) async fn set_rules(&self, rules: RuleSet) {
+        *self.rules.write().await = rules;
+    }
+
+    pub(crate) fn events_sender(&self) -> EventChannel {
+        self.events_tx.clone()
+    }
+}
+
+fn resolve_timestamp(resolver: &std::sync::Mutex<TimestampResolver>, line: &str) -> DateTime<Utc> {
+    let now = Utc::now();
+    resolver.lock().unwrap().resolve(line, now).unwrap_or(now)
+}
+
+fn detached_io_error(err: io::Error) -> ServerError {
+    ServerError::DetachedIo(err.to_string())
+}
+
+async fn file_len(path: &Path) -> u64 {
+    tokio::fs::metadata(path).await.map_or(0, |meta| meta.len())
+}
+
+/// Whether a server output file contains the line a server logs once it has started.
+#[cfg(all(feature = "events", feature = "mc-vanilla"))]
+async fn output_shows_started(path: &Path) -> bool {
+    use crate::config::LogMeta;
+
+    let Ok(file) = tokio::fs::File::open(path).await else {
+        return false;
+    };
+    let mut lines = BufReader::new(file).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let event = LogMeta::new(&line)
+            .ok()
+            .flatten()
+            .and_then(|meta| meta.parse_event().ok().flatten());
+        if matches!(event, Some(InternalEvent::ServerStarted)) {
+            return true;
+        }
+    }
+    false
+}
+
+fn push_output(output: &mut VecDeque<String>, event: InstanceEvent) {
+    if let EventPayload::StdLine { line } = event.payload {
+        if output.len() == STARTUP_OUTPUT_LINES {
+            output.pop_front();
+        }
+        output.push_back(line.line);
+    }
+}
+
+/// The last lines of a failed capture, for its error.
+fn captured_output(captured: Vec<InstanceEvent>) -> Vec<String> {
+    let mut output = VecDeque::new();
+    for event in captured {
+        push_output(&mut output, event);
+    }
+    output.into()
+}
+
+fn drain_output(output: &mut VecDeque<String>, rx: &mut broadcast::Receiver<InstanceEvent>) {
+    while let Ok(event) = rx.try_recv() {
+        push_output(output, event);
+    }
+}
+
+/// Sends `signal` to the process group led by `pid`, as created by `process_group(0)`.
+fn signal_process_group(pid: u32, signal: libc::c_int) -> Result<(), ServerError> {
+    let pgid = libc::pid_t::try_from(pid).map_err(|_| ServerError::SignalFailed)?;
+
+    // SAFETY: killpg has no memory safety preconditions; it only takes plain integers.
+    let res = unsafe { libc::killpg(pgid, signal) };
+    if res != 0 {
+        return Err(ServerError::SignalFailed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instance::testing::{self, DONE_LINE, TempRoot};
+
+    use super::*;
+
+    /// Launches a detached stand-in that exits with the code written to its stdin, and
+    /// reattaches a second runtime to it as `MineGuardServer::load` does.
+    async fn reattached(root: &TempRoot) -> InstanceRuntime {
+        let java = root.java(&format!("echo '{DONE_LINE}'\nread code\nexit \"$code\""));
+        let mut config = testing::config(java);
+        config.launch.detached = true;
+
+        testing::runtime(&root.0, &config).launch().await.unwrap();
+        let runtime = testing::runtime(&root.0, &config);
+        assert!(runtime.reattach().await.unwrap());
+        testing::reach(&runtime, InstanceStatus::Running).await;
+        runtime
+    }
+
+    #[tokio::test]
+    async fn rejects_illegal_transitions() {
+        let runtime = testing::runtime(&std::env::temp_dir(), &MineGuardConfig::new());
+
+        let err = runtime
+            .transition_status(InstanceStatus::Running)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ServerError::IllegalTransition {
+                from: InstanceStatus::Stopped,
+                to: InstanceStatus::Running,
+            }
+        ));
+        assert_eq!(runtime.status(), InstanceStatus::Stopped);
+
+        runtime
+            .transition_status(InstanceStatus::Starting)
+            .await
+            .unwrap();
+        assert_eq!(runtime.status(), InstanceStatus::Starting);
+    }
+
+    /// Launches a stand-in server that runs `script` once it reported having started.
+    async fn launched(root: &TempRoot, script: &str) -> InstanceRuntime {
+        let java = root.java(&format!("echo '{DONE_LINE}'\n{script}"));
+        let runtime = testing::runtime(&root.0, &testing::config(java));
+        runtime.launch().await.unwrap();
+        runtime
+    }
+
+    async fn stop_quickly(runtime: &InstanceRuntime) -> StopOutcome {
+        runtime
+            .stop_with_timeout(Duration::from_millis(300), Duration::from_millis(300))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn stop_ends_gracefully_on_the_stop_command() {
+        let root = TempRoot::new("stop-graceful");
+        let runtime = launched(
+            &root,
+            "while read command; do [ \"$command\" = stop ] && exit 0; done",
+        )
+        .await;
+
+        assert_eq!(stop_quickly(&runtime).await, StopOutcome::Graceful);
+        assert_eq!(runtime.status(), InstanceStatus::Stopped);
+        assert_eq!(runtime.last_exit().await.unwrap().code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn stop_escalates_to_sigterm() {
+        let root = TempRoot::new("stop-terminated");
+        let runtime = launched(&root, "while read command; do :; done").await;
+
+        assert_eq!(stop_quickly(&runtime).await, StopOutcome::Terminated);
+        assert_eq!(runtime.status(), InstanceStatus::Stopped);
+        assert_eq!(
+            runtime.last_exit().await.unwrap().signal,
+            Some(libc::SIGTERM)
+        );
+    }
+
+    #[tokio::test]
+    async fn stop_escalates_to_sigkill() {
+        let root = TempRoot::new("stop-killed");
+        let runtime = launched(&root, "trap '' TERM\nwhile read command; do :; done").await;
+
+        assert_eq!(stop_quickly(&runtime).await, StopOutcome::Killed);
+        assert_eq!(runtime.status(), InstanceStatus::Killed);
+        assert_eq!(
+            runtime.last_exit().await.unwrap().signal,
+            Some(libc::SIGKILL)
+        );
+    }
+
+    #[tokio::test]
+    async fn classifies_unrequested_exits() {
+        let cases = [
+            ("exit 0", InstanceStatus::Stopped, Some(0), None),
+            ("exit 3", InstanceStatus::Crashed, Some(3), None),
+            (
+                "kill -KILL $$",
+                InstanceStatus::Crashed,
+                None,
+                Some(libc::SIGKILL),
+            ),
+        ];
+
+        for (n, (exit, status, code, signal)) in cases.into_iter().enumerate() {
+            let root = TempRoot::new(&format!("exit-{n}"));
+            let runtime = launched(&root, &format!("read command\n{exit}")).await;
+            let mut events = runtime.subscribe(StreamSource::Event).unwrap();
+
+            runtime.send_command("exit".to_string()).await.unwrap();
+            testing::reach(&runtime, status.clone()).await;
+
+            let info = runtime.last_exit().await.unwrap();
+            assert_eq!((info.code, info.signal), (code, signal), "{exit}");
+            let published = timeout(Duration::from_secs(5), async {
+                loop {
+                    let event = events.next().await.unwrap().unwrap();
+                    if let EventPayload::Exited { info } = event.payload {
+                        return info;
+                    }
+                }
+            })
+            .await
+            .expect("no Exited event");
+            assert_eq!(published, info, "{exit}");
+        }
+    }
+
+    #[tokio::test]
+    async fn requested_kill_is_killed() {
+        let root = TempRoot::new("exit-kill");
+        let runtime = launched(&root, "while read command; do :; done").await;
+
+        runtime.kill().await.unwrap();
+        assert_eq!(runtime.status(), InstanceStatus::Killed);
+        assert_eq!(
+            runtime.last_exit().await.unwrap().signal,
+            Some(libc::SIGKILL)
+        );
+    }
+
+    #[tokio::test]
+    async fn exit_before_done_is_an_early_crash() {
+        let root = TempRoot::new("startup-crash");
+        let java = root.java("echo 'Loading libraries'\necho 'Failed to bind to port' >&2\nexit 1");
+        let runtime = testing::runtime(&root.0, &testing::config(java));
+
+        match runtime.launch().await {
+            Err(ServerError::EarlyCrash { code, output }) => {
+                assert_eq!(code, Some(1));
+                assert!(output.iter().any(|line| line == "Loading libraries"));
+                assert!(output.iter().any(|line| line == "Failed to bind to port"));
+            }
+            other => panic!("expected an early crash, got {other:?}"),
+        }
+        testing::reach(&runtime, InstanceStatus::Crashed).await;
+    }
+
+    #[tokio::test]
+    async fn no_done_before_the_deadline_is_a_timeout() {
+        let root = TempRoot::new("startup-timeout");
+        let java = root.java("echo 'Loading libraries'\nwhile read command; do :; done");
+        let mut config = testing::config(java);
+        config.startup_timeout_secs = 1;
+        let runtime = testing::runtime(&root.0, &config);
+
+        match runtime.launch().await {
+            Err(ServerError::StartTimeout { timeout, output }) => {
+                assert_eq!(timeout, Duration::from_secs(1));
+                assert_eq!(output, ["Loading libraries"]);
+            }
+            other => panic!("expected a start timeout, got {other:?}"),
+        }
+        testing::reach(&runtime, InstanceStatus::Crashed).await;
+        assert_eq!(
+            runtime.last_exit().await.unwrap().signal,
+            Some(libc::SIGKILL)
+        );
+    }
+
+    #[tokio::test]
+    async fn commands_wait_for_the_capture_in_progress() {
+        let root = TempRoot::new("capture");
+        let java = root.java(&format!(
+            "echo '{DONE_LINE}'\n\
+             while read command; do\n\
+             echo \"start $command\"\n\
+             sleep 0.2\n\
+             echo \"end $command\"\n\
+             done"
+        ));
+        let runtime = testing::runtime(&root.0, &testing::config(java));
+        runtime.launch().await.unwrap();
+
+        let capture = |command: &'static str| {
+            let runtime = runtime.clone();
+            let end = format!("end {command}");
+            tokio::spawn(async move {
+                let options = CaptureOptions::default().until(move |line| line == end);
+                let events = runtime
+                    .execute_and_capture(command.to_string(), &options)
+                    .await
+                    .unwrap();
+                captured_output(events)
+            })
+        };
+        let first = capture("first");
+        sleep(Duration::from_millis(50)).await;
+        let second = capture("second");
+        sleep(Duration::from_millis(50)).await;
+        // Queued behind both captures.
+        runtime.send_command("plain".to_string()).await.unwrap();
+
+        assert_eq!(first.await.unwrap(), ["start first", "end first"]);
+        assert_eq!(second.await.unwrap(), ["start second", "end second"]);
+        runtime.kill().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reattached_server_exiting_cleanly_is_stopped() {
+        let root = TempRoot::new("reattach-clean");
+        let runtime = reattached(&root).await;
+
+        runtime.send_command("0".to_string()).await.unwrap();
+        testing::reach(&runtime, InstanceStatus::Stopped).await;
+        assert_eq!(runtime.last_exit().await.unwrap().code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn reattached_server_failing_is_crashed() {
+        let root = TempRoot::new("reattach-crash");
+        let runtime = reattached(&root).await;
+
+        runtime.send_command("3".to_string()).await.unwrap();
+        testing::reach(&runtime, InstanceStatus::Crashed).await;
+        assert_eq!(runtime.last_exit().await.unwrap().code, Some(3));
+    }
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use tokio::{sync::watch, time::timeout};
+
+use crate::error::ServerError;
+
+use super::InstanceStatus;
+
+/// Waits until the status watched by `rx` satisfies `predicate`, checking the current
+/// status first, and returns the matching status.
+pub(crate) async fn wait_for_status<F>(
+    mut rx: watch::Receiver<InstanceStatus>,
+    mut predicate: F,
+    limit: Duration,
+) -> Result<InstanceStatus, ServerError>
+where
+    F: FnMut(&InstanceStatus) -> bool,
+{
+    let result = timeout(limit, rx.wait_for(|status| predicate(status)))
+        .await
+        .map(|waited| waited.map(|status| status.clone()));
+
+    match result {
+        Ok(Ok(status)) => Ok(status),
+        // The sender lives as long as the runtime, so this only happens on shutdown.
+        Ok(Err(_)) => Err(ServerError::NotRunning),
+        Err(_) => Err(ServerError::StatusTimeout {
+            timeout: limit,
+            last: rx.borrow().clone(),
+        }),
+    }
+}
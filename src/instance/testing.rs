@@ -0,0 +1,94 @@
+//! Stand-ins for a server process, shared by the tests that drive an `InstanceRuntime`.
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    config::MinecraftType, instance::InstanceData, parser::RuleSet, server::domain::MineGuardConfig,
+};
+
+use super::{InstanceStatus, runtime::InstanceRuntime, wait_for_status};
+
+/// The line a vanilla server prints once it finished starting.
+pub(crate) const DONE_LINE: &str =
+    r#"[12:00:01] [Server thread/INFO]: Done (1.234s)! For help, type "help""#;
+
+/// A fresh server root under the system temp dir, removed when dropped.
+pub(crate) struct TempRoot(pub(crate) PathBuf);
+
+impl TempRoot {
+    pub(crate) fn new(name: &str) -> Self {
+        let root =
+            std::env::temp_dir().join(format!("mineguard-runtime-{}-{}", name, std::process::id()));
+        _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        Self(root)
+    }
+
+    /// Writes a `java` stand-in that passes the version probe and otherwise runs
+    /// `script` with `sh`, returning its path.
+    pub(crate) fn java(&self, script: &str) -> PathBuf {
+        let path = self.0.join("java");
+        fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\n\
+                 if [ \"$1\" = -version ]; then\n\
+                 echo 'openjdk version \"21.0.2\" 2024-01-16' >&2\n\
+                 exit 0\n\
+                 fi\n\
+                 {script}\n"
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+}
+
+impl Drop for TempRoot {
+    fn drop(&mut self) {
+        _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A runtime for a vanilla 1.21.1 server in `root_dir`, with its events looped back as
+/// the handle does.
+pub(crate) fn runtime(root_dir: &Path, config: &MineGuardConfig) -> InstanceRuntime {
+    let data = InstanceData {
+        root_dir: root_dir.to_path_buf(),
+        jar_path: "server.jar".into(),
+        mc_version: "1.21.1".parse().unwrap(),
+        mc_type: MinecraftType::Vanilla,
+    };
+    let (runtime, mut internal_rx) = InstanceRuntime::new(data, config, RuleSet::default());
+    let events = runtime.events_sender();
+    tokio::spawn(async move {
+        while let Some(event) = internal_rx.recv().await {
+            events.send(event);
+        }
+    });
+    runtime
+}
+
+/// A config launching the `java` stand-in.
+pub(crate) fn config(java: PathBuf) -> MineGuardConfig {
+    let mut config = MineGuardConfig::new();
+    config.launch.java = java;
+    config
+}
+
+/// Waits up to 10s for the runtime to reach `status`.
+pub(crate) async fn reach(runtime: &InstanceRuntime, status: InstanceStatus) {
+    wait_for_status(
+        runtime.status_watch(),
+        |current| *current == status,
+        Duration::from_secs(10),
+    )
+    .await
+    .unwrap_or_else(|err| panic!("never reached {status:?}: {err}"));
+}
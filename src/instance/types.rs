@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use crate::config::{MinecraftType, MinecraftVersion};
 
@@ -11,12 +11,122 @@ pub struct InstanceData {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "wire", serde(rename_all = "snake_case"))]
 pub enum InstanceStatus {
     Starting,
     Running,
     Stopping,
+    Terminating,
     Stopped,
     Crashed,
     Killing,
     Killed,
 }
+
+impl InstanceStatus {
+    /// True when no server process is running.
+    pub fn is_stopped(&self) -> bool {
+        matches!(self, Self::Stopped | Self::Crashed | Self::Killed)
+    }
+
+    /// Whether an instance in this status may move to `next`.
+    ///
+    /// An exit can race a requested stop or kill, so every status with a live process
+    /// may end in `Stopped` or `Crashed`.
+    pub fn can_transition_to(&self, next: &InstanceStatus) -> bool {
+        use InstanceStatus::*;
+
+        match self {
+            Stopped | Crashed | Killed => *next == Starting,
+            Starting | Running => {
+                matches!(next, Running | Stopping | Killing | Stopped | Crashed) && next != self
+            }
+            Stopping => matches!(next, Terminating | Killing | Stopped | Crashed),
+            Terminating => matches!(next, Killing | Stopped | Crashed),
+            Killing => matches!(next, Killed | Stopped | Crashed),
+        }
+    }
+}
+
+/// How a server process ended after a stop with timeouts was requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopOutcome {
+    Graceful,
+    Terminated,
+    Killed,
+}
+
+/// How the server process exited, published as `EventPayload::Exited`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "wire", derive(serde::Deserialize, serde::Serialize))]
+pub struct ExitInfo {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    #[cfg_attr(
+        feature = "wire",
+        serde(with = "crate::config::stream::wire::duration_ms")
+    )]
+    pub uptime: Duration,
+    pub stderr_tail: Vec<String>,
+    /// The kernel killed the server for exceeding the memory limit of its cgroup.
+    #[cfg_attr(feature = "wire", serde(default))]
+    pub oom_killed: bool,
+}
+
+impl ExitInfo {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InstanceStatus::{self, *};
+
+    const ALL: [InstanceStatus; 8] = [
+        Starting,
+        Running,
+        Stopping,
+        Terminating,
+        Stopped,
+        Crashed,
+        Killing,
+        Killed,
+    ];
+
+    #[test]
+    fn allows_exactly_the_listed_transitions() {
+        let allowed: [(InstanceStatus, &[InstanceStatus]); 8] = [
+            (Starting, &[Running, Stopping, Killing, Stopped, Crashed]),
+            (Running, &[Stopping, Killing, Stopped, Crashed]),
+            (Stopping, &[Terminating, Killing, Stopped, Crashed]),
+            (Terminating, &[Killing, Stopped, Crashed]),
+            (Killing, &[Killed, Stopped, Crashed]),
+            (Stopped, &[Starting]),
+            (Crashed, &[Starting]),
+            (Killed, &[Starting]),
+        ];
+
+        for (from, nexts) in allowed {
+            for to in ALL {
+                assert_eq!(
+                    from.can_transition_to(&to),
+                    nexts.contains(&to),
+                    "{from:?} -> {to:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stopped_statuses_have_no_process() {
+        for status in ALL {
+            assert_eq!(
+                status.is_stopped(),
+                matches!(status, Stopped | Crashed | Killed),
+                "{status:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs::{canonicalize, read_dir};
+
+use crate::error::JavaError;
+
+use super::JavaRuntime;
+
+/// Parent directories that commonly hold one JVM installation per child directory.
+const INSTALL_ROOTS: &[&str] = &[
+    "/usr/lib/jvm",
+    "/usr/lib64/jvm",
+    "/usr/java",
+    "/usr/local/java",
+    "/opt/java",
+    "/opt/jdk",
+    "/opt",
+    "/Library/Java/JavaVirtualMachines",
+];
+
+/// Same as `INSTALL_ROOTS`, relative to the home directory (SDKMAN, IntelliJ, asdf).
+const HOME_INSTALL_ROOTS: &[&str] = &[
+    ".sdkman/candidates/java",
+    ".jdks",
+    ".asdf/installs/java",
+    ".local/share/mise/installs/java",
+];
+
+/// Finds Java installations from `JAVA_HOME`, `PATH` and common install directories.
+///
+/// Executables that fail to report a version are skipped; each installation is
+/// listed once even when reachable through several symlinks.
+pub async fn discover() -> Vec<JavaRuntime> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+
+    if let Some(home) = env::var_os("JAVA_HOME") {
+        candidates.push(java_in_home(Path::new(&home)));
+    }
+
+    if let Some(path) = env::var_os("PATH") {
+        candidates.extend(env::split_paths(&path).map(|dir| dir.join("java")));
+    }
+
+    let mut roots: Vec<PathBuf> = INSTALL_ROOTS.iter().map(PathBuf::from).collect();
+    if let Some(home) = env::var_os("HOME") {
+        let home = PathBuf::from(home);
+        roots.extend(HOME_INSTALL_ROOTS.iter().map(|rel| home.join(rel)));
+    }
+
+    for root in roots {
+        let Ok(mut entries) = read_dir(&root).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            candidates.push(java_in_home(&entry.path()));
+        }
+    }
+
+    let mut seen: Vec<PathBuf> = Vec::new();
+    let mut runtimes = Vec::new();
+
+    for candidate in candidates {
+        let Ok(resolved) = canonicalize(&candidate).await else {
+            continue;
+        };
+        if seen.contains(&resolved) {
+            continue;
+        }
+        seen.push(resolved);
+
+        if let Ok(runtime) = JavaRuntime::probe(&candidate).await {
+            runtimes.push(runtime);
+        }
+    }
+
+    runtimes
+}
+
+/// Picks the runtime best suited for a server that needs `required_major`.
+///
+/// An exact major match is preferred, then the closest newer major, since older
+/// servers tend to break on much newer JVMs.
+pub fn select_runtime(runtimes: &[JavaRuntime], required_major: u32) -> Option<&JavaRuntime> {
+    runtimes
+        .iter()
+        .filter(|runtime| runtime.satisfies(required_major))
+        .min_by_key(|runtime| {
+            (
+                runtime.version.major - required_major,
+                !runtime.is_64bit,
+                std::cmp::Reverse((runtime.version.minor, runtime.version.patch)),
+            )
+        })
+}
+
+/// Discovers the installed runtimes and selects one for `required_major`.
+pub async fn find_runtime(required_major: u32) -> Result<JavaRuntime, JavaError> {
+    let runtimes = discover().await;
+    select_runtime(&runtimes, required_major)
+        .cloned()
+        .ok_or(JavaError::NoCompatibleRuntime(required_major))
+}
+
+/// Location of the java executable inside a `JAVA_HOME` style directory.
+fn java_in_home(home: &Path) -> PathBuf {
+    let macos_home = home.join("Contents/Home");
+    if macos_home.is_dir() {
+        return macos_home.join("bin/java");
+    }
+    home.join("bin/java")
+}
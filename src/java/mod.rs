@@ -0,0 +1,5 @@
+mod discovery;
+mod runtime;
+
+pub use discovery::{discover, find_runtime, select_runtime};
+pub use runtime::{JavaRuntime, JavaVersion, required_java_major};
@@ -0,0 +1,193 @@
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    process::Stdio,
+    str::FromStr,
+    time::Duration,
+};
+
+use tokio::{process::Command, time::timeout};
+
+use crate::{
+    config::{MinecraftVersion, Snapshot, Version},
+    error::JavaError,
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Java version parsed from strings like `17.0.9`, `21` or the legacy `1.8.0_392`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JavaVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub raw: String,
+}
+
+/// A Java installation together with what `java -version` reported about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JavaRuntime {
+    pub executable: PathBuf,
+    pub version: JavaVersion,
+    /// First word of the version banner, such as `openjdk` or `java`.
+    pub implementation: String,
+    /// Runtime environment name, such as `OpenJDK Runtime Environment Temurin-21.0.1+12`.
+    pub runtime_name: Option<String>,
+    pub is_64bit: bool,
+}
+
+impl Display for JavaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl FromStr for JavaVersion {
+    type Err = JavaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw = s.trim().to_string();
+
+        // Drop pre-release and build suffixes such as `-ea` or `+9`.
+        let core = raw
+            .split(['-', '+', ' '])
+            .next()
+            .ok_or_else(|| JavaError::InvalidVersion(raw.clone()))?;
+
+        let mut parts = core.split(['.', '_']).map(|part| {
+            part.parse::<u32>()
+                .map_err(|_| JavaError::InvalidVersion(raw.clone()))
+        });
+
+        let first = parts
+            .next()
+            .ok_or_else(|| JavaError::InvalidVersion(raw.clone()))??;
+
+        // Up to Java 8 the major version is the second component of `1.x`.
+        let (major, minor, patch) = if first == 1 {
+            let major = parts
+                .next()
+                .ok_or_else(|| JavaError::InvalidVersion(raw.clone()))??;
+            let minor = parts.next().transpose()?.unwrap_or(0);
+            let patch = parts.next().transpose()?.unwrap_or(0);
+            (major, minor, patch)
+        } else {
+            let minor = parts.next().transpose()?.unwrap_or(0);
+            let patch = parts.next().transpose()?.unwrap_or(0);
+            (first, minor, patch)
+        };
+
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            raw,
+        })
+    }
+}
+
+impl JavaRuntime {
+    /// Runs `<executable> -version` and parses its output.
+    pub async fn probe<P: AsRef<Path>>(executable: P) -> Result<Self, JavaError> {
+        let executable = executable.as_ref().to_path_buf();
+
+        let output = timeout(
+            PROBE_TIMEOUT,
+            Command::new(&executable)
+                .arg("-version")
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .map_err(|_| JavaError::ProbeTimeout(executable.display().to_string()))?
+        .map_err(|_| JavaError::ProbeFailed(executable.display().to_string()))?;
+
+        // `java -version` prints to stderr, but some wrappers redirect it to stdout.
+        let mut text = String::from_utf8_lossy(&output.stderr).to_string();
+        if text.trim().is_empty() {
+            text = String::from_utf8_lossy(&output.stdout).to_string();
+        }
+
+        JavaRuntime::parse_version_output(executable, &text)
+    }
+
+    /// Parses the banner printed by `java -version`, for example:
+    ///
+    /// ```text
+    /// openjdk version "17.0.9" 2023-10-17
+    /// OpenJDK Runtime Environment (build 17.0.9+9-Ubuntu-122.04)
+    /// OpenJDK 64-Bit Server VM (build 17.0.9+9-Ubuntu-122.04, mixed mode, sharing)
+    /// ```
+    pub fn parse_version_output(executable: PathBuf, output: &str) -> Result<Self, JavaError> {
+        let mut lines = output.lines().map(str::trim).filter(|l| !l.is_empty());
+
+        // JVMs may print `Picked up JAVA_TOOL_OPTIONS` and similar notices first.
+        let banner = lines
+            .by_ref()
+            .find(|line| line.contains(" version \""))
+            .ok_or_else(|| JavaError::UnrecognizedOutput(output.to_string()))?;
+
+        let implementation = banner
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        let version_str = banner
+            .split('"')
+            .nth(1)
+            .ok_or_else(|| JavaError::UnrecognizedOutput(output.to_string()))?;
+        let version = JavaVersion::from_str(version_str)?;
+
+        let rest: Vec<&str> = lines.collect();
+        let runtime_name = rest.first().map(|line| match line.find(" (build") {
+            Some(i) => line[..i].to_string(),
+            None => line.to_string(),
+        });
+        let is_64bit = rest.iter().any(|line| line.contains("64-Bit"));
+
+        Ok(Self {
+            executable,
+            version,
+            implementation,
+            runtime_name,
+            is_64bit,
+        })
+    }
+
+    /// Returns true when this runtime can run a server that needs `required_major`.
+    pub fn satisfies(&self, required_major: u32) -> bool {
+        self.version.major >= required_major
+    }
+}
+
+/// Minimum Java major version a server needs, for when no release manifest is available.
+///
+/// Mirrors the `javaVersion.majorVersion` values Mojang publishes: Java 16 from 1.17,
+/// Java 17 from 1.18 and Java 21 from 1.20.5.
+pub fn required_java_major(version: &MinecraftVersion) -> u32 {
+    match version {
+        MinecraftVersion::Release(Version {
+            major,
+            minor,
+            patch,
+        }) => match (*major, *minor, *patch) {
+            (1, minor, _) if minor < 17 => 8,
+            (1, 17, _) => 16,
+            (1, minor, _) if minor < 20 => 17,
+            (1, 20, patch) if patch < 5 => 17,
+            _ => 21,
+        },
+        MinecraftVersion::Snapshot(Snapshot { year, week, .. }) => match (*year, *week) {
+            (year, _) if year < 21 => 8,
+            (21, week) if week < 19 => 8,
+            (21, week) if week < 44 => 16,
+            (year, _) if year < 24 => 17,
+            (24, week) if week < 14 => 17,
+            _ => 21,
+        },
+    }
+}
@@ -1,7 +1,9 @@
 pub mod config;
 pub mod error;
 pub mod instance;
+pub mod java;
 pub mod manifests;
+pub mod net;
 pub mod parser;
 pub mod server;
 pub mod utils;
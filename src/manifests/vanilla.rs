@@ -1,24 +1,26 @@
 #![cfg(feature = "mc-vanilla")]
 
-use async_trait::async_trait;
 use reqwest::Client;
 use serde::Deserialize;
 
 use crate::{config::MinecraftVersion, error::ManifestError};
 
 #[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
 pub struct VanillaManifestV2 {
     latest: VanillaManifestV2Latest,
     versions: Vec<VanillaManifestV2Version>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
 pub struct VanillaManifestV2Latest {
     release: String,
     snapshot: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
 pub struct VanillaManifestV2Version {
     id: String,
     #[serde(rename = "type")]
@@ -35,8 +37,20 @@ pub struct VanillaManifestV2Version {
 #[derive(Debug, Clone, Deserialize)]
 pub struct VanillaReleaseManifest {
     downloads: VanillaReleaseManifestDownloads,
+    // Missing from manifests of versions older than 1.6.
+    #[serde(rename = "javaVersion")]
+    java_version: Option<VanillaReleaseManifestJavaVersion>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
+pub struct VanillaReleaseManifestJavaVersion {
+    component: String,
+    #[serde(rename = "majorVersion")]
+    major_version: u32,
 }
 #[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
 pub struct VanillaReleaseManifestDownloads {
     client: VanillaReleaseManifestDownloadsItem,
     client_mappings: VanillaReleaseManifestDownloadsItem,
@@ -45,6 +59,7 @@ pub struct VanillaReleaseManifestDownloads {
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)]
 pub struct VanillaReleaseManifestDownloadsItem {
     sha1: String,
     size: u32,
@@ -75,6 +90,10 @@ impl VanillaReleaseManifest {
     pub fn server_url(&self) -> String {
         self.downloads.server.url.clone()
     }
+
+    pub fn java_major(&self) -> Option<u32> {
+        self.java_version.as_ref().map(|v| v.major_version)
+    }
 }
 
 impl VanillaManifestV2 {
@@ -104,10 +123,7 @@ impl VanillaManifestV2 {
     ) -> Result<Option<VanillaManifestV2Version>, ManifestError> {
         let id = version.to_string();
 
-        let found = match self.versions.iter().find(|p| p.id == id) {
-            Some(val) => Some(val.clone()),
-            None => None,
-        };
+        let found = self.versions.iter().find(|p| p.id == id).cloned();
 
         Ok(found)
     }
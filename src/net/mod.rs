@@ -0,0 +1,7 @@
+pub mod ping;
+pub mod query;
+pub mod rcon;
+
+pub use ping::{PlayerSample, ServerStatus};
+pub use query::{BasicStat, FullStat, QueryClient};
+pub use rcon::RconClient;
@@ -0,0 +1,463 @@
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{Instant, timeout},
+};
+
+use crate::error::PingError;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time the server gets to answer the ping after its status, which is kept either way.
+const LATENCY_TIMEOUT: Duration = Duration::from_secs(1);
+/// Protocol version sent in the handshake; servers answer status requests regardless.
+const HANDSHAKE_PROTOCOL: i32 = -1;
+const STATE_STATUS: i32 = 1;
+/// Upper bound for a status response, which is dominated by the base64 favicon.
+const MAX_PACKET_LEN: i32 = 2 * 1024 * 1024;
+/// Protocol version 74 (1.6.2) as sent by 1.6 clients in the legacy ping.
+const LEGACY_PROTOCOL: u8 = 74;
+
+/// One entry of the player sample returned by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: String,
+}
+
+/// Server status as reported by a Server List Ping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerStatus {
+    pub version_name: String,
+    pub protocol: i32,
+    pub players_online: u32,
+    pub players_max: u32,
+    /// Subset of online players chosen by the server, empty for legacy pings.
+    pub sample: Vec<PlayerSample>,
+    /// MOTD flattened to plain text, formatting codes included.
+    pub motd: String,
+    pub favicon: Option<String>,
+    pub latency: Option<Duration>,
+    /// True when the status came from the pre-1.7 legacy ping.
+    pub legacy: bool,
+}
+
+/// Pings `host:port`, falling back to the 1.6 legacy ping for servers older than 1.7.
+pub async fn ping(host: &str, port: u16) -> Result<ServerStatus, PingError> {
+    ping_with_timeout(host, port, DEFAULT_TIMEOUT).await
+}
+
+pub async fn ping_with_timeout(
+    host: &str,
+    port: u16,
+    duration: Duration,
+) -> Result<ServerStatus, PingError> {
+    match timeout(duration, request_status(host, port)).await {
+        Ok(Ok((mut stream, mut status))) => {
+            status.latency = measure_latency(&mut stream).await;
+            Ok(status)
+        }
+        Ok(Err(PingError::ConnectFailed(err))) => Err(PingError::ConnectFailed(err)),
+        _ => timeout(duration, ping_legacy(host, port))
+            .await
+            .map_err(|_| PingError::Timeout)?,
+    }
+}
+
+/// Handshake, status request and ping as used by 1.7 and newer.
+pub async fn ping_modern(host: &str, port: u16) -> Result<ServerStatus, PingError> {
+    let (mut stream, mut status) = request_status(host, port).await?;
+    status.latency = measure_latency(&mut stream).await;
+    Ok(status)
+}
+
+/// Handshake and status request, returning the connection for the ping that follows.
+async fn request_status(host: &str, port: u16) -> Result<(TcpStream, ServerStatus), PingError> {
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| PingError::ConnectFailed(e.to_string()))?;
+
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, 0x00);
+    write_varint(&mut handshake, HANDSHAKE_PROTOCOL);
+    write_string(&mut handshake, host);
+    handshake.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut handshake, STATE_STATUS);
+    write_packet(&mut stream, &handshake).await?;
+
+    write_packet(&mut stream, &[0x00]).await?;
+
+    let response = read_packet(&mut stream).await?;
+    let mut cursor = response.as_slice();
+    if read_varint_slice(&mut cursor)? != 0x00 {
+        return Err(PingError::Protocol(
+            "unexpected status packet id".to_string(),
+        ));
+    }
+    let json = read_string_slice(&mut cursor)?;
+    let status = parse_status_json(&json)?;
+    Ok((stream, status))
+}
+
+/// Round-trip time of a ping packet. Best effort, since some proxies close the
+/// connection after the status or never answer.
+async fn measure_latency(stream: &mut TcpStream) -> Option<Duration> {
+    let started = Instant::now();
+    let mut ping = Vec::new();
+    write_varint(&mut ping, 0x01);
+    ping.extend_from_slice(&0x4d47_i64.to_be_bytes());
+
+    let round_trip = async {
+        write_packet(stream, &ping).await?;
+        read_packet(stream).await
+    };
+    match timeout(LATENCY_TIMEOUT, round_trip).await {
+        Ok(Ok(_)) => Some(started.elapsed()),
+        _ => None,
+    }
+}
+
+/// The `0xFE 0x01` ping understood by servers from 1.4 to 1.6, and by newer servers
+/// for compatibility.
+pub async fn ping_legacy(host: &str, port: u16) -> Result<ServerStatus, PingError> {
+    let started = Instant::now();
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| PingError::ConnectFailed(e.to_string()))?;
+
+    let mut data = vec![LEGACY_PROTOCOL];
+    write_utf16_string(&mut data, host);
+    data.extend_from_slice(&(port as i32).to_be_bytes());
+
+    let mut request = vec![0xFE, 0x01, 0xFA];
+    write_utf16_string(&mut request, "MC|PingHost");
+    request.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    request.extend_from_slice(&data);
+    stream.write_all(&request).await.map_err(io_error)?;
+
+    let kick = stream.read_u8().await.map_err(io_error)?;
+    if kick != 0xFF {
+        return Err(PingError::Protocol(format!(
+            "unexpected legacy packet 0x{:02x}",
+            kick
+        )));
+    }
+    let len = stream.read_u16().await.map_err(io_error)? as usize;
+    let mut raw = vec![0u8; len * 2];
+    stream.read_exact(&mut raw).await.map_err(io_error)?;
+
+    let units: Vec<u16> = raw
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect();
+    let text = String::from_utf16_lossy(&units);
+
+    let mut status = parse_legacy_response(&text)?;
+    status.latency = Some(started.elapsed());
+    Ok(status)
+}
+
+/// Parses the JSON status document sent by 1.7+ servers.
+pub fn parse_status_json(json: &str) -> Result<ServerStatus, PingError> {
+    let value: Value =
+        serde_json::from_str(json).map_err(|e| PingError::InvalidResponse(e.to_string()))?;
+
+    let version = &value["version"];
+    let players = &value["players"];
+
+    let sample = players["sample"]
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|entry| PlayerSample {
+                    name: entry["name"].as_str().unwrap_or_default().to_string(),
+                    id: entry["id"].as_str().unwrap_or_default().to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(ServerStatus {
+        version_name: version["name"].as_str().unwrap_or_default().to_string(),
+        protocol: version["protocol"].as_i64().unwrap_or(-1) as i32,
+        players_online: players["online"].as_u64().unwrap_or(0) as u32,
+        players_max: players["max"].as_u64().unwrap_or(0) as u32,
+        sample,
+        motd: flatten_text_component(&value["description"]),
+        favicon: value["favicon"].as_str().map(str::to_string),
+        latency: None,
+        legacy: false,
+    })
+}
+
+/// Parses the kick message of a legacy ping: `§1\0protocol\0version\0motd\0online\0max`
+/// for 1.4 and newer, or `motd§online§max` before that.
+pub fn parse_legacy_response(text: &str) -> Result<ServerStatus, PingError> {
+    let invalid = || PingError::InvalidResponse(text.to_string());
+
+    if let Some(rest) = text.strip_prefix("§1\0") {
+        let fields: Vec<&str> = rest.split('\0').collect();
+        if fields.len() < 5 {
+            return Err(invalid());
+        }
+
+        return Ok(ServerStatus {
+            version_name: fields[1].to_string(),
+            protocol: fields[0].parse().map_err(|_| invalid())?,
+            players_online: fields[3].parse().map_err(|_| invalid())?,
+            players_max: fields[4].parse().map_err(|_| invalid())?,
+            sample: Vec::new(),
+            motd: fields[2].to_string(),
+            favicon: None,
+            latency: None,
+            legacy: true,
+        });
+    }
+
+    let mut fields = text.rsplitn(3, '§');
+    let max = fields.next().ok_or_else(invalid)?;
+    let online = fields.next().ok_or_else(invalid)?;
+    let motd = fields.next().ok_or_else(invalid)?;
+
+    Ok(ServerStatus {
+        version_name: String::new(),
+        protocol: -1,
+        players_online: online.parse().map_err(|_| invalid())?,
+        players_max: max.parse().map_err(|_| invalid())?,
+        sample: Vec::new(),
+        motd: motd.to_string(),
+        favicon: None,
+        latency: None,
+        legacy: true,
+    })
+}
+
+/// Flattens a chat component, which may be a plain string, into its text.
+fn flatten_text_component(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(flatten_text_component).collect(),
+        Value::Object(map) => {
+            let mut text = map
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            if let Some(extra) = map.get("extra") {
+                text.push_str(&flatten_text_component(extra));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+fn io_error(err: std::io::Error) -> PingError {
+    PingError::Io(err.to_string())
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as i32);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn write_utf16_string(buf: &mut Vec<u8>, value: &str) {
+    let units: Vec<u16> = value.encode_utf16().collect();
+    buf.extend_from_slice(&(units.len() as u16).to_be_bytes());
+    for unit in units {
+        buf.extend_from_slice(&unit.to_be_bytes());
+    }
+}
+
+async fn write_packet(stream: &mut TcpStream, payload: &[u8]) -> Result<(), PingError> {
+    let mut packet = Vec::with_capacity(payload.len() + 5);
+    write_varint(&mut packet, payload.len() as i32);
+    packet.extend_from_slice(payload);
+    stream.write_all(&packet).await.map_err(io_error)
+}
+
+async fn read_varint<R: AsyncRead + Unpin>(reader: &mut R) -> Result<i32, PingError> {
+    let mut value: u32 = 0;
+    for i in 0..5 {
+        let byte = reader.read_u8().await.map_err(io_error)?;
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(PingError::Protocol("varint too long".to_string()))
+}
+
+fn read_varint_slice(cursor: &mut &[u8]) -> Result<i32, PingError> {
+    let mut value: u32 = 0;
+    for i in 0..5 {
+        let (&byte, rest) = cursor
+            .split_first()
+            .ok_or_else(|| PingError::Protocol("truncated varint".to_string()))?;
+        *cursor = rest;
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(PingError::Protocol("varint too long".to_string()))
+}
+
+fn read_string_slice(cursor: &mut &[u8]) -> Result<String, PingError> {
+    let len = read_varint_slice(cursor)?;
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= cursor.len())
+        .ok_or_else(|| PingError::Protocol("invalid string length".to_string()))?;
+
+    let (text, rest) = cursor.split_at(len);
+    *cursor = rest;
+    String::from_utf8(text.to_vec()).map_err(|e| PingError::InvalidResponse(e.to_string()))
+}
+
+async fn read_packet(stream: &mut TcpStream) -> Result<Vec<u8>, PingError> {
+    let len = read_varint(stream).await?;
+    if !(1..=MAX_PACKET_LEN).contains(&len) {
+        return Err(PingError::Protocol(format!(
+            "invalid packet length {}",
+            len
+        )));
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload).await.map_err(io_error)?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const STATUS_JSON: &str = r#"{"version":{"name":"1.21.1","protocol":767},"players":{"max":20,"online":1,"sample":[{"name":"Notch","id":"069a79f4-44e9-4726-a5be-fca90e38aaf5"}]},"description":{"text":"A ","extra":[{"text":"Minecraft Server"}]}}"#;
+    const LEGACY_KICK: &str = "§1\u{0}78\u{0}1.6.4\u{0}A Minecraft Server\u{0}3\u{0}20";
+
+    /// How the fake responder behaves after its status reply or at all.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Responder {
+        Modern,
+        /// Sends the status but never answers the ping.
+        NoPong,
+        /// Only understands the legacy ping and hangs up on anything else.
+        Legacy,
+    }
+
+    async fn fake_server(responder: Responder) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(respond(stream, responder));
+            }
+        });
+        port
+    }
+
+    async fn respond(mut stream: TcpStream, responder: Responder) -> Result<(), PingError> {
+        let mut first = [0u8; 1];
+        stream.peek(&mut first).await.map_err(io_error)?;
+
+        if first[0] == 0xFE {
+            if responder != Responder::Legacy {
+                return Ok(());
+            }
+            // The request is not needed to answer, only read so the client can finish it.
+            let mut request = [0u8; 256];
+            _ = stream.read(&mut request).await;
+
+            let units: Vec<u16> = LEGACY_KICK.encode_utf16().collect();
+            let mut kick = vec![0xFF];
+            kick.extend_from_slice(&(units.len() as u16).to_be_bytes());
+            for unit in units {
+                kick.extend_from_slice(&unit.to_be_bytes());
+            }
+            return stream.write_all(&kick).await.map_err(io_error);
+        }
+        if responder == Responder::Legacy {
+            return Ok(());
+        }
+
+        read_packet(&mut stream).await?;
+        read_packet(&mut stream).await?;
+        let mut status = Vec::new();
+        write_varint(&mut status, 0x00);
+        write_string(&mut status, STATUS_JSON);
+        write_packet(&mut stream, &status).await?;
+
+        let ping = read_packet(&mut stream).await?;
+        if responder == Responder::NoPong {
+            // Hold the connection open without answering.
+            std::future::pending::<()>().await;
+        }
+        write_packet(&mut stream, &ping).await
+    }
+
+    #[tokio::test]
+    async fn pings_modern_server() {
+        let port = fake_server(Responder::Modern).await;
+        let status = ping("127.0.0.1", port).await.unwrap();
+
+        assert_eq!(status.version_name, "1.21.1");
+        assert_eq!(status.protocol, 767);
+        assert_eq!((status.players_online, status.players_max), (1, 20));
+        assert_eq!(status.sample[0].name, "Notch");
+        assert_eq!(status.motd, "A Minecraft Server");
+        assert!(status.latency.is_some());
+        assert!(!status.legacy);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_legacy_ping() {
+        let port = fake_server(Responder::Legacy).await;
+        let status = ping("127.0.0.1", port).await.unwrap();
+
+        assert_eq!(status.version_name, "1.6.4");
+        assert_eq!(status.protocol, 78);
+        assert_eq!((status.players_online, status.players_max), (3, 20));
+        assert_eq!(status.motd, "A Minecraft Server");
+        assert!(status.legacy);
+    }
+
+    #[tokio::test]
+    async fn keeps_status_when_pong_never_arrives() {
+        let port = fake_server(Responder::NoPong).await;
+        let status = ping_with_timeout("127.0.0.1", port, Duration::from_secs(2))
+            .await
+            .unwrap();
+
+        assert_eq!(status.version_name, "1.21.1");
+        assert_eq!(status.latency, None);
+        assert!(!status.legacy);
+    }
+
+    #[test]
+    fn parses_pre_1_4_legacy_response() {
+        let status = parse_legacy_response("A Minecraft Server§3§20").unwrap();
+
+        assert_eq!(status.motd, "A Minecraft Server");
+        assert_eq!((status.players_online, status.players_max), (3, 20));
+        assert_eq!(status.protocol, -1);
+    }
+}
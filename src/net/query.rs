@@ -0,0 +1,361 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use tokio::{net::UdpSocket, time::timeout};
+
+use crate::error::QueryError;
+
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const TYPE_HANDSHAKE: u8 = 0x09;
+const TYPE_STAT: u8 = 0x00;
+/// The server ignores the upper nibble of every session id byte.
+const SESSION_ID_MASK: i32 = 0x0F0F_0F0F;
+/// Constant `splitnum\0\x80\0` header in front of the full stat key/value section.
+const FULL_STAT_PADDING: usize = 11;
+/// Constant `\x01player_\0\0` header in front of the full stat player section.
+const PLAYER_SECTION_PADDING: usize = 10;
+const MAX_DATAGRAM_LEN: usize = 8192;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answer to a basic stat request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicStat {
+    pub motd: String,
+    pub game_type: String,
+    pub map: String,
+    pub num_players: u32,
+    pub max_players: u32,
+    pub host_port: u16,
+    pub host_ip: String,
+}
+
+/// Answer to a full stat request, including every online player.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullStat {
+    pub motd: String,
+    pub game_type: String,
+    pub game_id: String,
+    pub version: String,
+    /// Server software from the `plugins` field, e.g. `Paper on 1.20.4`; empty for vanilla.
+    pub server_mod: String,
+    pub plugins: Vec<String>,
+    pub map: String,
+    pub num_players: u32,
+    pub max_players: u32,
+    pub host_port: u16,
+    pub host_ip: String,
+    pub players: Vec<String>,
+    /// Every key/value pair sent by the server, including ones not mapped above.
+    pub raw: BTreeMap<String, String>,
+}
+
+/// Client for the GameSpy4 based Query protocol enabled by `enable-query`.
+#[derive(Debug, Clone)]
+pub struct QueryClient {
+    addr: String,
+    timeout: Duration,
+    session_id: i32,
+}
+
+impl QueryClient {
+    pub fn new<A: Into<String>>(addr: A) -> Self {
+        Self {
+            addr: addr.into(),
+            timeout: DEFAULT_TIMEOUT,
+            session_id: fastrand::i32(..) & SESSION_ID_MASK,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub async fn basic_stat(&self) -> Result<BasicStat, QueryError> {
+        let socket = self.connect().await?;
+        let challenge = self.handshake(&socket).await?;
+
+        let request = self.request(TYPE_STAT, &challenge.to_be_bytes());
+        let response = self.exchange(&socket, &request, TYPE_STAT).await?;
+        parse_basic_stat(&response)
+    }
+
+    pub async fn full_stat(&self) -> Result<FullStat, QueryError> {
+        let socket = self.connect().await?;
+        let challenge = self.handshake(&socket).await?;
+
+        // The four padding bytes are what distinguishes a full from a basic request.
+        let mut payload = challenge.to_be_bytes().to_vec();
+        payload.extend_from_slice(&[0, 0, 0, 0]);
+        let request = self.request(TYPE_STAT, &payload);
+        let response = self.exchange(&socket, &request, TYPE_STAT).await?;
+        parse_full_stat(&response)
+    }
+
+    async fn connect(&self) -> Result<UdpSocket, QueryError> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(|e| QueryError::Io(e.to_string()))?;
+        socket
+            .connect(&self.addr)
+            .await
+            .map_err(|e| QueryError::ConnectFailed(e.to_string()))?;
+        Ok(socket)
+    }
+
+    /// Requests a challenge token, which the server accepts for about 30 seconds.
+    async fn handshake(&self, socket: &UdpSocket) -> Result<i32, QueryError> {
+        let request = self.request(TYPE_HANDSHAKE, &[]);
+        let response = self.exchange(socket, &request, TYPE_HANDSHAKE).await?;
+        parse_challenge(&response)
+    }
+
+    fn request(&self, kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(7 + payload.len());
+        buf.extend_from_slice(&MAGIC);
+        buf.push(kind);
+        buf.extend_from_slice(&self.session_id.to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    /// Sends `request` and returns the response body after its type and session id.
+    async fn exchange(
+        &self,
+        socket: &UdpSocket,
+        request: &[u8],
+        kind: u8,
+    ) -> Result<Vec<u8>, QueryError> {
+        socket
+            .send(request)
+            .await
+            .map_err(|e| QueryError::Io(e.to_string()))?;
+
+        let mut buf = vec![0u8; MAX_DATAGRAM_LEN];
+        loop {
+            let len = timeout(self.timeout, socket.recv(&mut buf))
+                .await
+                .map_err(|_| QueryError::Timeout)?
+                .map_err(|e| QueryError::Io(e.to_string()))?;
+
+            let datagram = &buf[..len];
+            if datagram.len() < 5 {
+                return Err(QueryError::Protocol("datagram too short".to_string()));
+            }
+
+            // Late answers to an earlier request are skipped.
+            let session_id = i32::from_be_bytes(datagram[1..5].try_into().unwrap());
+            if datagram[0] == kind && session_id == self.session_id {
+                return Ok(datagram[5..].to_vec());
+            }
+        }
+    }
+}
+
+/// Parses the challenge token of a handshake response, after its type and session id.
+fn parse_challenge(body: &[u8]) -> Result<i32, QueryError> {
+    let mut cursor = body;
+    let token = read_cstring(&mut cursor)?;
+    token
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| QueryError::InvalidResponse(format!("invalid challenge token {}", token)))
+}
+
+/// Parses the body of a basic stat response, after its type and session id.
+pub fn parse_basic_stat(body: &[u8]) -> Result<BasicStat, QueryError> {
+    let mut cursor = body;
+
+    let motd = read_cstring(&mut cursor)?;
+    let game_type = read_cstring(&mut cursor)?;
+    let map = read_cstring(&mut cursor)?;
+    let num_players = parse_number(&read_cstring(&mut cursor)?)?;
+    let max_players = parse_number(&read_cstring(&mut cursor)?)?;
+
+    // The port is the only little endian value in the protocol.
+    if cursor.len() < 2 {
+        return Err(QueryError::Protocol("truncated host port".to_string()));
+    }
+    let host_port = u16::from_le_bytes([cursor[0], cursor[1]]);
+    cursor = &cursor[2..];
+    let host_ip = read_cstring(&mut cursor)?;
+
+    Ok(BasicStat {
+        motd,
+        game_type,
+        map,
+        num_players,
+        max_players,
+        host_port,
+        host_ip,
+    })
+}
+
+/// Parses the body of a full stat response, after its type and session id.
+pub fn parse_full_stat(body: &[u8]) -> Result<FullStat, QueryError> {
+    let mut cursor = body
+        .get(FULL_STAT_PADDING..)
+        .ok_or_else(|| QueryError::Protocol("truncated full stat".to_string()))?;
+
+    let mut raw = BTreeMap::new();
+    loop {
+        let key = read_cstring(&mut cursor)?;
+        if key.is_empty() {
+            break;
+        }
+        let value = read_cstring(&mut cursor)?;
+        raw.insert(key, value);
+    }
+
+    cursor = cursor
+        .get(PLAYER_SECTION_PADDING..)
+        .ok_or_else(|| QueryError::Protocol("truncated player section".to_string()))?;
+
+    let mut players = Vec::new();
+    while !cursor.is_empty() {
+        let player = read_cstring(&mut cursor)?;
+        if player.is_empty() {
+            break;
+        }
+        players.push(player);
+    }
+
+    let field = |key: &str| raw.get(key).cloned().unwrap_or_default();
+    let (server_mod, plugins) = parse_plugins(&field("plugins"));
+
+    Ok(FullStat {
+        motd: field("hostname"),
+        game_type: field("gametype"),
+        game_id: field("game_id"),
+        version: field("version"),
+        server_mod,
+        plugins,
+        map: field("map"),
+        num_players: parse_number(&field("numplayers"))?,
+        max_players: parse_number(&field("maxplayers"))?,
+        host_port: parse_number(&field("hostport"))?,
+        host_ip: field("hostip"),
+        players,
+        raw,
+    })
+}
+
+/// Splits `Paper on 1.20.4: PluginA 1.0; PluginB 2.1` into the server software and plugins.
+fn parse_plugins(value: &str) -> (String, Vec<String>) {
+    match value.split_once(':') {
+        Some((server_mod, plugins)) => (
+            server_mod.trim().to_string(),
+            plugins
+                .split(';')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_string)
+                .collect(),
+        ),
+        None => (value.trim().to_string(), Vec::new()),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, QueryError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| QueryError::InvalidResponse(format!("invalid number {}", value)))
+}
+
+/// Reads a null-terminated string; the server encodes text as ISO-8859-1.
+fn read_cstring(cursor: &mut &[u8]) -> Result<String, QueryError> {
+    let end = cursor
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| QueryError::Protocol("unterminated string".to_string()))?;
+
+    let text = cursor[..end].iter().map(|b| *b as char).collect();
+    *cursor = &cursor[end + 1..];
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Body of a captured datagram, after its type and session id as `exchange` returns it.
+    fn body(datagram: &[u8]) -> &[u8] {
+        &datagram[5..]
+    }
+
+    #[test]
+    fn parses_challenge_tokens() {
+        let handshake = include_bytes!("../../tests/fixtures/query/handshake.bin");
+        assert_eq!(parse_challenge(body(handshake)).unwrap(), 9513307);
+
+        let negative = include_bytes!("../../tests/fixtures/query/handshake_negative.bin");
+        assert_eq!(parse_challenge(body(negative)).unwrap(), -1393281547);
+
+        assert!(parse_challenge(b"9513307").is_err());
+    }
+
+    #[test]
+    fn parses_basic_stat() {
+        let datagram = include_bytes!("../../tests/fixtures/query/basic_stat.bin");
+        let stat = parse_basic_stat(body(datagram)).unwrap();
+
+        assert_eq!(
+            stat,
+            BasicStat {
+                motd: "§aWelcome to §lMineGuard".to_string(),
+                game_type: "SMP".to_string(),
+                map: "world".to_string(),
+                num_players: 2,
+                max_players: 20,
+                host_port: 25565,
+                host_ip: "127.0.0.1".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_vanilla_full_stat() {
+        let datagram = include_bytes!("../../tests/fixtures/query/full_stat.bin");
+        let stat = parse_full_stat(body(datagram)).unwrap();
+
+        assert_eq!(stat.motd, "A Minecraft Server");
+        assert_eq!(stat.game_type, "SMP");
+        assert_eq!(stat.game_id, "MINECRAFT");
+        assert_eq!(stat.version, "1.21.1");
+        assert_eq!(stat.server_mod, "");
+        assert!(stat.plugins.is_empty());
+        assert_eq!(stat.map, "world");
+        assert_eq!((stat.num_players, stat.max_players), (2, 20));
+        assert_eq!(stat.host_port, 25565);
+        assert_eq!(stat.host_ip, "127.0.0.1");
+        assert_eq!(stat.players, ["Notch", "jeb_"]);
+        assert_eq!(stat.raw.len(), 10);
+    }
+
+    #[test]
+    fn parses_full_stat_with_plugins_and_no_players() {
+        let datagram = include_bytes!("../../tests/fixtures/query/full_stat_paper.bin");
+        let stat = parse_full_stat(body(datagram)).unwrap();
+
+        assert_eq!(stat.server_mod, "Paper on 1.20.4-R0.1-SNAPSHOT");
+        assert_eq!(stat.plugins, ["EssentialsX 2.20.1", "LuckPerms 5.4.102"]);
+        assert_eq!(stat.num_players, 0);
+        assert!(stat.players.is_empty());
+    }
+
+    #[test]
+    fn rejects_truncated_full_stat() {
+        let datagram = include_bytes!("../../tests/fixtures/query/full_stat.bin");
+        let truncated = &body(datagram)[..FULL_STAT_PADDING + 20];
+
+        assert!(matches!(
+            parse_full_stat(truncated),
+            Err(QueryError::Protocol(_))
+        ));
+    }
+}
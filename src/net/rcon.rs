@@ -0,0 +1,437 @@
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::error::RconError;
+
+const PACKET_AUTH: i32 = 3;
+const PACKET_AUTH_RESPONSE: i32 = 2;
+const PACKET_EXEC_COMMAND: i32 = 2;
+const PACKET_RESPONSE_VALUE: i32 = 0;
+/// Minecraft answers unknown packet types with `Unknown request`, which marks the end
+/// of a fragmented response.
+const PACKET_SENTINEL: i32 = 100;
+
+/// Longest command body the vanilla server accepts.
+pub const MAX_COMMAND_LEN: usize = 1446;
+/// Largest payload the server sends in one packet; longer responses are fragmented.
+const MAX_RESPONSE_PAYLOAD: usize = 4096;
+/// Size of id, type and the two trailing null bytes.
+const PACKET_OVERHEAD: usize = 10;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        let len = (self.body.len() + PACKET_OVERHEAD) as i32;
+
+        let mut buf = Vec::with_capacity(self.body.len() + PACKET_OVERHEAD + 4);
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(&self.id.to_le_bytes());
+        buf.extend_from_slice(&self.kind.to_le_bytes());
+        buf.extend_from_slice(self.body.as_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf
+    }
+
+    fn decode(payload: &[u8]) -> Result<Self, RconError> {
+        if payload.len() < PACKET_OVERHEAD {
+            return Err(RconError::Protocol("packet too short".to_string()));
+        }
+
+        let id = i32::from_le_bytes(payload[0..4].try_into().unwrap());
+        let kind = i32::from_le_bytes(payload[4..8].try_into().unwrap());
+        let body = &payload[8..];
+        let end = body.iter().position(|b| *b == 0).unwrap_or(body.len());
+
+        Ok(Self {
+            id,
+            kind,
+            body: String::from_utf8_lossy(&body[..end]).to_string(),
+        })
+    }
+}
+
+/// Client for the Source RCON protocol as implemented by the Minecraft server.
+///
+/// The connection is opened lazily. A command whose packet cannot be written because the
+/// server closed the connection, for example on a restart, is sent again over a new one.
+/// Failures after the command was written are returned instead, as running it twice
+/// could repeat its effects.
+#[derive(Debug)]
+pub struct RconClient {
+    addr: String,
+    password: String,
+    timeout: Duration,
+    stream: Option<TcpStream>,
+    next_id: i32,
+}
+
+impl RconClient {
+    pub fn new<A: Into<String>, P: Into<String>>(addr: A, password: P) -> Self {
+        Self {
+            addr: addr.into(),
+            password: password.into(),
+            timeout: DEFAULT_TIMEOUT,
+            stream: None,
+            next_id: 1,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Opens the TCP connection and authenticates with the password.
+    pub async fn connect(&mut self) -> Result<(), RconError> {
+        self.stream = None;
+
+        let stream = timeout(self.timeout, TcpStream::connect(&self.addr))
+            .await
+            .map_err(|_| RconError::Timeout)?
+            .map_err(|e| RconError::ConnectFailed(e.to_string()))?;
+        self.stream = Some(stream);
+
+        let id = self.next_id();
+        let password = self.password.clone();
+        self.send(id, PACKET_AUTH, &password).await?;
+
+        loop {
+            let packet = self.receive().await?;
+            match packet.kind {
+                // Source servers send an empty response value before the auth response.
+                PACKET_RESPONSE_VALUE => continue,
+                PACKET_AUTH_RESPONSE if packet.id == -1 => {
+                    self.stream = None;
+                    return Err(RconError::AuthFailed);
+                }
+                PACKET_AUTH_RESPONSE if packet.id == id => return Ok(()),
+                _ => {
+                    self.stream = None;
+                    return Err(RconError::Protocol(format!(
+                        "unexpected packet type {} during auth",
+                        packet.kind
+                    )));
+                }
+            }
+        }
+    }
+
+    pub fn disconnect(&mut self) {
+        self.stream = None;
+    }
+
+    /// Runs `command` and returns the complete response text, joining fragmented packets.
+    pub async fn execute(&mut self, command: &str) -> Result<String, RconError> {
+        if command.len() > MAX_COMMAND_LEN {
+            return Err(RconError::CommandTooLong(command.len()));
+        }
+
+        let reused = self.stream.is_some();
+        if !reused {
+            self.connect().await?;
+        }
+
+        let id = self.next_id();
+        match self.send(id, PACKET_EXEC_COMMAND, command).await {
+            // Nothing reached the server, so the command can safely be sent again.
+            Err(RconError::Io(_) | RconError::Timeout) if reused => {
+                self.connect().await?;
+                self.send(id, PACKET_EXEC_COMMAND, command).await?;
+            }
+            res => res?,
+        }
+
+        let sentinel = self.next_id();
+        self.send(sentinel, PACKET_SENTINEL, "").await?;
+
+        let mut response = String::new();
+        loop {
+            let packet = self.receive().await?;
+            if packet.id == sentinel {
+                return Ok(response);
+            }
+            if packet.id == id {
+                response.push_str(&packet.body);
+            }
+        }
+    }
+
+    fn next_id(&mut self) -> i32 {
+        let id = self.next_id;
+        // Ids must stay positive since -1 signals an authentication failure.
+        self.next_id = if id == i32::MAX { 1 } else { id + 1 };
+        id
+    }
+
+    async fn send(&mut self, id: i32, kind: i32, body: &str) -> Result<(), RconError> {
+        let stream = self.stream.as_mut().ok_or(RconError::NotConnected)?;
+        let packet = Packet {
+            id,
+            kind,
+            body: body.to_string(),
+        };
+
+        let res = timeout(self.timeout, stream.write_all(&packet.encode())).await;
+        self.check_io(res)
+    }
+
+    async fn receive(&mut self) -> Result<Packet, RconError> {
+        let stream = self.stream.as_mut().ok_or(RconError::NotConnected)?;
+        let duration = self.timeout;
+
+        let res = timeout(duration, async {
+            let len = stream.read_i32_le().await?;
+            if len < PACKET_OVERHEAD as i32 || len as usize > MAX_RESPONSE_PAYLOAD + PACKET_OVERHEAD
+            {
+                return Ok(Err(RconError::Protocol(format!(
+                    "invalid packet length {}",
+                    len
+                ))));
+            }
+
+            let mut payload = vec![0; len as usize];
+            stream.read_exact(&mut payload).await?;
+            Ok(Packet::decode(&payload))
+        })
+        .await;
+
+        let packet = self.check_io(res)?;
+        if packet.is_err() {
+            self.stream = None;
+        }
+        packet
+    }
+
+    /// Flattens a timed out IO result, dropping the connection on any failure.
+    fn check_io<T>(
+        &mut self,
+        res: Result<std::io::Result<T>, tokio::time::error::Elapsed>,
+    ) -> Result<T, RconError> {
+        match res {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(err)) => {
+                self.stream = None;
+                Err(RconError::Io(err.to_string()))
+            }
+            Err(_) => {
+                self.stream = None;
+                Err(RconError::Timeout)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        os::fd::AsRawFd,
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const PASSWORD: &str = "hunter2";
+
+    /// What the stand-in does after reading a command.
+    #[derive(Debug, Clone, Copy)]
+    enum Behavior {
+        Answer,
+        /// Resets every connection right after authenticating it, once.
+        ResetFirstConnection,
+        /// Closes the connection without answering.
+        HangUp,
+    }
+
+    /// In-process RCON server that answers `echo <n>` with `n` bytes and records the
+    /// commands it receives.
+    struct StandIn {
+        addr: SocketAddr,
+        commands: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl StandIn {
+        async fn start(behavior: Behavior) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let commands = Arc::new(Mutex::new(Vec::new()));
+
+            let log = commands.clone();
+            tokio::spawn(async move {
+                let mut first = true;
+                while let Ok((stream, _)) = listener.accept().await {
+                    let reset = first && matches!(behavior, Behavior::ResetFirstConnection);
+                    first = false;
+                    tokio::spawn(serve(stream, behavior, reset, log.clone()));
+                }
+            });
+
+            Self { addr, commands }
+        }
+
+        fn client(&self) -> RconClient {
+            RconClient::new(self.addr.to_string(), PASSWORD).with_timeout(Duration::from_secs(2))
+        }
+
+        fn commands(&self) -> Vec<String> {
+            self.commands.lock().unwrap().clone()
+        }
+    }
+
+    async fn read_packet(stream: &mut TcpStream) -> Option<Packet> {
+        let len = stream.read_i32_le().await.ok()?;
+        let mut payload = vec![0; len as usize];
+        stream.read_exact(&mut payload).await.ok()?;
+        Packet::decode(&payload).ok()
+    }
+
+    async fn write_packet(stream: &mut TcpStream, id: i32, kind: i32, body: &str) {
+        let packet = Packet {
+            id,
+            kind,
+            body: body.to_string(),
+        };
+        stream.write_all(&packet.encode()).await.unwrap();
+    }
+
+    /// Drops `stream` with a RST instead of a FIN, so the next write to it fails.
+    fn reset(stream: TcpStream) {
+        let linger = libc::linger {
+            l_onoff: 1,
+            l_linger: 0,
+        };
+        // SAFETY: the descriptor is open and `linger` outlives the call.
+        unsafe {
+            libc::setsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_LINGER,
+                (&raw const linger).cast(),
+                size_of::<libc::linger>() as libc::socklen_t,
+            );
+        }
+    }
+
+    async fn serve(
+        mut stream: TcpStream,
+        behavior: Behavior,
+        reset_after_auth: bool,
+        commands: Arc<Mutex<Vec<String>>>,
+    ) {
+        let Some(auth) = read_packet(&mut stream).await else {
+            return;
+        };
+        let id = if auth.body == PASSWORD { auth.id } else { -1 };
+        write_packet(&mut stream, auth.id, PACKET_RESPONSE_VALUE, "").await;
+        write_packet(&mut stream, id, PACKET_AUTH_RESPONSE, "").await;
+        if reset_after_auth {
+            reset(stream);
+            return;
+        }
+
+        while let Some(packet) = read_packet(&mut stream).await {
+            if packet.kind == PACKET_SENTINEL {
+                let body = format!("Unknown request {:x}", PACKET_SENTINEL);
+                write_packet(&mut stream, packet.id, PACKET_RESPONSE_VALUE, &body).await;
+                continue;
+            }
+
+            commands.lock().unwrap().push(packet.body.clone());
+            if matches!(behavior, Behavior::HangUp) {
+                return;
+            }
+
+            let len = packet
+                .body
+                .strip_prefix("echo ")
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+            let response = "x".repeat(len);
+            for chunk in response.as_bytes().chunks(MAX_RESPONSE_PAYLOAD) {
+                let chunk = std::str::from_utf8(chunk).unwrap();
+                write_packet(&mut stream, packet.id, PACKET_RESPONSE_VALUE, chunk).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_password() {
+        let server = StandIn::start(Behavior::Answer).await;
+        let mut client = RconClient::new(server.addr.to_string(), "wrong");
+
+        assert!(matches!(client.connect().await, Err(RconError::AuthFailed)));
+        assert!(!client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn joins_fragmented_responses() {
+        let server = StandIn::start(Behavior::Answer).await;
+        let mut client = server.client();
+
+        let response = client.execute("echo 10000").await.unwrap();
+        assert_eq!(response.len(), 10000);
+        assert_eq!(client.execute("echo 3").await.unwrap(), "xxx");
+        assert_eq!(client.execute("echo 0").await.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn resends_command_that_could_not_be_written() {
+        let server = StandIn::start(Behavior::ResetFirstConnection).await;
+        let mut client = server.client();
+
+        client.connect().await.unwrap();
+        // Let the reset arrive so that writing to the connection fails.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(client.execute("echo 2").await.unwrap(), "xx");
+        assert_eq!(server.commands(), ["echo 2"]);
+    }
+
+    #[tokio::test]
+    async fn does_not_repeat_command_after_it_was_written() {
+        let server = StandIn::start(Behavior::HangUp).await;
+        let mut client = server.client();
+
+        assert!(client.execute("op Notch").await.is_err());
+        assert!(!client.is_connected());
+        assert_eq!(server.commands(), ["op Notch"]);
+
+        // The next command reconnects.
+        assert!(client.execute("op jeb_").await.is_err());
+        assert_eq!(server.commands(), ["op Notch", "op jeb_"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_overlong_commands() {
+        let mut client = RconClient::new("127.0.0.1:1", PASSWORD);
+        let command = "x".repeat(MAX_COMMAND_LEN + 1);
+
+        assert!(matches!(
+            client.execute(&command).await,
+            Err(RconError::CommandTooLong(len)) if len == MAX_COMMAND_LEN + 1
+        ));
+    }
+}
@@ -1,13 +1,27 @@
+use std::sync::LazyLock;
+
 use regex::Regex;
 
 use crate::{
     config::{
         LogMeta,
-        stream::{EventPayload, InstanceEvent, InternalEvent, LogLevel},
+        stream::{InternalEvent, LogLevel},
     },
     error::ParserError,
 };
 
+#[cfg(all(feature = "events", feature = "mc-vanilla"))]
+mod rules;
+#[cfg(all(feature = "events", feature = "mc-vanilla"))]
+mod vanilla;
+
+#[cfg(all(feature = "events", feature = "mc-vanilla"))]
+pub use rules::RuleSet;
+#[cfg(all(feature = "events", feature = "mc-vanilla"))]
+pub use vanilla::{LogParser, parse_player_list, parse_tick_query};
+
+static STARTED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"Done \([0-9.]+s\)!").unwrap());
+
 impl LogMeta {
     pub fn parse_event(&self) -> Result<Option<InternalEvent>, ParserError> {
         if self.thread == "Server thread" && self.level == LogLevel::Info {
@@ -17,8 +31,7 @@ impl LogMeta {
     }
 
     fn parse_server_thread_info_lv2(&self) -> Result<Option<InternalEvent>, ParserError> {
-        let re = Regex::new(r"Done \([0-9.]+s\)!").unwrap();
-        if re.is_match(&self.msg) {
+        if STARTED.is_match(&self.msg) {
             return Ok(Some(InternalEvent::ServerStarted));
         }
         Ok(None)
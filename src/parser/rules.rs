@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::{
+    config::{
+        EventRule, LogMeta,
+        stream::{EventPayload, LogLevel},
+    },
+    error::ParserError,
+};
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    name: String,
+    thread: Option<Regex>,
+    level: Option<LogLevel>,
+    pattern: Regex,
+    fields: BTreeMap<String, String>,
+}
+
+/// `EventRule`s with their regexes compiled, built once per instance.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    pub fn compile(rules: &[EventRule]) -> Result<Self, ParserError> {
+        let invalid = |rule: &EventRule, err: regex::Error| ParserError::InvalidRule {
+            name: rule.name.clone(),
+            error: err.to_string(),
+        };
+
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                Ok(CompiledRule {
+                    name: rule.name.clone(),
+                    thread: rule
+                        .thread
+                        .as_deref()
+                        .map(Regex::new)
+                        .transpose()
+                        .map_err(|e| invalid(rule, e))?,
+                    level: rule.level.clone(),
+                    pattern: Regex::new(&rule.pattern).map_err(|e| invalid(rule, e))?,
+                    fields: rule.fields.clone(),
+                })
+            })
+            .collect::<Result<_, ParserError>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// `Custom` payloads of every rule matching a line. `meta` is `None` for lines
+    /// without the log prefix, which only rules without thread or level can match.
+    pub fn apply(&self, meta: Option<&LogMeta>, line: &str) -> Vec<EventPayload> {
+        let msg = meta.map_or(line, |meta| meta.msg.as_str());
+
+        self.rules
+            .iter()
+            .filter(|rule| match meta {
+                Some(meta) => {
+                    rule.thread
+                        .as_ref()
+                        .is_none_or(|t| t.is_match(&meta.thread))
+                        && rule.level.as_ref().is_none_or(|l| *l == meta.level)
+                }
+                None => rule.thread.is_none() && rule.level.is_none(),
+            })
+            .filter_map(|rule| {
+                let caps = rule.pattern.captures(msg)?;
+
+                let fields = if rule.fields.is_empty() {
+                    rule.pattern
+                        .capture_names()
+                        .flatten()
+                        .filter_map(|name| {
+                            caps.name(name)
+                                .map(|m| (name.to_string(), m.as_str().to_string()))
+                        })
+                        .collect()
+                } else {
+                    rule.fields
+                        .iter()
+                        .map(|(key, template)| {
+                            let mut value = String::new();
+                            caps.expand(template, &mut value);
+                            (key.clone(), value)
+                        })
+                        .collect()
+                };
+
+                Some(EventPayload::Custom {
+                    name: rule.name.clone(),
+                    fields,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(line: &str) -> LogMeta {
+        LogMeta::new(line).unwrap().unwrap()
+    }
+
+    fn custom(name: &str, fields: &[(&str, &str)]) -> EventPayload {
+        EventPayload::Custom {
+            name: name.to_string(),
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn filters_by_thread_and_level() {
+        let rules = RuleSet::compile(&[
+            EventRule::new("any", "^saved$"),
+            EventRule::new("server", "^saved$").with_thread("^Server thread$"),
+            EventRule::new("worker", "^saved$").with_thread("^Worker-"),
+            EventRule::new("warn", "^saved$").with_level(LogLevel::Warn),
+            EventRule::new("info", "^saved$")
+                .with_thread("thread")
+                .with_level(LogLevel::Info),
+        ])
+        .unwrap();
+
+        let names = |meta: Option<&LogMeta>, line: &str| -> Vec<String> {
+            rules
+                .apply(meta, line)
+                .into_iter()
+                .map(|payload| match payload {
+                    EventPayload::Custom { name, .. } => name,
+                    other => panic!("unexpected payload {other:?}"),
+                })
+                .collect()
+        };
+
+        let server_info = meta("[12:00:00] [Server thread/INFO]: saved");
+        assert_eq!(
+            names(Some(&server_info), "unused"),
+            ["any", "server", "info"]
+        );
+
+        let worker_warn = meta("[12:00:00] [Worker-Main-3/WARN]: saved");
+        assert_eq!(
+            names(Some(&worker_warn), "unused"),
+            ["any", "worker", "warn"]
+        );
+
+        // Lines without the log prefix only reach rules without thread or level.
+        assert_eq!(names(None, "saved"), ["any"]);
+    }
+
+    #[test]
+    fn matches_the_message_not_the_prefix() {
+        let rules = RuleSet::compile(&[EventRule::new("thread", "Server thread")]).unwrap();
+        let line = "[12:00:00] [Server thread/INFO]: Stopping server";
+        assert!(rules.apply(Some(&meta(line)), line).is_empty());
+    }
+
+    #[test]
+    fn named_captures_become_fields() {
+        let rules = RuleSet::compile(&[EventRule::new(
+            "advancement",
+            r"^(?P<player>\w+) has made the advancement \[(?P<advancement>[^\]]+)\](?P<suffix>!)?$",
+        )])
+        .unwrap();
+        let line = "[12:00:00] [Server thread/INFO]: Notch has made the advancement [Stone Age]";
+
+        assert_eq!(
+            rules.apply(Some(&meta(line)), line),
+            [custom(
+                "advancement",
+                &[("advancement", "Stone Age"), ("player", "Notch")]
+            )]
+        );
+    }
+
+    #[test]
+    fn expands_field_templates() {
+        let rules = RuleSet::compile(&[EventRule::new(
+            "tps",
+            r"^Running (?P<behind>\d+)ms or (?P<ticks>\d+) ticks behind(?P<note> \(.*\))?$",
+        )
+        .with_field("summary", "${behind}ms/${ticks}t")
+        .with_field("ticks", "$ticks")
+        .with_field("note", "[${note}]")
+        .with_field("unknown", "${nope}")])
+        .unwrap();
+        let line = "[12:00:00] [Server thread/WARN]: Running 2013ms or 40 ticks behind";
+
+        assert_eq!(
+            rules.apply(Some(&meta(line)), line),
+            [custom(
+                "tps",
+                &[
+                    ("note", "[]"),
+                    ("summary", "2013ms/40t"),
+                    ("ticks", "40"),
+                    ("unknown", ""),
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn emits_matches_in_rule_order() {
+        let rules = RuleSet::compile(&[
+            EventRule::new("second", "b"),
+            EventRule::new("first", "a"),
+            EventRule::new("never", "z"),
+            EventRule::new("third", "ab"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            rules.apply(None, "abc"),
+            [
+                custom("second", &[]),
+                custom("first", &[]),
+                custom("third", &[])
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_regexes() {
+        let invalid = |rule: EventRule| match RuleSet::compile(&[EventRule::new("ok", "ok"), rule])
+        {
+            Err(ParserError::InvalidRule { name, .. }) => name,
+            other => panic!("expected an invalid rule, got {other:?}"),
+        };
+
+        assert_eq!(invalid(EventRule::new("pattern", "(unclosed")), "pattern");
+        assert_eq!(
+            invalid(EventRule::new("thread", "ok").with_thread("[")),
+            "thread"
+        );
+    }
+}
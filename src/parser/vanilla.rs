@@ -0,0 +1,464 @@
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
+
+use regex::Regex;
+use uuid::Uuid;
+
+use crate::config::{
+    LogMeta,
+    stream::{AdvancementKind, EventPayload, LogLevel, StopStage},
+};
+
+/// Players that authenticated or logged in without joining yet are forgotten past this.
+const MAX_PENDING_PLAYERS: usize = 64;
+
+static AUTHENTICATED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^UUID of player (\S+) is ([0-9a-fA-F-]{32,36})$").unwrap());
+static LOGGED_IN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\S+?)\[/?(.+?)\] logged in with entity id").unwrap());
+/// Since 1.20.2 a player who changed their name joins as `New (formerly known as Old)`.
+static JOINED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\S+)(?: \(formerly known as \S+\))? joined the game$").unwrap()
+});
+static LOST_CONNECTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\S+) lost connection: (.*)$").unwrap());
+static LEFT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(\S+) left the game$").unwrap());
+static CHAT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^<([^>\s]+)> (.*)$").unwrap());
+static SAY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[([^\]\s:]+)\] (.*)$").unwrap());
+static EMOTE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\* (\S+) (.*)$").unwrap());
+static ADVANCEMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(\S+) has (made the advancement|reached the goal|completed the challenge|just earned the achievement) \[(.+)\]$",
+    )
+    .unwrap()
+});
+/// Covers `Running 2345ms or 46 ticks behind` and the pre-1.12
+/// `Running 2345ms behind, skipping 46 tick(s)`.
+static LAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^Can't keep up!.*Running (\d+)ms (?:or (\d+) ticks behind|behind, skipping (\d+) tick)",
+    )
+    .unwrap()
+});
+/// Death messages start with the player name followed by one of the vanilla phrasings.
+static DEATH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^([A-Za-z0-9_]{1,16}) (was |were |fell |drowned|died|blew up|burned to death|went up in flames|went off with a bang|walked into|tried to swim in lava|hit the ground too hard|experienced kinetic energy|froze to death|starved to death|suffocated|withered away|discovered the floor was lava|didn't want to live|left the confines of this world)",
+    )
+    .unwrap()
+});
+
+/// `There are 2 of a max of 20 players online: A, B`, or before 1.13
+/// `There are 2/20 players online:` with the names on the following line.
+static PLAYER_LIST: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"There are (\d+) ?(?:of a max of |/)(\d+) players online:[ \t]*\n?(.*)").unwrap()
+});
+/// Second line of the `tick query` output, `Average time per tick: 2.3ms (Target: 50.0ms)`.
+static TICK_QUERY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"Average time per tick: ([0-9]+(?:\.[0-9]+)?)ms").unwrap());
+
+/// Turns vanilla server log lines into typed `EventPayload`s.
+///
+/// Joins are reported with the UUID and IP logged on the lines before them, so the
+/// parser keeps a little state and must see every line of a run in order.
+#[derive(Debug, Default)]
+pub struct LogParser {
+    uuids: HashMap<String, Uuid>,
+    ips: HashMap<String, String>,
+    disconnects: HashMap<String, String>,
+}
+
+impl LogParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(&mut self, meta: &LogMeta) -> Option<EventPayload> {
+        let msg = meta.msg.as_str();
+
+        if meta.thread.starts_with("User Authenticator") {
+            if let Some(caps) = AUTHENTICATED.captures(msg)
+                && let Ok(uuid) = Uuid::parse_str(&caps[2])
+            {
+                remember(&mut self.uuids, &caps[1], uuid);
+            }
+            return None;
+        }
+
+        match meta.level {
+            LogLevel::Info => self.parse_info(msg),
+            LogLevel::Warn => parse_warn(msg),
+            _ => None,
+        }
+    }
+
+    fn parse_info(&mut self, msg: &str) -> Option<EventPayload> {
+        // Chat from 1.19 on is marked when the message signature could not be verified.
+        let msg = msg.strip_prefix("[Not Secure] ").unwrap_or(msg);
+
+        if let Some(caps) = CHAT.captures(msg) {
+            return Some(EventPayload::Chat {
+                sender: caps[1].to_string(),
+                message: caps[2].to_string(),
+            });
+        }
+        if let Some(caps) = SAY.captures(msg) {
+            return Some(EventPayload::Say {
+                source: caps[1].to_string(),
+                message: caps[2].to_string(),
+            });
+        }
+        if let Some(caps) = EMOTE.captures(msg) {
+            return Some(EventPayload::Emote {
+                name: caps[1].to_string(),
+                action: caps[2].to_string(),
+            });
+        }
+
+        if let Some(caps) = LOGGED_IN.captures(msg) {
+            let ip = strip_port(&caps[2]);
+            remember(&mut self.ips, &caps[1], ip.to_string());
+            return None;
+        }
+        if let Some(caps) = JOINED.captures(msg) {
+            let name = &caps[1];
+            return Some(EventPayload::PlayerJoined {
+                name: name.to_string(),
+                uuid: self.uuids.remove(name),
+                ip: self.ips.remove(name),
+            });
+        }
+        if let Some(caps) = LOST_CONNECTION.captures(msg) {
+            let name = &caps[1];
+            self.uuids.remove(name);
+            self.ips.remove(name);
+            remember(&mut self.disconnects, name, caps[2].to_string());
+            return None;
+        }
+        if let Some(caps) = LEFT.captures(msg) {
+            let name = &caps[1];
+            return Some(EventPayload::PlayerLeft {
+                name: name.to_string(),
+                reason: self.disconnects.remove(name),
+            });
+        }
+
+        if let Some(caps) = ADVANCEMENT.captures(msg) {
+            let kind = match &caps[2] {
+                "made the advancement" => AdvancementKind::Advancement,
+                "reached the goal" => AdvancementKind::Goal,
+                "completed the challenge" => AdvancementKind::Challenge,
+                _ => AdvancementKind::Achievement,
+            };
+            return Some(EventPayload::Advancement {
+                name: caps[1].to_string(),
+                kind,
+                title: caps[3].to_string(),
+            });
+        }
+
+        if let Some(stage) = parse_stop_stage(msg) {
+            return Some(EventPayload::Stopping { stage });
+        }
+        if msg == "Saved the game" || msg == "Saved the world" {
+            return Some(EventPayload::WorldSaved);
+        }
+
+        if let Some(caps) = DEATH.captures(msg) {
+            return Some(EventPayload::PlayerDied {
+                name: caps[1].to_string(),
+                message: msg.to_string(),
+            });
+        }
+
+        None
+    }
+}
+
+/// Names listed in the output of the `list` command, given as log messages or an RCON
+/// response. Returns `None` when the output is not a complete player list.
+pub fn parse_player_list(output: &str) -> Option<Vec<String>> {
+    let caps = PLAYER_LIST.captures(output)?;
+    let online: usize = caps[1].parse().ok()?;
+
+    let names: Vec<String> = caps[3]
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    (names.len() == online).then_some(names)
+}
+
+/// Average milliseconds per tick reported by the `tick query` command, from a console
+/// line or an RCON response.
+pub fn parse_tick_query(output: &str) -> Option<f64> {
+    TICK_QUERY.captures(output)?[1].parse().ok()
+}
+
+fn parse_warn(msg: &str) -> Option<EventPayload> {
+    let caps = LAG.captures(msg)?;
+    let behind = caps[1].parse().ok()?;
+    let ticks = caps
+        .get(2)
+        .or_else(|| caps.get(3))
+        .and_then(|ticks| ticks.as_str().parse().ok())
+        .unwrap_or_default();
+
+    Some(EventPayload::LagWarning {
+        behind: Duration::from_millis(behind),
+        ticks,
+    })
+}
+
+fn parse_stop_stage(msg: &str) -> Option<StopStage> {
+    match msg {
+        "Stopping the server" => Some(StopStage::Requested),
+        "Stopping server" => Some(StopStage::Stopping),
+        "Saving players" => Some(StopStage::SavingPlayers),
+        "Saving worlds" => Some(StopStage::SavingWorlds),
+        _ if msg.ends_with("All dimensions are saved") => Some(StopStage::Saved),
+        _ => None,
+    }
+}
+
+/// Turns `127.0.0.1:53412` or `0:0:0:0:0:0:0:1:53412` into the address alone.
+fn strip_port(addr: &str) -> &str {
+    addr.rsplit_once(':').map_or(addr, |(ip, _)| ip)
+}
+
+fn remember<V>(pending: &mut HashMap<String, V>, name: &str, value: V) {
+    if pending.len() >= MAX_PENDING_PLAYERS && !pending.contains_key(name) {
+        pending.clear();
+    }
+    pending.insert(name.to_string(), value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTCH: &str = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+    /// Disconnect reasons were logged as the raw chat component before 1.9.
+    const LEGACY_DISCONNECT: &str = "TextComponent{text='Disconnected', siblings=[], \
+        style=Style{hasParent=false, color=null, bold=null, italic=null, underlined=null, \
+        obfuscated=null, clickEvent=null, hoverEvent=null, insertion=null}}";
+
+    /// Every event the parser emits for a log, in order.
+    fn parse_log(log: &str) -> Vec<EventPayload> {
+        let mut parser = LogParser::new();
+        log.lines()
+            .filter_map(|line| LogMeta::new(line).unwrap())
+            .filter_map(|meta| parser.parse(&meta))
+            .collect()
+    }
+
+    fn joined(name: &str, uuid: &str, ip: &str) -> EventPayload {
+        EventPayload::PlayerJoined {
+            name: name.to_string(),
+            uuid: Some(Uuid::parse_str(uuid).unwrap()),
+            ip: Some(ip.to_string()),
+        }
+    }
+
+    fn left(name: &str, reason: &str) -> EventPayload {
+        EventPayload::PlayerLeft {
+            name: name.to_string(),
+            reason: Some(reason.to_string()),
+        }
+    }
+
+    fn chat(sender: &str, message: &str) -> EventPayload {
+        EventPayload::Chat {
+            sender: sender.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn say(source: &str, message: &str) -> EventPayload {
+        EventPayload::Say {
+            source: source.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn emote(name: &str, action: &str) -> EventPayload {
+        EventPayload::Emote {
+            name: name.to_string(),
+            action: action.to_string(),
+        }
+    }
+
+    fn died(name: &str, message: &str) -> EventPayload {
+        EventPayload::PlayerDied {
+            name: name.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn advancement(name: &str, kind: AdvancementKind, title: &str) -> EventPayload {
+        EventPayload::Advancement {
+            name: name.to_string(),
+            kind,
+            title: title.to_string(),
+        }
+    }
+
+    fn lag(behind_ms: u64, ticks: u64) -> EventPayload {
+        EventPayload::LagWarning {
+            behind: Duration::from_millis(behind_ms),
+            ticks,
+        }
+    }
+
+    fn stopping(stages: &[StopStage]) -> Vec<EventPayload> {
+        stages
+            .iter()
+            .map(|&stage| EventPayload::Stopping { stage })
+            .collect()
+    }
+
+    const STOP_SEQUENCE: &[StopStage] = &[
+        StopStage::Requested,
+        StopStage::Stopping,
+        StopStage::SavingPlayers,
+        StopStage::SavingWorlds,
+    ];
+
+    #[test]
+    fn parses_1_8_9_log() {
+        let events = parse_log(include_str!("../../tests/fixtures/logs/1.8.9.log"));
+
+        let mut expected = vec![
+            joined("Notch", NOTCH, "127.0.0.1"),
+            chat("Notch", "hello world"),
+            say("Server", "restart in 5 minutes"),
+            emote("Notch", "waves"),
+            advancement("Notch", AdvancementKind::Achievement, "Taking Inventory"),
+            died("Notch", "Notch was slain by Zombie"),
+            lag(2345, 46),
+            left("Notch", LEGACY_DISCONNECT),
+            EventPayload::WorldSaved,
+        ];
+        expected.extend(stopping(STOP_SEQUENCE));
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn parses_1_12_2_log() {
+        let events = parse_log(include_str!("../../tests/fixtures/logs/1.12.2.log"));
+
+        let mut expected = vec![
+            joined(
+                "jeb_",
+                "853c80ef-3c37-49fd-aa49-938b674adae6",
+                "192.168.1.20",
+            ),
+            chat("jeb_", "anyone around?"),
+            advancement("jeb_", AdvancementKind::Advancement, "Stone Age"),
+            died("jeb_", "jeb_ fell from a high place"),
+            lag(2034, 40),
+            left("jeb_", "Disconnected"),
+        ];
+        expected.extend(stopping(STOP_SEQUENCE));
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn parses_1_16_5_log() {
+        let events = parse_log(include_str!("../../tests/fixtures/logs/1.16.5.log"));
+
+        let mut expected = vec![
+            joined(
+                "Dinnerbone",
+                "61699b2e-d327-4a01-9f1e-0ea8c3f06bc6",
+                "10.0.0.7",
+            ),
+            say("Dinnerbone", "is anyone on?"),
+            emote("Dinnerbone", "is building a farm"),
+            advancement("Dinnerbone", AdvancementKind::Goal, "Sky's the Limit"),
+            advancement("Dinnerbone", AdvancementKind::Challenge, "Return to Sender"),
+            died("Dinnerbone", "Dinnerbone was blown up by Creeper"),
+            left("Dinnerbone", "Disconnected"),
+            EventPayload::WorldSaved,
+        ];
+        expected.extend(stopping(STOP_SEQUENCE));
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn parses_1_19_4_log_with_unsigned_chat() {
+        let events = parse_log(include_str!("../../tests/fixtures/logs/1.19.4.log"));
+
+        let mut expected = vec![
+            joined(
+                "Grumm",
+                "e6b5c088-0680-44df-9e1b-9bf11792291b",
+                "172.17.0.1",
+            ),
+            chat("Grumm", "is this thing on"),
+            chat("Grumm", "signed this time"),
+            say("Grumm", "brb"),
+            emote("Grumm", "waves"),
+            died("Grumm", "Grumm drowned"),
+            died(
+                "Grumm",
+                "Grumm hit the ground too hard whilst trying to escape Skeleton",
+            ),
+            lag(3150, 63),
+            left("Grumm", "Timed out"),
+        ];
+        expected.extend(stopping(STOP_SEQUENCE));
+        expected.extend(stopping(&[StopStage::Saved]));
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn parses_1_21_1_log_with_renamed_player() {
+        let events = parse_log(include_str!("../../tests/fixtures/logs/1.21.1.log"));
+
+        let mut expected = vec![
+            joined("Notch", NOTCH, "203.0.113.9"),
+            chat("Notch", "hi all"),
+            say("Rcon", "backup starting"),
+            advancement("Notch", AdvancementKind::Advancement, "Monster Hunter"),
+            died("Notch", "Notch was slain by Zombie using [Iron Sword]"),
+            lag(5012, 100),
+            EventPayload::WorldSaved,
+            left("Notch", "Disconnected"),
+        ];
+        expected.extend(stopping(STOP_SEQUENCE));
+        expected.extend(stopping(&[StopStage::Saved]));
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn join_without_login_lines_has_no_uuid_or_ip() {
+        let events = parse_log("[12:00:00] [Server thread/INFO]: Alex joined the game");
+
+        assert_eq!(
+            events,
+            vec![EventPayload::PlayerJoined {
+                name: "Alex".to_string(),
+                uuid: None,
+                ip: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_player_list_formats() {
+        assert_eq!(
+            parse_player_list("There are 2 of a max of 20 players online: Notch, jeb_"),
+            Some(vec!["Notch".to_string(), "jeb_".to_string()])
+        );
+        assert_eq!(
+            parse_player_list("There are 1/20 players online:\nNotch"),
+            Some(vec!["Notch".to_string()])
+        );
+        assert_eq!(
+            parse_player_list("There are 0 of a max of 20 players online: "),
+            Some(Vec::new())
+        );
+    }
+}
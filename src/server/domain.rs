@@ -1,23 +1,46 @@
-use std::{ops::RangeInclusive, path::PathBuf, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use tokio::{
     fs::{File, create_dir, read, read_dir},
-    io::{self, AsyncWriteExt},
+    io::AsyncWriteExt,
     sync::{RwLock, watch},
 };
-use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
 use uuid::Uuid;
 
+#[cfg(feature = "cgroups")]
+use crate::config::CgroupLimits;
 use crate::{
-    config::{self, MinecraftType, MinecraftVersion, StreamSource, Version, stream::InstanceEvent},
-    error::{CreationError, ServerError, SubscribeError},
-    instance::InstanceHandle,
-    manifests::vanilla::{VanillaManifestV2, VanillaManifestV2Version, VanillaReleaseManifest},
-    server,
+    config::{
+        BacklogPolicy, EventRule, HealthPolicy, LaunchProfile, LogSinkPolicy, MetricsPolicy,
+        MinecraftType, MinecraftVersion, RestartPolicy, StreamSource, Version,
+        stream::InstanceEvent,
+    },
+    error::{
+        CreationError, ParserError, PingError, PropertiesError, QueryError, RconError, ServerError,
+        SubscribeError,
+    },
+    instance::{
+        BacklogStream, CaptureOptions, ExitInfo, InstanceHandle, InstanceStatus, LogRecord,
+        PlayerRoster, PlaytimeLedger, ResourceSample, ServerHealth, StopOutcome, wait_for_status,
+    },
+    java,
+    manifests::vanilla::{VanillaManifestV2, VanillaReleaseManifest},
+    net::{BasicStat, FullStat, QueryClient, RconClient, ServerStatus, ping},
 };
 
+use super::{PlayerLists, ServerProperties};
+
+const DEFAULT_SERVER_PORT: u16 = 25565;
+const DEFAULT_RCON_PORT: u16 = 25575;
+const RCON_PASSWORD_LEN: usize = 32;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MineGuardConfig {
     uuid: Uuid,
@@ -25,6 +48,32 @@ pub struct MineGuardConfig {
     pub jar_path: PathBuf,
     pub mc_version: MinecraftVersion,
     pub mc_type: MinecraftType,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    #[serde(default = "default_startup_timeout_secs")]
+    pub startup_timeout_secs: u64,
+    #[serde(default)]
+    pub launch: LaunchProfile,
+    /// Java major version required by the server, from the release manifest.
+    #[serde(default)]
+    pub java_major: Option<u32>,
+    #[serde(default)]
+    pub event_rules: Vec<EventRule>,
+    #[serde(default)]
+    pub log_sink: LogSinkPolicy,
+    #[serde(default)]
+    pub backlog: BacklogPolicy,
+    #[serde(default)]
+    pub metrics: MetricsPolicy,
+    #[serde(default)]
+    pub health: HealthPolicy,
+    #[cfg(feature = "cgroups")]
+    #[serde(default)]
+    pub cgroup: Option<CgroupLimits>,
+}
+
+fn default_startup_timeout_secs() -> u64 {
+    300
 }
 
 #[derive(Debug)]
@@ -33,6 +82,12 @@ pub struct MineGuardServer {
     pub config: RwLock<MineGuardConfig>,
 }
 
+impl Default for MineGuardConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MineGuardConfig {
     pub fn new() -> Self {
         Self {
@@ -41,8 +96,35 @@ impl MineGuardConfig {
             jar_path: PathBuf::new(),
             mc_version: MinecraftVersion::Release(Version::from_str("0.00.00").unwrap()),
             mc_type: MinecraftType::Vanilla,
+            restart: RestartPolicy::default(),
+            startup_timeout_secs: default_startup_timeout_secs(),
+            launch: LaunchProfile::default(),
+            java_major: None,
+            event_rules: Vec::new(),
+            log_sink: LogSinkPolicy::default(),
+            backlog: BacklogPolicy::default(),
+            metrics: MetricsPolicy::default(),
+            health: HealthPolicy::default(),
+            #[cfg(feature = "cgroups")]
+            cgroup: None,
         }
     }
+
+    /// Identifier of the instance, also the name of its directory.
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    pub fn startup_timeout(&self) -> Duration {
+        Duration::from_secs(self.startup_timeout_secs)
+    }
+
+    /// Java major version the server needs, estimated from `mc_version` when the
+    /// manifest did not provide one.
+    pub fn required_java_major(&self) -> u32 {
+        self.java_major
+            .unwrap_or_else(|| java::required_java_major(&self.mc_version))
+    }
 }
 
 impl MineGuardServer {
@@ -81,6 +163,7 @@ impl MineGuardServer {
             .map_err(|_| CreationError::DirectoryError)?;
 
         let mut url = String::new();
+        let mut java_major = None;
 
         if mc_type == MinecraftType::Vanilla {
             let vanilla_manifest = VanillaManifestV2::load()
@@ -100,6 +183,7 @@ impl MineGuardServer {
                 .map_err(|_| CreationError::ManifestError)?;
 
             url = release_manifest.server_url();
+            java_major = release_manifest.java_major();
         }
 
         let resp = reqwest::get(url)
@@ -116,21 +200,33 @@ impl MineGuardServer {
             .await
             .map_err(|_| CreationError::DirectoryError)?;
 
-        let config = MineGuardConfig {
-            uuid: uuid,
+        let mut config = MineGuardConfig {
+            uuid,
             server_dir: server_root,
             jar_path: jar_path_rel,
-            mc_version: mc_version,
-            mc_type: mc_type,
+            mc_version,
+            mc_type,
+            restart: RestartPolicy::default(),
+            startup_timeout_secs: default_startup_timeout_secs(),
+            launch: LaunchProfile::default(),
+            java_major,
+            event_rules: Vec::new(),
+            log_sink: LogSinkPolicy::default(),
+            backlog: BacklogPolicy::default(),
+            metrics: MetricsPolicy::default(),
+            health: HealthPolicy::default(),
+            #[cfg(feature = "cgroups")]
+            cgroup: None,
         };
 
-        let handle = InstanceHandle::new_with_params(
-            config.server_dir.clone(),
-            config.jar_path.clone(),
-            config.mc_version.clone(),
-            config.mc_type.clone(),
-        )
-        .map_err(|_| CreationError::CreationError)?;
+        // Fall back to `java` from PATH when no compatible runtime is installed, so
+        // the mismatch is reported when the server is started.
+        if let Ok(runtime) = java::find_runtime(config.required_java_major()).await {
+            config.launch.java = runtime.executable;
+        }
+
+        let handle = InstanceHandle::new_with_config(config.clone())
+            .map_err(|_| CreationError::CreationError)?;
 
         let server = MineGuardServer {
             config: RwLock::new(config),
@@ -140,21 +236,150 @@ impl MineGuardServer {
         Ok(server)
     }
 
+    pub async fn status(&self) -> InstanceStatus {
+        let handle_r = self.handle.read().await;
+        handle_r.status()
+    }
+
+    /// Waits up to `timeout` for a status matching `predicate`. The handle lock is
+    /// released while waiting, so `start` and `stop` can run meanwhile.
+    pub async fn wait_for<F>(
+        &self,
+        predicate: F,
+        timeout: Duration,
+    ) -> Result<InstanceStatus, ServerError>
+    where
+        F: FnMut(&InstanceStatus) -> bool,
+    {
+        let status_rx = self.handle.read().await.status_watch();
+        wait_for_status(status_rx, predicate, timeout).await
+    }
+
+    pub async fn wait_until_running(&self, timeout: Duration) -> Result<(), ServerError> {
+        self.wait_for(|status| *status == InstanceStatus::Running, timeout)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn wait_until_stopped(
+        &self,
+        timeout: Duration,
+    ) -> Result<InstanceStatus, ServerError> {
+        self.wait_for(InstanceStatus::is_stopped, timeout).await
+    }
+
     pub async fn start(&self) -> Result<(), ServerError> {
         let mut handle_w = self.handle.write().await;
-        let res = handle_w.start().await;
-        res
+        handle_w.start().await
     }
 
     pub async fn kill(&self) -> Result<(), ServerError> {
         let mut handle_w = self.handle.write().await;
-        let res = handle_w.kill().await;
-        res
+        handle_w.kill().await
     }
     pub async fn stop(&self) -> Result<(), ServerError> {
         let mut handle_w = self.handle.write().await;
-        let res = handle_w.stop().await;
-        res
+        handle_w.stop().await
+    }
+
+    pub async fn stop_with_timeout(
+        &self,
+        graceful: Duration,
+        terminate: Duration,
+    ) -> Result<StopOutcome, ServerError> {
+        let mut handle_w = self.handle.write().await;
+        handle_w.stop_with_timeout(graceful, terminate).await
+    }
+
+    pub async fn last_exit(&self) -> Option<ExitInfo> {
+        let handle_r = self.handle.read().await;
+        handle_r.last_exit().await
+    }
+
+    pub async fn set_launch_profile(&self, profile: LaunchProfile) {
+        let handle_r = self.handle.read().await;
+        handle_r.set_launch_profile(profile.clone()).await;
+        drop(handle_r);
+
+        let mut config_w = self.config.write().await;
+        config_w.launch = profile;
+    }
+
+    #[cfg(feature = "cgroups")]
+    pub async fn set_cgroup_limits(&self, limits: Option<CgroupLimits>) {
+        let handle_r = self.handle.read().await;
+        handle_r.set_cgroup_limits(limits.clone()).await;
+        drop(handle_r);
+
+        let mut config_w = self.config.write().await;
+        config_w.cgroup = limits;
+    }
+
+    pub async fn set_event_rules(&self, rules: Vec<EventRule>) -> Result<(), ParserError> {
+        let handle_r = self.handle.read().await;
+        handle_r.set_event_rules(&rules).await?;
+        drop(handle_r);
+
+        let mut config_w = self.config.write().await;
+        config_w.event_rules = rules;
+        Ok(())
+    }
+
+    pub async fn set_backlog_policy(&self, policy: BacklogPolicy) {
+        let handle_r = self.handle.read().await;
+        handle_r.set_backlog_policy(policy.clone());
+        drop(handle_r);
+
+        let mut config_w = self.config.write().await;
+        config_w.backlog = policy;
+    }
+
+    pub async fn set_log_sink_policy(&self, policy: LogSinkPolicy) {
+        let handle_r = self.handle.read().await;
+        handle_r.set_log_sink_policy(policy.clone()).await;
+        drop(handle_r);
+
+        let mut config_w = self.config.write().await;
+        config_w.log_sink = policy;
+    }
+
+    pub async fn set_metrics_policy(&self, policy: MetricsPolicy) {
+        let handle_r = self.handle.read().await;
+        handle_r.set_metrics_policy(policy.clone()).await;
+        drop(handle_r);
+
+        let mut config_w = self.config.write().await;
+        config_w.metrics = policy;
+    }
+
+    /// Most recent resource usage of the running server.
+    pub async fn resource_sample(&self) -> Option<ResourceSample> {
+        let handle_r = self.handle.read().await;
+        handle_r.resource_sample()
+    }
+
+    pub async fn set_health_policy(&self, policy: HealthPolicy) {
+        let handle_r = self.handle.read().await;
+        handle_r.set_health_policy(policy.clone()).await;
+        drop(handle_r);
+
+        let mut config_w = self.config.write().await;
+        config_w.health = policy;
+    }
+
+    /// Current tick rate estimate of the server.
+    pub async fn health(&self) -> ServerHealth {
+        let handle_r = self.handle.read().await;
+        handle_r.health()
+    }
+
+    pub async fn set_restart_policy(&self, policy: RestartPolicy) {
+        let handle_r = self.handle.read().await;
+        handle_r.set_restart_policy(policy.clone()).await;
+        drop(handle_r);
+
+        let mut config_w = self.config.write().await;
+        config_w.restart = policy;
     }
 
     pub async fn subscribe(
@@ -162,8 +387,214 @@ impl MineGuardServer {
         stream: StreamSource,
     ) -> Result<BroadcastStream<InstanceEvent>, SubscribeError> {
         let handle_r = self.handle.read().await;
-        let res = handle_r.subscribe(stream);
-        res
+        handle_r.subscribe(stream)
+    }
+
+    /// Like `subscribe`, but first yields up to `n` of the most recent events.
+    pub async fn subscribe_with_backlog(
+        &self,
+        stream: StreamSource,
+        n: usize,
+    ) -> Result<BacklogStream, SubscribeError> {
+        let handle_r = self.handle.read().await;
+        handle_r.subscribe_with_backlog(stream, n)
+    }
+
+    /// Every event of `stream` from sequence number `seq` on, see `InstanceHandle::resume_from`.
+    pub async fn resume_from(
+        &self,
+        stream: StreamSource,
+        seq: u64,
+    ) -> Result<BacklogStream, SubscribeError> {
+        let handle_r = self.handle.read().await;
+        handle_r.resume_from(stream, seq)
+    }
+
+    /// Turns on RCON in `server.properties` with a newly generated password and
+    /// points the handle's RCON client at it. Takes effect on the next server start.
+    pub async fn enable_rcon(&self, port: Option<u16>) -> Result<(), ServerError> {
+        let properties_path = self.properties_path().await;
+        let mut properties = ServerProperties::load(&properties_path)
+            .await
+            .map_err(|_| ServerError::FileIO)?;
+
+        let password =
+            rcon_password().map_err(|err| ServerError::PasswordGeneration(err.to_string()))?;
+        properties.set_enable_rcon(true);
+        properties.set_rcon_port(port.unwrap_or(DEFAULT_RCON_PORT));
+        properties.set_rcon_password(password);
+        properties
+            .save(&properties_path)
+            .await
+            .map_err(|_| ServerError::FileIO)?;
+
+        let client = rcon_client_from_properties(&properties);
+        let handle_r = self.handle.read().await;
+        handle_r.set_rcon(client).await;
+
+        Ok(())
+    }
+
+    /// Runs `cmd` over RCON and returns the server's response.
+    pub async fn execute<S: Into<String>>(&self, cmd: S) -> Result<String, RconError> {
+        let handle_r = self.handle.read().await;
+        handle_r.execute(cmd).await
+    }
+
+    pub async fn execute_and_capture<S: Into<String>>(
+        &self,
+        cmd: S,
+        options: CaptureOptions,
+    ) -> Result<Vec<InstanceEvent>, ServerError> {
+        let handle_r = self.handle.read().await;
+        handle_r.execute_and_capture(cmd, options).await
+    }
+
+    /// Queries the running server with a Server List Ping on its configured port.
+    pub async fn ping(&self) -> Result<ServerStatus, PingError> {
+        let properties = ServerProperties::load(&self.properties_path().await)
+            .await
+            .unwrap_or_default();
+        let port = properties.server_port().unwrap_or(DEFAULT_SERVER_PORT);
+
+        ping::ping(&local_host(&properties), port).await
+    }
+
+    /// Basic stat over the Query protocol, which requires `enable-query=true`.
+    pub async fn query_basic(&self) -> Result<BasicStat, QueryError> {
+        self.query_client().await?.basic_stat().await
+    }
+
+    /// Full stat over the Query protocol, listing every online player and plugin.
+    pub async fn query_full(&self) -> Result<FullStat, QueryError> {
+        self.query_client().await?.full_stat().await
+    }
+
+    async fn query_client(&self) -> Result<QueryClient, QueryError> {
+        let properties = ServerProperties::load(&self.properties_path().await)
+            .await
+            .unwrap_or_default();
+        if properties.enable_query() != Some(true) {
+            return Err(QueryError::NotEnabled);
+        }
+
+        // The query port defaults to the game port, which is TCP so they do not collide.
+        let port = properties
+            .query_port()
+            .or(properties.server_port())
+            .unwrap_or(DEFAULT_SERVER_PORT);
+
+        Ok(QueryClient::new(format!(
+            "{}:{}",
+            local_host(&properties),
+            port
+        )))
+    }
+
+    async fn properties_path(&self) -> PathBuf {
+        let config_r = self.config.read().await;
+        config_r.server_dir.join("server.properties")
+    }
+
+    /// Current contents of `server.properties`.
+    pub async fn properties(&self) -> Result<ServerProperties, PropertiesError> {
+        ServerProperties::load(&self.properties_path().await).await
+    }
+
+    /// Applies `edit` to `server.properties` and saves it, returning the new contents.
+    ///
+    /// While the server runs, edits to keys that only take effect after a restart are
+    /// refused unless `force` is set. `white-list` and `difficulty` are applied live
+    /// through console commands, except in a forced edit, where the server would
+    /// otherwise write its stale in-memory properties over the file.
+    pub async fn update_properties<F: FnOnce(&mut ServerProperties)>(
+        &self,
+        edit: F,
+        force: bool,
+    ) -> Result<ServerProperties, PropertiesError> {
+        let properties_path = self.properties_path().await;
+        let old = ServerProperties::load(&properties_path).await?;
+        let mut new = old.clone();
+        edit(&mut new);
+
+        let changed = old.changed_keys(&new);
+        let restart_keys: Vec<String> = changed
+            .iter()
+            .filter(|key| ServerProperties::requires_restart(key))
+            .cloned()
+            .collect();
+
+        let handle_r = self.handle.read().await;
+        let running = !handle_r.status().is_stopped();
+        if running && !restart_keys.is_empty() && !force {
+            return Err(PropertiesError::RestartRequired(restart_keys));
+        }
+
+        new.save(&properties_path).await?;
+
+        if running && restart_keys.is_empty() {
+            for key in &changed {
+                let command = match key.as_str() {
+                    "white-list" if new.white_list() == Some(true) => "whitelist on".to_string(),
+                    "white-list" => "whitelist off".to_string(),
+                    "difficulty" => match new.difficulty() {
+                        Some(difficulty) => format!("difficulty {}", difficulty),
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                _ = handle_r.send_command(command).await;
+            }
+        }
+
+        Ok(new)
+    }
+
+    pub async fn roster(&self) -> watch::Receiver<PlayerRoster> {
+        let handle_r = self.handle.read().await;
+        handle_r.roster()
+    }
+
+    /// Corrects the roster from the `list` command, falling back to a Server List Ping
+    /// when the command fails and the ping sample covers every online player.
+    pub async fn reconcile_roster(&self) -> Result<PlayerRoster, ServerError> {
+        let listed = self.handle.read().await.reconcile_roster().await;
+        let Err(err) = listed else {
+            return listed;
+        };
+
+        match self.ping().await {
+            Ok(status) if status.sample.len() == status.players_online as usize => {
+                let online = status
+                    .sample
+                    .into_iter()
+                    .map(|player| (player.name, Uuid::parse_str(&player.id).ok()))
+                    .collect();
+                let handle_r = self.handle.read().await;
+                Ok(handle_r.reconcile_roster_with(online).await)
+            }
+            _ => Err(err),
+        }
+    }
+
+    pub async fn playtime(&self) -> Result<PlaytimeLedger, ServerError> {
+        let handle_r = self.handle.read().await;
+        handle_r.playtime().await
+    }
+
+    /// Console lines and events recorded on disk with `from <= timestamp < to`.
+    pub async fn console_history(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<ReceiverStream<LogRecord>, ServerError> {
+        let handle_r = self.handle.read().await;
+        handle_r.console_history(from, to).await
+    }
+
+    /// Whitelist, operators and bans of this server.
+    pub fn player_lists(&self) -> PlayerLists<'_> {
+        PlayerLists::new(self)
     }
 
     pub async fn accept_eula(&self) -> Result<(), ServerError> {
@@ -201,7 +632,7 @@ impl MineGuardServer {
         Ok(())
     }
 
-    pub async fn load(path: &PathBuf) -> Result<Self, CreationError> {
+    pub async fn load(path: &Path) -> Result<Self, CreationError> {
         let config_path = path.join(".mineguard/config.json");
 
         let data = read(config_path)
@@ -210,9 +641,20 @@ impl MineGuardServer {
 
         let config: MineGuardConfig =
             serde_json::from_slice(&data).map_err(|_| CreationError::CreationError)?;
-        let handle = InstanceHandle::new_with_config(config.clone())
+        let mut handle = InstanceHandle::new_with_config(config.clone())
             .map_err(|_| CreationError::CreationError)?;
 
+        let properties = ServerProperties::load(&config.server_dir.join("server.properties"))
+            .await
+            .unwrap_or_default();
+        handle
+            .set_rcon(rcon_client_from_properties(&properties))
+            .await;
+        // A detached server left running by an earlier supervisor is taken over. One
+        // that cannot be keeps running unsupervised, and the instance loads as stopped
+        // with the failure published as `ReattachFailed`.
+        _ = handle.reattach().await;
+
         MineGuardServer::load_cfg_handle(config, handle).await
     }
 
@@ -239,10 +681,66 @@ impl MineGuardServer {
         let mut servers: Vec<Self> = Vec::new();
 
         for v in dirs {
-            println!("{}", v.to_str().unwrap());
             servers.push(Self::load(&v).await?);
         }
 
         Ok(servers)
     }
 }
+
+/// A random alphanumeric RCON password of `RCON_PASSWORD_LEN` characters from the OS
+/// CSPRNG. Bytes past the largest multiple of the alphabet size are redrawn, so that
+/// every character is equally likely.
+fn rcon_password() -> Result<String, getrandom::Error> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    let limit = u8::MAX - u8::MAX % ALPHABET.len() as u8;
+
+    let mut password = String::with_capacity(RCON_PASSWORD_LEN);
+    let mut bytes = [0u8; RCON_PASSWORD_LEN];
+    while password.len() < RCON_PASSWORD_LEN {
+        getrandom::fill(&mut bytes)?;
+        password.extend(
+            bytes
+                .iter()
+                .filter(|&&byte| byte < limit)
+                .map(|&byte| ALPHABET[usize::from(byte) % ALPHABET.len()] as char)
+                .take(RCON_PASSWORD_LEN - password.len()),
+        );
+    }
+    Ok(password)
+}
+
+/// Builds an RCON client from `server.properties` when RCON is enabled there.
+fn rcon_client_from_properties(properties: &ServerProperties) -> Option<RconClient> {
+    if properties.enable_rcon() != Some(true) {
+        return None;
+    }
+    let password = properties.rcon_password().filter(|p| !p.is_empty())?;
+    let port = properties.rcon_port().unwrap_or(DEFAULT_RCON_PORT);
+
+    Some(RconClient::new(
+        format!("{}:{}", local_host(properties), port),
+        password,
+    ))
+}
+
+/// Address the server listens on, from `server-ip`, or loopback when it binds to all.
+fn local_host(properties: &ServerProperties) -> String {
+    properties
+        .server_ip()
+        .filter(|ip| !ip.is_empty())
+        .unwrap_or_else(|| "127.0.0.1".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rcon_password_is_alphanumeric_of_fixed_length() {
+        let password = rcon_password().unwrap();
+        assert_eq!(password.len(), RCON_PASSWORD_LEN);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(password, rcon_password().unwrap());
+    }
+}
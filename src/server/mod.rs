@@ -1 +1,6 @@
 pub mod domain;
+pub mod players;
+pub mod properties;
+
+pub use players::{IpBan, OpEntry, PlayerBan, PlayerLists, WhitelistEntry, offline_uuid};
+pub use properties::ServerProperties;
@@ -0,0 +1,456 @@
+use std::{io::ErrorKind, net::IpAddr, path::PathBuf};
+
+use chrono::{DateTime, FixedOffset, Local};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::fs::{read, write};
+use uuid::{Builder, Uuid};
+
+use crate::{error::PlayerListError, instance::InstanceStatus};
+
+use super::{ServerProperties, domain::MineGuardServer};
+
+const WHITELIST_FILE: &str = "whitelist.json";
+const OPS_FILE: &str = "ops.json";
+const BANNED_PLAYERS_FILE: &str = "banned-players.json";
+const BANNED_IPS_FILE: &str = "banned-ips.json";
+const USER_CACHE_FILE: &str = "usercache.json";
+
+const MOJANG_PROFILE_URL: &str = "https://api.mojang.com/users/profiles/minecraft/";
+const DEFAULT_OP_LEVEL: u8 = 4;
+const DEFAULT_BAN_SOURCE: &str = "Server";
+const DEFAULT_BAN_REASON: &str = "Banned by an operator.";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WhitelistEntry {
+    pub uuid: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpEntry {
+    pub uuid: Uuid,
+    pub name: String,
+    pub level: u8,
+    pub bypasses_player_limit: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PlayerBan {
+    pub uuid: Uuid,
+    pub name: String,
+    #[serde(with = "ban_date")]
+    pub created: DateTime<FixedOffset>,
+    pub source: String,
+    /// `None` for permanent bans, stored as `forever`.
+    #[serde(with = "ban_expiry")]
+    pub expires: Option<DateTime<FixedOffset>>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IpBan {
+    pub ip: String,
+    #[serde(with = "ban_date")]
+    pub created: DateTime<FixedOffset>,
+    pub source: String,
+    #[serde(with = "ban_expiry")]
+    pub expires: Option<DateTime<FixedOffset>>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct UserCacheEntry {
+    name: String,
+    uuid: Uuid,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MojangProfile {
+    id: Uuid,
+}
+
+/// UUID an offline-mode server assigns to `name`, the same as Java's
+/// `UUID.nameUUIDFromBytes("OfflinePlayer:" + name)`.
+pub fn offline_uuid(name: &str) -> Uuid {
+    let digest = Md5::digest(format!("OfflinePlayer:{}", name).as_bytes());
+    Builder::from_md5_bytes(digest.into()).into_uuid()
+}
+
+/// Whitelist, operator and ban lists of a server.
+///
+/// While the server is `Running` every change is sent as a console command so the
+/// server stays authoritative over its files; while it is stopped the JSON files
+/// are edited directly.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerLists<'a> {
+    server: &'a MineGuardServer,
+}
+
+/// Whether a change goes through the console or the files.
+enum Mode {
+    Console,
+    Files,
+}
+
+impl<'a> PlayerLists<'a> {
+    pub(crate) fn new(server: &'a MineGuardServer) -> Self {
+        Self { server }
+    }
+
+    pub async fn whitelist(&self) -> Result<Vec<WhitelistEntry>, PlayerListError> {
+        self.read_list(WHITELIST_FILE).await
+    }
+
+    pub async fn ops(&self) -> Result<Vec<OpEntry>, PlayerListError> {
+        self.read_list(OPS_FILE).await
+    }
+
+    pub async fn banned_players(&self) -> Result<Vec<PlayerBan>, PlayerListError> {
+        self.read_list(BANNED_PLAYERS_FILE).await
+    }
+
+    pub async fn banned_ips(&self) -> Result<Vec<IpBan>, PlayerListError> {
+        self.read_list(BANNED_IPS_FILE).await
+    }
+
+    pub async fn whitelist_add(&self, name: &str) -> Result<(), PlayerListError> {
+        validate_name(name)?;
+        if let Mode::Console = self.mode().await? {
+            return self.command(format!("whitelist add {}", name)).await;
+        }
+
+        let uuid = self.resolve_uuid(name).await?;
+        let mut list: Vec<WhitelistEntry> = self.read_list(WHITELIST_FILE).await?;
+        if !list.iter().any(|entry| entry.uuid == uuid) {
+            list.push(WhitelistEntry {
+                uuid,
+                name: name.to_string(),
+            });
+        }
+        self.write_list(WHITELIST_FILE, &list).await
+    }
+
+    pub async fn whitelist_remove(&self, name: &str) -> Result<(), PlayerListError> {
+        validate_name(name)?;
+        if let Mode::Console = self.mode().await? {
+            return self.command(format!("whitelist remove {}", name)).await;
+        }
+
+        let mut list: Vec<WhitelistEntry> = self.read_list(WHITELIST_FILE).await?;
+        list.retain(|entry| !entry.name.eq_ignore_ascii_case(name));
+        self.write_list(WHITELIST_FILE, &list).await
+    }
+
+    /// Makes `name` an operator. `level` defaults to `op-permission-level` and is
+    /// ignored while the server runs, as the `op` command always uses that default.
+    pub async fn op(&self, name: &str, level: Option<u8>) -> Result<(), PlayerListError> {
+        validate_name(name)?;
+        if let Mode::Console = self.mode().await? {
+            return self.command(format!("op {}", name)).await;
+        }
+
+        let level = match level {
+            Some(level) => level,
+            None => self
+                .properties()
+                .await?
+                .get_parsed("op-permission-level")
+                .unwrap_or(DEFAULT_OP_LEVEL),
+        };
+        let uuid = self.resolve_uuid(name).await?;
+
+        let mut list: Vec<OpEntry> = self.read_list(OPS_FILE).await?;
+        match list.iter_mut().find(|entry| entry.uuid == uuid) {
+            Some(entry) => entry.level = level,
+            None => list.push(OpEntry {
+                uuid,
+                name: name.to_string(),
+                level,
+                bypasses_player_limit: false,
+            }),
+        }
+        self.write_list(OPS_FILE, &list).await
+    }
+
+    pub async fn deop(&self, name: &str) -> Result<(), PlayerListError> {
+        validate_name(name)?;
+        if let Mode::Console = self.mode().await? {
+            return self.command(format!("deop {}", name)).await;
+        }
+
+        let mut list: Vec<OpEntry> = self.read_list(OPS_FILE).await?;
+        list.retain(|entry| !entry.name.eq_ignore_ascii_case(name));
+        self.write_list(OPS_FILE, &list).await
+    }
+
+    pub async fn ban(&self, name: &str, reason: Option<&str>) -> Result<(), PlayerListError> {
+        validate_name(name)?;
+        if let Mode::Console = self.mode().await? {
+            return self
+                .command(with_reason(format!("ban {}", name), reason))
+                .await;
+        }
+
+        let uuid = self.resolve_uuid(name).await?;
+        let mut list: Vec<PlayerBan> = self.read_list(BANNED_PLAYERS_FILE).await?;
+        list.retain(|entry| entry.uuid != uuid);
+        list.push(PlayerBan {
+            uuid,
+            name: name.to_string(),
+            created: Local::now().fixed_offset(),
+            source: DEFAULT_BAN_SOURCE.to_string(),
+            expires: None,
+            reason: reason.unwrap_or(DEFAULT_BAN_REASON).to_string(),
+        });
+        self.write_list(BANNED_PLAYERS_FILE, &list).await
+    }
+
+    pub async fn pardon(&self, name: &str) -> Result<(), PlayerListError> {
+        validate_name(name)?;
+        if let Mode::Console = self.mode().await? {
+            return self.command(format!("pardon {}", name)).await;
+        }
+
+        let mut list: Vec<PlayerBan> = self.read_list(BANNED_PLAYERS_FILE).await?;
+        list.retain(|entry| !entry.name.eq_ignore_ascii_case(name));
+        self.write_list(BANNED_PLAYERS_FILE, &list).await
+    }
+
+    pub async fn ban_ip(&self, ip: IpAddr, reason: Option<&str>) -> Result<(), PlayerListError> {
+        if let Mode::Console = self.mode().await? {
+            return self
+                .command(with_reason(format!("ban-ip {}", ip), reason))
+                .await;
+        }
+
+        let ip = ip.to_string();
+        let mut list: Vec<IpBan> = self.read_list(BANNED_IPS_FILE).await?;
+        list.retain(|entry| entry.ip != ip);
+        list.push(IpBan {
+            ip,
+            created: Local::now().fixed_offset(),
+            source: DEFAULT_BAN_SOURCE.to_string(),
+            expires: None,
+            reason: reason.unwrap_or(DEFAULT_BAN_REASON).to_string(),
+        });
+        self.write_list(BANNED_IPS_FILE, &list).await
+    }
+
+    pub async fn pardon_ip(&self, ip: IpAddr) -> Result<(), PlayerListError> {
+        if let Mode::Console = self.mode().await? {
+            return self.command(format!("pardon-ip {}", ip)).await;
+        }
+
+        let ip = ip.to_string();
+        let mut list: Vec<IpBan> = self.read_list(BANNED_IPS_FILE).await?;
+        list.retain(|entry| entry.ip != ip);
+        self.write_list(BANNED_IPS_FILE, &list).await
+    }
+
+    /// UUID the server would assign to `name`: from `usercache.json` when known, the
+    /// offline UUID when `online-mode` is off, and the Mojang profile API otherwise.
+    pub async fn resolve_uuid(&self, name: &str) -> Result<Uuid, PlayerListError> {
+        validate_name(name)?;
+        let cache: Vec<UserCacheEntry> = self.read_list(USER_CACHE_FILE).await.unwrap_or_default();
+        if let Some(entry) = cache
+            .iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+        {
+            return Ok(entry.uuid);
+        }
+
+        if self.properties().await?.online_mode() == Some(false) {
+            return Ok(offline_uuid(name));
+        }
+
+        let response = reqwest::get(format!("{}{}", MOJANG_PROFILE_URL, name))
+            .await
+            .map_err(|e| PlayerListError::LookupFailed(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(PlayerListError::UnknownPlayer(name.to_string()));
+        }
+        let profile: MojangProfile = response
+            .json()
+            .await
+            .map_err(|e| PlayerListError::LookupFailed(e.to_string()))?;
+        Ok(profile.id)
+    }
+
+    async fn mode(&self) -> Result<Mode, PlayerListError> {
+        let handle_r = self.server.handle.read().await;
+        let status = handle_r.status();
+        match status {
+            InstanceStatus::Running => Ok(Mode::Console),
+            InstanceStatus::Stopped | InstanceStatus::Crashed | InstanceStatus::Killed => {
+                Ok(Mode::Files)
+            }
+            _ => Err(PlayerListError::Busy(status)),
+        }
+    }
+
+    async fn command(&self, command: String) -> Result<(), PlayerListError> {
+        let handle_r = self.server.handle.read().await;
+        handle_r
+            .send_command(command)
+            .await
+            .map_err(|e| PlayerListError::CommandFailed(e.to_string()))
+    }
+
+    async fn server_dir(&self) -> PathBuf {
+        self.server.config.read().await.server_dir.clone()
+    }
+
+    async fn properties(&self) -> Result<ServerProperties, PlayerListError> {
+        ServerProperties::load(&self.server_dir().await.join("server.properties"))
+            .await
+            .map_err(|e| PlayerListError::Io(e.to_string()))
+    }
+
+    async fn read_list<T: DeserializeOwned>(&self, file: &str) -> Result<Vec<T>, PlayerListError> {
+        let path = self.server_dir().await.join(file);
+        let data = match read(&path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(PlayerListError::Io(err.to_string())),
+        };
+
+        // The server writes an empty file before the first entry is added.
+        if data.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Vec::new());
+        }
+        serde_json::from_slice(&data)
+            .map_err(|e| PlayerListError::Parse(format!("{}: {}", file, e)))
+    }
+
+    async fn write_list<T: Serialize>(
+        &self,
+        file: &str,
+        list: &[T],
+    ) -> Result<(), PlayerListError> {
+        let path = self.server_dir().await.join(file);
+        let json =
+            serde_json::to_vec_pretty(list).map_err(|e| PlayerListError::Parse(e.to_string()))?;
+        write(path, json)
+            .await
+            .map_err(|e| PlayerListError::Io(e.to_string()))
+    }
+}
+
+/// Rejects names that would break out of a console command.
+/// Accepts names matching `^[A-Za-z0-9_]{1,16}$`, as Mojang allows for accounts, so
+/// that a name never alters the URL or console command it is put into.
+fn validate_name(name: &str) -> Result<(), PlayerListError> {
+    let valid = (1..=16).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+    if !valid {
+        return Err(PlayerListError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn with_reason(command: String, reason: Option<&str>) -> String {
+    match reason {
+        Some(reason) => format!("{} {}", command, reason.replace(['\r', '\n'], " ")),
+        None => command,
+    }
+}
+
+/// The `yyyy-MM-dd HH:mm:ss Z` format used by the ban lists.
+mod ban_date {
+    use chrono::{DateTime, FixedOffset};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub(super) const FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+    pub(super) fn serialize<S: Serializer>(
+        date: &DateTime<FixedOffset>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&date.format(FORMAT).to_string())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<FixedOffset>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        DateTime::parse_from_str(&text, FORMAT).map_err(D::Error::custom)
+    }
+}
+
+/// Ban expiry, either a date in the ban list format or `forever`.
+mod ban_expiry {
+    use chrono::{DateTime, FixedOffset};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    use super::ban_date::FORMAT;
+
+    const FOREVER: &str = "forever";
+
+    pub(super) fn serialize<S: Serializer>(
+        date: &Option<DateTime<FixedOffset>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => serializer.serialize_str(&date.format(FORMAT).to_string()),
+            None => serializer.serialize_str(FOREVER),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<FixedOffset>>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        if text == FOREVER {
+            return Ok(None);
+        }
+        DateTime::parse_from_str(&text, FORMAT)
+            .map(Some)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offline_uuid_matches_the_server() {
+        assert_eq!(
+            offline_uuid("Notch"),
+            Uuid::parse_str("b50ad385-829d-3141-a216-7e7d7539ba7f").unwrap()
+        );
+        assert_eq!(
+            offline_uuid("jeb_"),
+            Uuid::parse_str("a762f560-4fce-3236-812a-b80efff0b62b").unwrap()
+        );
+    }
+
+    #[test]
+    fn accepts_account_names() {
+        for name in ["Notch", "jeb_", "a", "Player_1234567_9"] {
+            assert!(validate_name(name).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_names_that_could_alter_a_url_or_command() {
+        for name in [
+            "",
+            "Notch/../../x",
+            "Notch?x=1",
+            "Notch other",
+            "Notch\nop Notch",
+            "Notch\r",
+            "Notch#",
+            "Nötch",
+            "Player_12345678_9",
+        ] {
+            assert!(
+                matches!(validate_name(name), Err(PlayerListError::InvalidName(_))),
+                "{name:?}"
+            );
+        }
+    }
+}
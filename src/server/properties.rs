@@ -0,0 +1,470 @@
+use std::{
+    fmt::{self, Display},
+    io::ErrorKind,
+    path::Path,
+    str::FromStr,
+};
+
+use tokio::fs::{read, write};
+
+use crate::error::PropertiesError;
+
+/// Keys the server applies through console commands, so they can change while it runs.
+const LIVE_KEYS: &[&str] = &["white-list", "difficulty"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// Blank line or comment, written back verbatim.
+    Verbatim(String),
+    Entry {
+        key: String,
+        value: String,
+        /// Original text of the entry, dropped once the value changes.
+        raw: Option<String>,
+    },
+}
+
+/// Contents of `server.properties`, keeping comments, ordering and unknown keys
+/// so that a load and save round-trip leaves untouched lines unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerProperties {
+    lines: Vec<Line>,
+    /// Read from an ISO-8859-1 file, so that it is saved in that encoding again.
+    latin1: bool,
+}
+
+impl ServerProperties {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a properties file, returning empty properties when it does not exist yet.
+    pub async fn load(path: &Path) -> Result<Self, PropertiesError> {
+        let bytes = match read(path).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::new()),
+            Err(err) => return Err(PropertiesError::Io(err.to_string())),
+        };
+
+        // Newer servers write UTF-8, older ones ISO-8859-1 like `java.util.Properties`.
+        let (text, latin1) = match String::from_utf8(bytes) {
+            Ok(text) => (text, false),
+            Err(err) => (err.into_bytes().iter().map(|b| *b as char).collect(), true),
+        };
+        let mut properties: Self = text.parse()?;
+        properties.latin1 = latin1;
+        Ok(properties)
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), PropertiesError> {
+        let text = self.to_string();
+        // Lines read from the file hold ISO-8859-1 characters only, and new entries are
+        // escaped to ASCII.
+        let bytes = if self.latin1 {
+            text.chars()
+                .map(|c| u8::try_from(c).unwrap_or(b'?'))
+                .collect()
+        } else {
+            text.into_bytes()
+        };
+        write(path, bytes)
+            .await
+            .map_err(|e| PropertiesError::Io(e.to_string()))
+    }
+
+    /// Value of `key`; like Java, the last occurrence wins when a key is repeated.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries()
+            .filter(|(k, _)| *k == key)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    pub fn get_parsed<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key).and_then(|value| value.parse().ok())
+    }
+
+    /// Sets `key`, replacing its value in place or appending it at the end.
+    pub fn set<K: Into<String>, V: ToString>(&mut self, key: K, value: V) {
+        let key = key.into();
+        let value = value.to_string();
+
+        let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+            Line::Entry { key: k, value, raw } if *k == key => Some((value, raw)),
+            _ => None,
+        });
+
+        match existing {
+            Some((old, _)) if *old == value => {}
+            Some((old, raw)) => {
+                *old = value;
+                *raw = None;
+            }
+            None => self.lines.push(Line::Entry {
+                key,
+                value,
+                raw: None,
+            }),
+        }
+    }
+
+    /// Removes every occurrence of `key`, returning the value that was in effect.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let value = self.get(key).map(str::to_string);
+        self.lines
+            .retain(|line| !matches!(line, Line::Entry { key: k, .. } if k == key));
+        value
+    }
+
+    /// Key/value pairs in file order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, .. } => Some((key.as_str(), value.as_str())),
+            Line::Verbatim(_) => None,
+        })
+    }
+
+    /// Keys whose effective value differs between `self` and `other`.
+    pub fn changed_keys(&self, other: &ServerProperties) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for (key, _) in self.entries().chain(other.entries()) {
+            if self.get(key) != other.get(key) && !keys.iter().any(|k| k == key) {
+                keys.push(key.to_string());
+            }
+        }
+        keys
+    }
+
+    /// Returns true when a change to `key` only takes effect after a server restart.
+    pub fn requires_restart(key: &str) -> bool {
+        !LIVE_KEYS.contains(&key)
+    }
+}
+
+/// Typed getter and setter pairs for well-known keys.
+macro_rules! typed_properties {
+    ($($get:ident, $set:ident, $key:literal, $ty:ty;)*) => {
+        impl ServerProperties {
+            $(
+                #[doc = concat!("Value of `", $key, "`.")]
+                pub fn $get(&self) -> Option<$ty> {
+                    self.get_parsed($key)
+                }
+
+                pub fn $set(&mut self, value: $ty) {
+                    self.set($key, value);
+                }
+            )*
+        }
+    };
+}
+
+typed_properties! {
+    server_ip, set_server_ip, "server-ip", String;
+    server_port, set_server_port, "server-port", u16;
+    max_players, set_max_players, "max-players", u32;
+    online_mode, set_online_mode, "online-mode", bool;
+    motd, set_motd, "motd", String;
+    level_name, set_level_name, "level-name", String;
+    level_seed, set_level_seed, "level-seed", String;
+    gamemode, set_gamemode, "gamemode", String;
+    difficulty, set_difficulty, "difficulty", String;
+    hardcore, set_hardcore, "hardcore", bool;
+    pvp, set_pvp, "pvp", bool;
+    white_list, set_white_list, "white-list", bool;
+    enforce_whitelist, set_enforce_whitelist, "enforce-whitelist", bool;
+    view_distance, set_view_distance, "view-distance", u32;
+    simulation_distance, set_simulation_distance, "simulation-distance", u32;
+    spawn_protection, set_spawn_protection, "spawn-protection", u32;
+    enable_rcon, set_enable_rcon, "enable-rcon", bool;
+    rcon_port, set_rcon_port, "rcon.port", u16;
+    rcon_password, set_rcon_password, "rcon.password", String;
+    enable_query, set_enable_query, "enable-query", bool;
+    query_port, set_query_port, "query.port", u16;
+}
+
+impl FromStr for ServerProperties {
+    type Err = PropertiesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = Vec::new();
+        let mut physical = s.lines();
+
+        while let Some(line) = physical.next() {
+            let trimmed = trim_start(line);
+            if trimmed.is_empty() || trimmed.starts_with(['#', '!']) {
+                lines.push(Line::Verbatim(line.to_string()));
+                continue;
+            }
+
+            // A line ending in an odd number of backslashes continues on the next one,
+            // whose leading whitespace is ignored.
+            let mut raw = line.to_string();
+            let mut logical = trimmed.to_string();
+            while ends_with_continuation(&logical) {
+                logical.pop();
+                let Some(next) = physical.next() else {
+                    break;
+                };
+                raw.push('\n');
+                raw.push_str(next);
+                logical.push_str(trim_start(next));
+            }
+
+            let (key, value) = split_entry(&logical);
+            lines.push(Line::Entry {
+                key: unescape(key)?,
+                value: unescape(value)?,
+                raw: Some(raw),
+            });
+        }
+
+        Ok(Self {
+            lines,
+            latin1: false,
+        })
+    }
+}
+
+impl Display for ServerProperties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Verbatim(text) => writeln!(f, "{}", text)?,
+                Line::Entry { raw: Some(raw), .. } => writeln!(f, "{}", raw)?,
+                Line::Entry { key, value, .. } => {
+                    writeln!(f, "{}={}", escape(key, true), escape(value, false))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn trim_start(line: &str) -> &str {
+    line.trim_start_matches([' ', '\t', '\x0c'])
+}
+
+fn ends_with_continuation(line: &str) -> bool {
+    line.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+/// Splits at the first unescaped `=`, `:` or whitespace, skipping the separator and
+/// the whitespace around it.
+fn split_entry(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    let mut key_end = line.len();
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if matches!(c, '=' | ':' | ' ' | '\t' | '\x0c') {
+            key_end = i;
+            break;
+        }
+    }
+
+    let key = &line[..key_end];
+    let mut rest = trim_start(&line[key_end..]);
+    if let Some(stripped) = rest.strip_prefix(['=', ':']) {
+        rest = trim_start(stripped);
+    }
+    (key, rest)
+}
+
+fn unescape(text: &str) -> Result<String, PropertiesError> {
+    // Collected as UTF-16 so that `\u` escaped surrogate pairs combine.
+    let mut units: Vec<u16> = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('f') => '\x0c',
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let unit = (hex.len() == 4)
+                        .then(|| u16::from_str_radix(&hex, 16).ok())
+                        .flatten()
+                        .ok_or_else(|| PropertiesError::InvalidEscape(format!("\\u{}", hex)))?;
+                    units.push(unit);
+                    continue;
+                }
+                Some(other) => other,
+                None => break,
+            }
+        } else {
+            c
+        };
+
+        let mut buf = [0u16; 2];
+        units.extend_from_slice(c.encode_utf16(&mut buf));
+    }
+
+    Ok(String::from_utf16_lossy(&units))
+}
+
+/// Escapes like `Properties.store`, including non-ASCII characters, so the file reads
+/// the same whether the server decodes it as UTF-8 or ISO-8859-1.
+fn escape(text: &str, is_key: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            ' ' if is_key || i == 0 => out.push_str("\\ "),
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\x0c' => out.push_str("\\f"),
+            '=' | ':' | '#' | '!' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            _ => {
+                let mut buf = [0u16; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    out.push_str(&format!("\\u{:04X}", unit));
+                }
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) -> ServerProperties {
+        let properties: ServerProperties = text.parse().unwrap();
+        assert_eq!(properties.to_string(), text);
+        properties
+    }
+
+    #[test]
+    fn keeps_comments_blank_lines_and_order() {
+        let text = "#Minecraft server properties\n\
+                    #Sat Jun 01 12:00:00 CEST 2024\n\
+                    \n\
+                    ! another comment\n\
+                    motd = A Minecraft Server\n\
+                    \x20 level-name:world\n\
+                    pvp true\n\
+                    server-port=25565\n";
+        let properties = round_trip(text);
+
+        let entries: Vec<_> = properties.entries().collect();
+        assert_eq!(
+            entries,
+            [
+                ("motd", "A Minecraft Server"),
+                ("level-name", "world"),
+                ("pvp", "true"),
+                ("server-port", "25565"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unescapes_separators_and_backslashes() {
+        let properties = round_trip("a\\:b\\=c=d\\=e\\:f\nlevel-name=C\\:\\\\worlds\\\\main\n");
+        assert_eq!(properties.get("a:b=c"), Some("d=e:f"));
+        assert_eq!(properties.level_name().as_deref(), Some("C:\\worlds\\main"));
+
+        let mut properties = ServerProperties::new();
+        properties.set("a:b=c", "d=e:f");
+        properties.set_level_name("C:\\worlds".to_string());
+        assert_eq!(
+            properties.to_string(),
+            "a\\:b\\=c=d\\=e\\:f\nlevel-name=C\\:\\\\worlds\n"
+        );
+    }
+
+    #[test]
+    fn decodes_and_encodes_unicode_escapes() {
+        let properties = round_trip("motd=\\u00A7aCaf\\u00e9 \\uD83D\\uDE00\n");
+        assert_eq!(properties.motd().as_deref(), Some("§aCafé 😀"));
+
+        let mut properties = ServerProperties::new();
+        properties.set_motd("§aCafé 😀".to_string());
+        assert_eq!(
+            properties.to_string(),
+            "motd=\\u00A7aCaf\\u00E9 \\uD83D\\uDE00\n"
+        );
+        assert_eq!(
+            properties
+                .to_string()
+                .parse::<ServerProperties>()
+                .unwrap()
+                .motd(),
+            properties.motd()
+        );
+
+        for invalid in ["motd=\\u12", "motd=\\uZZZZ"] {
+            assert!(matches!(
+                invalid.parse::<ServerProperties>(),
+                Err(PropertiesError::InvalidEscape(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn joins_continued_lines() {
+        let properties = round_trip("motd=Hello \\\n    World\npath=C\\:\\\\\nafter=1\n");
+        assert_eq!(properties.motd().as_deref(), Some("Hello World"));
+        // An even number of trailing backslashes is an escaped backslash.
+        assert_eq!(properties.get("path"), Some("C:\\"));
+        assert_eq!(properties.get("after"), Some("1"));
+    }
+
+    #[test]
+    fn updates_keys_in_place_and_appends_new_ones() {
+        let mut properties: ServerProperties =
+            "# settings\nmax-players = 20\npvp=true\n".parse().unwrap();
+
+        // An unchanged value keeps the original formatting.
+        properties.set_max_players(20);
+        assert_eq!(
+            properties.to_string(),
+            "# settings\nmax-players = 20\npvp=true\n"
+        );
+
+        properties.set_max_players(10);
+        properties.set_difficulty("hard".to_string());
+        assert_eq!(
+            properties.to_string(),
+            "# settings\nmax-players=10\npvp=true\ndifficulty=hard\n"
+        );
+        assert_eq!(properties.max_players(), Some(10));
+    }
+
+    #[tokio::test]
+    async fn saves_iso_8859_1_files_in_that_encoding() {
+        let path = std::env::temp_dir().join(format!(
+            "mineguard-properties-latin1-{}",
+            std::process::id()
+        ));
+        tokio::fs::write(&path, b"# \xa7 colours\nmotd=Caf\xe9\n")
+            .await
+            .unwrap();
+
+        let mut properties = ServerProperties::load(&path).await.unwrap();
+        assert_eq!(properties.motd().as_deref(), Some("Café"));
+        properties.set_level_name("Wörld ☃".to_string());
+        properties.save(&path).await.unwrap();
+
+        let saved = tokio::fs::read(&path).await.unwrap();
+        _ = tokio::fs::remove_file(&path).await;
+        assert_eq!(
+            saved,
+            b"# \xa7 colours\nmotd=Caf\xe9\nlevel-name=W\\u00F6rld \\u2603\n"
+        );
+    }
+}